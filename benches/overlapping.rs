@@ -4,14 +4,16 @@ use std::path::Path;
 
 use image::{DynamicImage, GenericImageView, Rgb};
 
+use fast_wfc::direction::Direction;
 use fast_wfc::overlapping_wfc::*;
 use fast_wfc::utils::vec2d::*;
+use fast_wfc::wave::Heuristic;
 
 extern crate test;
 use test::Bencher;
 
 fn read_image(filepath: &str) -> DynamicImage {
-    image::open(&Path::new(&filepath)).unwrap()
+    image::open(Path::new(&filepath)).unwrap()
 }
 
 fn image_to_vec2d(image: &DynamicImage) -> Vec2D<Rgb<u8>> {
@@ -31,10 +33,20 @@ fn image_to_vec2d(image: &DynamicImage) -> Vec2D<Rgb<u8>> {
 }
 
 fn bench_overlapping(bencher: &mut Bencher, file: &str, options: OverlappingWFCOptions) {
+    bench_overlapping_heuristic(bencher, file, options, Heuristic::MinEntropy);
+}
+
+fn bench_overlapping_heuristic(
+    bencher: &mut Bencher,
+    file: &str,
+    options: OverlappingWFCOptions,
+    heuristic: Heuristic,
+) {
     let image = read_image(file);
     let image = image_to_vec2d(&image);
     let mut i = 0;
     let mut wfc = OverlappingWFC::new(image, options, [i; 16]);
+    wfc.set_heuristic(heuristic);
     bencher.iter(|| loop {
         wfc.restart([i; 16]);
         let image = wfc.run();
@@ -47,49 +59,73 @@ fn bench_overlapping(bencher: &mut Bencher, file: &str, options: OverlappingWFCO
 
 #[bench]
 fn bench_flowers_small(bencher: &mut Bencher) {
-    let options = OverlappingWFCOptions {
-        periodic_input: true,
-        periodic_output: true,
-        out_height: 21,
-        out_width: 21,
-        symmetry: 2,
-        pattern_size: 3,
-        ground: true,
-    };
+    let options = OverlappingWFCOptions::builder()
+        .periodic_input(true)
+        .periodic_output(true)
+        .out_height(21)
+        .out_width(21)
+        .symmetry(2)
+        .pattern_size(3)
+        .ground_edge(Some(Direction::Down))
+        .build()
+        .unwrap();
 
     bench_overlapping(bencher, "images/Flowers.png", options);
 }
 
 #[bench]
 fn bench_flowers_medium(bencher: &mut Bencher) {
-    let options = OverlappingWFCOptions {
-        periodic_input: true,
-        periodic_output: true,
-        out_height: 42,
-        out_width: 42,
-        symmetry: 2,
-        pattern_size: 3,
-        ground: true,
-    };
+    let options = OverlappingWFCOptions::builder()
+        .periodic_input(true)
+        .periodic_output(true)
+        .out_height(42)
+        .out_width(42)
+        .symmetry(2)
+        .pattern_size(3)
+        .ground_edge(Some(Direction::Down))
+        .build()
+        .unwrap();
 
     bench_overlapping(bencher, "images/Flowers.png", options);
 }
 
 #[bench]
 fn bench_flowers_big(bencher: &mut Bencher) {
-    let options = OverlappingWFCOptions {
-        periodic_input: true,
-        periodic_output: true,
-        out_height: 63,
-        out_width: 63,
-        symmetry: 2,
-        pattern_size: 3,
-        ground: true,
-    };
+    let options = OverlappingWFCOptions::builder()
+        .periodic_input(true)
+        .periodic_output(true)
+        .out_height(63)
+        .out_width(63)
+        .symmetry(2)
+        .pattern_size(3)
+        .ground_edge(Some(Direction::Down))
+        .build()
+        .unwrap();
 
     bench_overlapping(bencher, "images/Flowers.png", options);
 }
 
+#[bench]
+fn bench_flowers_big_most_constrained(bencher: &mut Bencher) {
+    let options = OverlappingWFCOptions::builder()
+        .periodic_input(true)
+        .periodic_output(true)
+        .out_height(63)
+        .out_width(63)
+        .symmetry(2)
+        .pattern_size(3)
+        .ground_edge(Some(Direction::Down))
+        .build()
+        .unwrap();
+
+    bench_overlapping_heuristic(
+        bencher,
+        "images/Flowers.png",
+        options,
+        Heuristic::MostConstrained,
+    );
+}
+
 fn bench_restart(bencher: &mut Bencher, file: &str, options: OverlappingWFCOptions) {
     let image = read_image(file);
     let image = image_to_vec2d(&image);
@@ -101,45 +137,136 @@ fn bench_restart(bencher: &mut Bencher, file: &str, options: OverlappingWFCOptio
 
 #[bench]
 fn bench_flowers_restart_small(bencher: &mut Bencher) {
-    let options = OverlappingWFCOptions {
-        periodic_input: true,
-        periodic_output: true,
-        out_height: 21,
-        out_width: 21,
-        symmetry: 2,
-        pattern_size: 3,
-        ground: true,
-    };
+    let options = OverlappingWFCOptions::builder()
+        .periodic_input(true)
+        .periodic_output(true)
+        .out_height(21)
+        .out_width(21)
+        .symmetry(2)
+        .pattern_size(3)
+        .ground_edge(Some(Direction::Down))
+        .build()
+        .unwrap();
 
     bench_restart(bencher, "images/Flowers.png", options);
 }
 
 #[bench]
 fn bench_flowers_restart_medium(bencher: &mut Bencher) {
-    let options = OverlappingWFCOptions {
-        periodic_input: true,
-        periodic_output: true,
-        out_height: 42,
-        out_width: 42,
-        symmetry: 2,
-        pattern_size: 3,
-        ground: true,
-    };
+    let options = OverlappingWFCOptions::builder()
+        .periodic_input(true)
+        .periodic_output(true)
+        .out_height(42)
+        .out_width(42)
+        .symmetry(2)
+        .pattern_size(3)
+        .ground_edge(Some(Direction::Down))
+        .build()
+        .unwrap();
 
     bench_restart(bencher, "images/Flowers.png", options);
 }
 
 #[bench]
 fn bench_flowers_restart_big(bencher: &mut Bencher) {
-    let options = OverlappingWFCOptions {
-        periodic_input: true,
-        periodic_output: true,
-        out_height: 63,
-        out_width: 63,
-        symmetry: 2,
-        pattern_size: 3,
-        ground: true,
-    };
+    let options = OverlappingWFCOptions::builder()
+        .periodic_input(true)
+        .periodic_output(true)
+        .out_height(63)
+        .out_width(63)
+        .symmetry(2)
+        .pattern_size(3)
+        .ground_edge(Some(Direction::Down))
+        .build()
+        .unwrap();
 
     bench_restart(bencher, "images/Flowers.png", options);
 }
+
+fn bench_setup(bencher: &mut Bencher, file: &str, options: OverlappingWFCOptions) {
+    let image = read_image(file);
+    let image = image_to_vec2d(&image);
+    bencher.iter(|| OverlappingWFC::new(image.clone(), options, [0; 16]));
+}
+
+/// Measure the setup time on an input with many patterns.
+#[bench]
+fn bench_city_setup(bencher: &mut Bencher) {
+    let options = OverlappingWFCOptions::builder()
+        .periodic_input(true)
+        .periodic_output(true)
+        .out_height(48)
+        .out_width(48)
+        .symmetry(8)
+        .pattern_size(3)
+        .build()
+        .unwrap();
+
+    bench_setup(bencher, "examples/samples/City.png", options);
+}
+
+/// Compare the setup time with bench_city_setup, when the patterns
+/// compatibility is computed in parallel.
+#[cfg(feature = "parallel")]
+#[bench]
+fn bench_city_setup_parallel(bencher: &mut Bencher) {
+    let options = OverlappingWFCOptions::builder()
+        .periodic_input(true)
+        .periodic_output(true)
+        .out_height(48)
+        .out_width(48)
+        .symmetry(8)
+        .pattern_size(3)
+        .build()
+        .unwrap();
+    let image = image_to_vec2d(&read_image("examples/samples/City.png"));
+    bencher.iter(|| OverlappingWFC::new_parallel(image.clone(), options, [0; 16]));
+}
+
+/// Measure the cost of the first steps on an input with many patterns, where
+/// the pattern of a cell is sampled among many possible patterns.
+#[bench]
+fn bench_city_steps(bencher: &mut Bencher) {
+    let options = OverlappingWFCOptions::builder()
+        .periodic_input(true)
+        .periodic_output(true)
+        .out_height(48)
+        .out_width(48)
+        .symmetry(8)
+        .pattern_size(3)
+        .build()
+        .unwrap();
+    let image = image_to_vec2d(&read_image("examples/samples/City.png"));
+    let mut wfc = OverlappingWFC::new(image, options, [0; 16]);
+    bencher.iter(|| {
+        wfc.restart([0; 16]);
+        for _ in 0..1000 {
+            if wfc.step().is_err() {
+                break;
+            }
+        }
+    });
+}
+
+const PATTERN_SIZES: [usize; 3] = [2, 3, 4];
+
+#[bench]
+fn bench_flowers_get_patterns_sizes(bencher: &mut Bencher) {
+    let image = image_to_vec2d(&read_image("images/Flowers.png"));
+    bencher.iter(|| {
+        for &size in &PATTERN_SIZES {
+            test::black_box(get_patterns(&image, true, size, size, 8));
+        }
+    });
+}
+
+#[bench]
+fn bench_flowers_pattern_extractor_sizes(bencher: &mut Bencher) {
+    let image = image_to_vec2d(&read_image("images/Flowers.png"));
+    bencher.iter(|| {
+        let extractor = PatternExtractor::new(&image, true, 8);
+        for &size in &PATTERN_SIZES {
+            test::black_box(extractor.patterns(size, size));
+        }
+    });
+}
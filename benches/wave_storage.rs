@@ -0,0 +1,122 @@
+//! Compare the storages of the wave on the Flowers big case: the raw
+//! accesses to a Vec3D<bool> and to a BitVec3D, and a full run with the wave
+//! storage selected by the bitvec feature. The runs are compared by running
+//! the benchmarks with and without --features bitvec.
+#![feature(test)]
+
+use std::path::Path;
+
+use image::{DynamicImage, GenericImageView, Rgb};
+
+use fast_wfc::direction::Direction;
+use fast_wfc::overlapping_wfc::*;
+use fast_wfc::utils::bitvec3d::*;
+use fast_wfc::utils::vec2d::*;
+use fast_wfc::utils::vec3d::*;
+
+extern crate test;
+use test::Bencher;
+
+fn read_image(filepath: &str) -> DynamicImage {
+    image::open(Path::new(&filepath)).unwrap()
+}
+
+fn image_to_vec2d(image: &DynamicImage) -> Vec2D<Rgb<u8>> {
+    let mut image_vec2d = Vec2D::new(
+        image.height() as usize,
+        image.width() as usize,
+        &Rgb { data: [0, 0, 0] },
+    );
+
+    for (x, y, pixel) in image.pixels() {
+        image_vec2d[y as usize][x as usize] = Rgb {
+            data: [pixel[0], pixel[1], pixel[2]],
+        };
+    }
+
+    image_vec2d
+}
+
+/// Get the options of the Flowers big case.
+fn flowers_big_options() -> OverlappingWFCOptions {
+    OverlappingWFCOptions::builder()
+        .periodic_input(true)
+        .periodic_output(true)
+        .out_height(63)
+        .out_width(63)
+        .symmetry(2)
+        .pattern_size(3)
+        .ground_edge(Some(Direction::Down))
+        .build()
+        .unwrap()
+}
+
+/// Get the size of the wave used in the Flowers big case.
+fn flowers_big_size() -> (usize, usize, usize) {
+    let options = flowers_big_options();
+    let image = image_to_vec2d(&read_image("images/Flowers.png"));
+    let wfc = OverlappingWFC::new(image, options, [0; 16]);
+    (options.out_height, options.out_width, wfc.patterns().len())
+}
+
+/// Run the Flowers big case until a run succeeds, as bench_flowers_big does.
+fn run_flowers_big(bencher: &mut Bencher) {
+    let image = image_to_vec2d(&read_image("images/Flowers.png"));
+    let mut i = 0;
+    let mut wfc = OverlappingWFC::new(image, flowers_big_options(), [i; 16]);
+    bencher.iter(|| loop {
+        wfc.restart([i; 16]);
+        if wfc.run().is_some() {
+            break;
+        }
+        i += 1;
+    });
+}
+
+#[bench]
+#[cfg(not(feature = "bitvec"))]
+fn bench_flowers_big_run_vec3d(bencher: &mut Bencher) {
+    run_flowers_big(bencher);
+}
+
+#[bench]
+#[cfg(feature = "bitvec")]
+fn bench_flowers_big_run_bitvec3d(bencher: &mut Bencher) {
+    run_flowers_big(bencher);
+}
+
+#[bench]
+fn bench_flowers_big_vec3d(bencher: &mut Bencher) {
+    let (height, width, n_patterns) = flowers_big_size();
+    let mut wave = Vec3D::new(height, width, n_patterns, &true);
+    bencher.iter(|| {
+        for i in 0..height {
+            for j in 0..width {
+                for k in (0..n_patterns).step_by(3) {
+                    if *wave.get(i, j, k) {
+                        *wave.get_mut(i, j, k) = false;
+                    }
+                }
+            }
+        }
+        wave.iter_mut().for_each(|v| *v = true);
+    });
+}
+
+#[bench]
+fn bench_flowers_big_bitvec3d(bencher: &mut Bencher) {
+    let (height, width, n_patterns) = flowers_big_size();
+    let mut wave = BitVec3D::new(height, width, n_patterns, true);
+    bencher.iter(|| {
+        for i in 0..height {
+            for j in 0..width {
+                for k in (0..n_patterns).step_by(3) {
+                    if wave.get(i, j, k) {
+                        wave.clear(i, j, k);
+                    }
+                }
+            }
+        }
+        wave.fill(true);
+    });
+}
@@ -6,13 +6,17 @@ use std::fs::File;
 use std::path::Path;
 use std::str::from_utf8;
 
-use image::{DynamicImage, GenericImageView, ImageBuffer, Rgb};
+use image::{DynamicImage, Frame, GenericImageView, ImageBuffer, Rgb};
 
+use fast_wfc::direction::Direction;
 use fast_wfc::overlapping_wfc::*;
 use fast_wfc::utils::vec2d::*;
+use fast_wfc::wave::WaveError;
+use fast_wfc::wfc::seed_from_u64;
+use fast_wfc::Real;
 
 fn read_image(filepath: &str) -> DynamicImage {
-    image::open(&Path::new(&filepath)).unwrap()
+    image::open(Path::new(&filepath)).unwrap()
 }
 
 fn image_to_vec2d(image: &DynamicImage) -> Vec2D<Rgb<u8>> {
@@ -39,13 +43,69 @@ fn vec2d_to_image(image: &Vec2D<Rgb<u8>>) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
 
 fn write_to_file(file: &str, image: ImageBuffer<Rgb<u8>, Vec<u8>>) {
     let image = DynamicImage::ImageRgb8(image);
-    let fout = &mut File::create(&Path::new(file)).unwrap();
+    let fout = &mut File::create(Path::new(file)).unwrap();
     image.write_to(fout, image::PNG).unwrap();
 }
 
+/// Blend the colors a pixel can still take, weighted by the weight of their pattern.
+/// A pixel that cannot take any color is black.
+fn blend(candidates: &[(&Rgb<u8>, Real)]) -> Rgb<u8> {
+    let total: Real = candidates.iter().map(|(_, weight)| weight).sum();
+    if total == 0.0 {
+        return Rgb { data: [0, 0, 0] };
+    }
+    let mut data = [0.0; 3];
+    for (color, weight) in candidates {
+        for (channel, value) in data.iter_mut().zip(color.data.iter()) {
+            *channel += Real::from(*value) * weight;
+        }
+    }
+    Rgb {
+        data: [
+            (data[0] / total).round() as u8,
+            (data[1] / total).round() as u8,
+            (data[2] / total).round() as u8,
+        ],
+    }
+}
+
+/// Get the current output, where the undetermined pixels have a blended color.
+fn partial_image(wfc: &OverlappingWFC<Rgb<u8>>) -> Frame {
+    let image = wfc.to_image_partial(blend);
+    Frame::new(DynamicImage::ImageRgb8(vec2d_to_image(&image)).to_rgba())
+}
+
+/// Run the algorithm step by step, and record a frame every frame_stride steps,
+/// as well as the last one.
+/// Return None if the algorithm failed.
+fn record_run(wfc: &mut OverlappingWFC<Rgb<u8>>, frame_stride: usize) -> Option<Vec<Frame>> {
+    let mut frames = vec![];
+    let mut steps = 0;
+    loop {
+        if steps % frame_stride == 0 {
+            frames.push(partial_image(wfc));
+        }
+        match wfc.step() {
+            Ok(()) => steps += 1,
+            Err(WaveError::Finished) => {
+                frames.push(partial_image(wfc));
+                return Some(frames);
+            }
+            Err(_) => return None,
+        }
+    }
+}
+
+fn write_gif(file: &str, frames: Vec<Frame>) {
+    let fout = File::create(Path::new(file)).unwrap();
+    image::gif::Encoder::new(fout)
+        .encode_frames(frames)
+        .unwrap();
+}
+
 fn get_attribute<'a, 'b>(attributes: &'b [Attribute<'a>], key: &str) -> &'b str {
     let v = attributes
-        .into_iter()
+        .iter()
         .find(|a| a.key == key.as_bytes())
         .map(|a| &a.value)
         .unwrap();
@@ -58,18 +118,23 @@ fn get_attribute_or<'a, 'b>(
     default: &'b str,
 ) -> &'b str {
     let v = attributes
-        .into_iter()
+        .iter()
         .find(|a| a.key == key.as_bytes())
         .map_or(default.as_bytes(), |a| &a.value);
     from_utf8(v).unwrap()
 }
 
 fn main() {
-    let file = if env::args().count() == 2 {
+    let file = if env::args().count() >= 2 {
         env::args().nth(1).unwrap()
     } else {
         panic!("Please enter a file")
     };
+    // If a frame stride is given, the first screenshot of each sample is
+    // also saved as an animation, with a frame every frame_stride steps.
+    let frame_stride = env::args()
+        .nth(2)
+        .map(|stride| stride.parse::<usize>().unwrap().max(1));
 
     let mut reader = Reader::from_file(&file).unwrap();
     reader.trim_text(true);
@@ -78,77 +143,88 @@ fn main() {
     loop {
         match reader.read_event(&mut buf) {
             Err(e) => panic!("Error at position {}: {:?}", reader.buffer_position(), e),
-            Ok(Event::Empty(ref c)) => match c.name() {
-                b"overlapping" => {
-                    let attributes = c.attributes().map(|a| a.unwrap()).collect::<Vec<_>>();
-                    let name = &get_attribute(&attributes, "name");
-                    let pattern_size = get_attribute_or(&attributes, "N", "3")
-                        .parse::<usize>()
-                        .unwrap();
-                    let periodic_output =
-                        get_attribute_or(&attributes, "periodic", "False") == "True";
-                    let periodic_input =
-                        get_attribute_or(&attributes, "periodicInput", "True") == "True";
-                    let ground = get_attribute_or(&attributes, "ground", "0")
-                        .parse::<i32>()
-                        .unwrap()
-                        != 0;
-                    let symmetry = get_attribute_or(&attributes, "symmetry", "8")
-                        .parse::<usize>()
-                        .unwrap();
-                    let screenshots = get_attribute_or(&attributes, "screenshots", "2")
-                        .parse::<usize>()
-                        .unwrap();
-                    let out_width = get_attribute_or(&attributes, "width", "48")
-                        .parse::<usize>()
-                        .unwrap();
-                    let out_height = get_attribute_or(&attributes, "height", "48")
-                        .parse::<usize>()
-                        .unwrap();
-
-                    let options = OverlappingWFCOptions {
-                        periodic_input,
-                        periodic_output,
-                        out_height,
-                        out_width,
-                        symmetry,
-                        pattern_size,
-                        ground,
-                    };
-
-                    run_example(name, options, screenshots);
-                }
-                _ => (),
-            },
+            Ok(Event::Empty(ref c)) if c.name() == b"overlapping" => {
+                let attributes = c.attributes().map(|a| a.unwrap()).collect::<Vec<_>>();
+                let name = &get_attribute(&attributes, "name");
+                let pattern_size = get_attribute_or(&attributes, "N", "3")
+                    .parse::<usize>()
+                    .unwrap();
+                let periodic_output = get_attribute_or(&attributes, "periodic", "False") == "True";
+                let periodic_input =
+                    get_attribute_or(&attributes, "periodicInput", "True") == "True";
+                let ground = get_attribute_or(&attributes, "ground", "0")
+                    .parse::<i32>()
+                    .unwrap()
+                    != 0;
+                let ground_edge = if ground { Some(Direction::Down) } else { None };
+                let symmetry = get_attribute_or(&attributes, "symmetry", "8")
+                    .parse::<usize>()
+                    .unwrap();
+                let screenshots = get_attribute_or(&attributes, "screenshots", "2")
+                    .parse::<usize>()
+                    .unwrap();
+                let out_width = get_attribute_or(&attributes, "width", "48")
+                    .parse::<usize>()
+                    .unwrap();
+                let out_height = get_attribute_or(&attributes, "height", "48")
+                    .parse::<usize>()
+                    .unwrap();
+
+                let options = OverlappingWFCOptions::builder()
+                    .periodic_input(periodic_input)
+                    .periodic_output(periodic_output)
+                    .out_height(out_height)
+                    .out_width(out_width)
+                    .symmetry(symmetry)
+                    .pattern_size(pattern_size)
+                    .ground_edge(ground_edge)
+                    .build()
+                    .unwrap();
+
+                run_example(name, options, screenshots, frame_stride);
+            }
+            Ok(Event::Empty(_)) => (),
             Ok(Event::Eof) => break,
             _ => println!("other"),
         }
     }
 }
 
-fn run_example(filename: &str, options: OverlappingWFCOptions, screenshots: usize) {
+fn run_example(
+    filename: &str,
+    options: OverlappingWFCOptions,
+    screenshots: usize,
+    frame_stride: Option<usize>,
+) {
     println!("{} started!", filename);
     let image = read_image(&(String::from("samples/") + filename + ".png"));
     let image = image_to_vec2d(&image);
 
-    let mut i = 0;
-    let mut wfc = OverlappingWFC::new(image.clone(), options, [i; 16]);
-
-    for _ in 0..screenshots {
-        let mut result_image = None;
-        for _ in 0..10 {
-            i += 1;
-            wfc.restart([i; 16]);
-            result_image = wfc.run();
-            if result_image.is_some() {
+    let mut wfc = OverlappingWFC::new(image, options, [0; 16]);
+    if let Some(frame_stride) = frame_stride {
+        let mut frames = record_run(&mut wfc, frame_stride);
+        for attempt in 1..10 {
+            if frames.is_some() {
                 break;
             }
-            println!("failed!");
+            wfc.restart_u64(attempt);
+            frames = record_run(&mut wfc, frame_stride);
+        }
+        match frames {
+            Some(frames) => write_gif(&(String::from("results/") + filename + ".gif"), frames),
+            None => println!("{} animation failed!", filename),
         }
+    }
+
+    for screenshot in 0..screenshots {
+        let result_image = wfc.run_until_success(10, seed_from_u64(screenshot as u64));
         println!("{} finished!", filename);
-        if let Some(image) = result_image {
-            let image = vec2d_to_image(&image);
-            write_to_file(&(String::from("results/") + filename), image);
+        match result_image {
+            Some(image) => {
+                let image = vec2d_to_image(&image);
+                write_to_file(&(String::from("results/") + filename), image);
+            }
+            None => println!("failed!"),
         }
     }
 }
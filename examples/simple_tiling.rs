@@ -0,0 +1,90 @@
+//! Run the tiling WFC on a small hand-coded tileset of knots, without any
+//! tileset file, and write the result in results/simple_tiling.png.
+
+use std::fs::{self, File};
+use std::path::Path;
+
+use image::{DynamicImage, ImageBuffer, Rgb};
+
+use fast_wfc::tile::*;
+use fast_wfc::tiling_wfc::*;
+use fast_wfc::utils::vec2d::*;
+
+const BACKGROUND: Rgb<u8> = Rgb {
+    data: [255, 255, 255],
+};
+const LINE: Rgb<u8> = Rgb { data: [40, 40, 40] };
+
+/// Create a tile from its rows, where '#' is a line pixel.
+fn tile_from_rows(rows: &[&str]) -> Vec2D<Rgb<u8>> {
+    let rows = rows
+        .iter()
+        .map(|row| {
+            row.chars()
+                .map(|c| if c == '#' { LINE } else { BACKGROUND })
+                .collect()
+        })
+        .collect();
+    Vec2D::from_rows(rows).unwrap()
+}
+
+fn vec2d_to_image(image: &Vec2D<Rgb<u8>>) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+    ImageBuffer::from_fn(image.width() as u32, image.height() as u32, |x, y| {
+        image[y as usize][x as usize]
+    })
+}
+
+fn write_to_file(file: &str, image: ImageBuffer<Rgb<u8>, Vec<u8>>) {
+    let image = DynamicImage::ImageRgb8(image);
+    let fout = &mut File::create(Path::new(file)).unwrap();
+    image.write_to(fout, image::PNG).unwrap();
+}
+
+fn main() {
+    // The orientation 0 of each tile. The other orientations are its
+    // anticlockwise rotations.
+    let corner = 0;
+    let cross = 1;
+    let empty = 2;
+    let line = 3;
+    let tiles = vec![
+        Tile::new(tile_from_rows(&[".#.", ".##", "..."]), Symmetry::L, 0.5),
+        Tile::new(tile_from_rows(&[".#.", "###", ".#."]), Symmetry::X, 0.2),
+        Tile::new(tile_from_rows(&["...", "...", "..."]), Symmetry::X, 1.0),
+        Tile::new(tile_from_rows(&["...", "###", "..."]), Symmetry::I, 1.0),
+    ];
+
+    // Each entry [tile1, orientation1, tile2, orientation2] allows tile2 on
+    // the right of tile1. The rotations and reflections of the pairs are
+    // added by TilingWFC.
+    let neighbors = [
+        [corner, 0, corner, 1],
+        [corner, 0, corner, 2],
+        [corner, 0, cross, 0],
+        [corner, 0, line, 0],
+        [corner, 1, corner, 0],
+        [corner, 1, corner, 3],
+        [corner, 1, empty, 0],
+        [corner, 1, line, 1],
+        [cross, 0, cross, 0],
+        [cross, 0, line, 0],
+        [empty, 0, empty, 0],
+        [empty, 0, line, 1],
+        [line, 0, line, 0],
+        [line, 1, line, 1],
+    ];
+
+    let options = TilingWFCOptions { is_periodic: true };
+    let mut wfc = TilingWFC::from_u64_seed(tiles, &neighbors, 24, 24, options, 0).unwrap();
+
+    for seed in 0..10 {
+        wfc.restart_u64(seed);
+        if let Some(output) = wfc.run() {
+            fs::create_dir_all("results").unwrap();
+            write_to_file("results/simple_tiling.png", vec2d_to_image(&output));
+            println!("simple tiling finished!");
+            return;
+        }
+        println!("failed!");
+    }
+}
@@ -0,0 +1,143 @@
+use quick_xml::events::attributes::Attribute;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::env;
+use std::fs::File;
+use std::path::Path;
+use std::str::from_utf8;
+
+use image::{DynamicImage, GenericImageView, ImageBuffer, Rgb};
+
+use fast_wfc::tiling_wfc::xml::*;
+use fast_wfc::tiling_wfc::*;
+use fast_wfc::utils::vec2d::*;
+
+fn read_image(filepath: &Path) -> DynamicImage {
+    image::open(filepath).unwrap()
+}
+
+fn image_to_vec2d(image: &DynamicImage) -> Vec2D<Rgb<u8>> {
+    let mut image_vec2d = Vec2D::new(
+        image.height() as usize,
+        image.width() as usize,
+        &Rgb { data: [0, 0, 0] },
+    );
+
+    for (x, y, pixel) in image.pixels() {
+        image_vec2d[y as usize][x as usize] = Rgb {
+            data: [pixel[0], pixel[1], pixel[2]],
+        };
+    }
+
+    image_vec2d
+}
+
+fn vec2d_to_image(image: &Vec2D<Rgb<u8>>) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+    ImageBuffer::from_fn(image.width() as u32, image.height() as u32, |x, y| {
+        image[y as usize][x as usize]
+    })
+}
+
+fn write_to_file(file: &str, image: ImageBuffer<Rgb<u8>, Vec<u8>>) {
+    let image = DynamicImage::ImageRgb8(image);
+    let fout = &mut File::create(Path::new(file)).unwrap();
+    image.write_to(fout, image::PNG).unwrap();
+}
+
+fn get_attribute<'a, 'b>(attributes: &'b [Attribute<'a>], key: &str) -> Option<&'b str> {
+    attributes
+        .iter()
+        .find(|a| a.key == key.as_bytes())
+        .map(|a| from_utf8(&a.value).unwrap())
+}
+
+fn get_attribute_or<'a, 'b>(
+    attributes: &'b [Attribute<'a>],
+    key: &str,
+    default: &'b str,
+) -> &'b str {
+    get_attribute(attributes, key).unwrap_or(default)
+}
+
+fn main() {
+    let file = if env::args().count() == 2 {
+        env::args().nth(1).unwrap()
+    } else {
+        panic!("Please enter a file")
+    };
+
+    let mut reader = Reader::from_file(&file).unwrap();
+    reader.trim_text(true);
+    let mut buf = Vec::new();
+
+    loop {
+        match reader.read_event(&mut buf) {
+            Err(e) => panic!("Error at position {}: {:?}", reader.buffer_position(), e),
+            Ok(Event::Empty(ref c)) if c.name() == b"simpletiled" => {
+                let attributes = c.attributes().map(|a| a.unwrap()).collect::<Vec<_>>();
+                let name = get_attribute(&attributes, "name").unwrap();
+                let subset = get_attribute(&attributes, "subset");
+                let is_periodic = get_attribute_or(&attributes, "periodic", "False") == "True";
+                let screenshots = get_attribute_or(&attributes, "screenshots", "2")
+                    .parse::<usize>()
+                    .unwrap();
+                let width = get_attribute_or(&attributes, "width", "48")
+                    .parse::<usize>()
+                    .unwrap();
+                let height = get_attribute_or(&attributes, "height", "48")
+                    .parse::<usize>()
+                    .unwrap();
+
+                let options = TilingWFCOptions { is_periodic };
+                run_example(name, subset, height, width, options, screenshots);
+            }
+            Ok(Event::Empty(_)) => (),
+            Ok(Event::Eof) => break,
+            _ => println!("other"),
+        }
+    }
+}
+
+fn run_example(
+    name: &str,
+    subset: Option<&str>,
+    height: usize,
+    width: usize,
+    options: TilingWFCOptions,
+    screenshots: usize,
+) {
+    println!("{} started!", name);
+    let directory = String::from("samples/") + name;
+    let tileset = load_tileset(Path::new(&directory), subset, |path| {
+        image_to_vec2d(&read_image(path))
+    })
+    .unwrap();
+
+    let mut i = 0;
+    let mut wfc =
+        TilingWFC::from_u64_seed(tileset.tiles, &tileset.neighbors, height, width, options, i)
+            .unwrap();
+
+    let filename = match subset {
+        Some(subset) => format!("{} {}", name, subset),
+        None => String::from(name),
+    };
+
+    for _ in 0..screenshots {
+        let mut result_image = None;
+        for _ in 0..10 {
+            i += 1;
+            wfc.restart_u64(i);
+            result_image = wfc.run();
+            if result_image.is_some() {
+                break;
+            }
+            println!("failed!");
+        }
+        println!("{} finished!", filename);
+        if let Some(image) = result_image {
+            let image = vec2d_to_image(&image);
+            write_to_file(&(String::from("results/") + &filename + ".png"), image);
+        }
+    }
+}
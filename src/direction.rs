@@ -1,10 +1,10 @@
-//! A direction parallel to the axes in a 2D space
+//! A direction in a 2D space, parallel to the axes or diagonal
 
 use std::ops::{Index, IndexMut};
 use std::slice::{Iter, IterMut};
 use Direction::{Down, Left, Right, Up};
 
-/// The enum representing a direction parallel to the axes in a 2D space
+/// The enum representing a direction parallel to the axes in a 2D space.
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum Direction {
     Down = 0,
@@ -24,12 +24,13 @@ impl Direction {
         }
     }
 
-    /// Get all possible directions
+    /// Get all directions
     pub fn directions() -> [Direction; 4] {
         [Down, Left, Right, Up]
     }
 
-    /// Get the (y,x) coordinates of the normalized vector representing the direction.
+    /// Get the (y,x) coordinates of the vector representing the direction.
+    /// The vector is normalized.
     pub fn get_coordinates(self) -> (isize, isize) {
         match self {
             Down => (-1, 0),
@@ -40,10 +41,157 @@ impl Direction {
     }
 }
 
-/// An array that is indexed by a direction
+/// The enum representing a direction in a 2D space, either parallel to the
+/// axes or diagonal. The directions parallel to the axes have the same
+/// values as in Direction.
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
-pub struct DirArray<T> {
-    data: [T; 4],
+pub enum Direction8 {
+    Down = 0,
+    Left = 1,
+    Right = 2,
+    Up = 3,
+    DownLeft = 4,
+    DownRight = 5,
+    UpLeft = 6,
+    UpRight = 7,
+}
+
+impl Direction8 {
+    /// Get the opposite direction
+    pub fn opposite(self) -> Self {
+        match self {
+            Direction8::Down => Direction8::Up,
+            Direction8::Left => Direction8::Right,
+            Direction8::Right => Direction8::Left,
+            Direction8::Up => Direction8::Down,
+            Direction8::DownLeft => Direction8::UpRight,
+            Direction8::DownRight => Direction8::UpLeft,
+            Direction8::UpLeft => Direction8::DownRight,
+            Direction8::UpRight => Direction8::DownLeft,
+        }
+    }
+
+    /// Get all directions, the ones parallel to the axes first.
+    pub fn directions() -> [Direction8; 8] {
+        [
+            Direction8::Down,
+            Direction8::Left,
+            Direction8::Right,
+            Direction8::Up,
+            Direction8::DownLeft,
+            Direction8::DownRight,
+            Direction8::UpLeft,
+            Direction8::UpRight,
+        ]
+    }
+
+    /// Get the (y,x) coordinates of the vector representing the direction.
+    /// The vector is normalized on each axis.
+    pub fn get_coordinates(self) -> (isize, isize) {
+        match self {
+            Direction8::Down => (-1, 0),
+            Direction8::Left => (0, -1),
+            Direction8::Right => (0, 1),
+            Direction8::Up => (1, 0),
+            Direction8::DownLeft => (-1, -1),
+            Direction8::DownRight => (-1, 1),
+            Direction8::UpLeft => (1, -1),
+            Direction8::UpRight => (1, 1),
+        }
+    }
+}
+
+impl From<Direction> for Direction8 {
+    fn from(direction: Direction) -> Self {
+        match direction {
+            Down => Direction8::Down,
+            Left => Direction8::Left,
+            Right => Direction8::Right,
+            Up => Direction8::Up,
+        }
+    }
+}
+
+/// The enum representing a direction parallel to the axes in a 3D space.
+/// The coordinates are given as (z, y, x): Down, Left, Right and Up move on the
+/// y and x axes as in 2D, while Back decreases z and Front increases z.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Direction3D {
+    Down = 0,
+    Left = 1,
+    Right = 2,
+    Up = 3,
+    Back = 4,
+    Front = 5,
+}
+
+impl Direction3D {
+    /// Get the opposite direction
+    pub fn opposite(self) -> Self {
+        match self {
+            Direction3D::Down => Direction3D::Up,
+            Direction3D::Left => Direction3D::Right,
+            Direction3D::Right => Direction3D::Left,
+            Direction3D::Up => Direction3D::Down,
+            Direction3D::Back => Direction3D::Front,
+            Direction3D::Front => Direction3D::Back,
+        }
+    }
+
+    /// Get all directions
+    pub fn directions() -> [Direction3D; 6] {
+        [
+            Direction3D::Down,
+            Direction3D::Left,
+            Direction3D::Right,
+            Direction3D::Up,
+            Direction3D::Back,
+            Direction3D::Front,
+        ]
+    }
+
+    /// Get the (z, y, x) coordinates of the vector representing the direction.
+    pub fn get_coordinates(self) -> (isize, isize, isize) {
+        match self {
+            Direction3D::Down => (0, -1, 0),
+            Direction3D::Left => (0, 0, -1),
+            Direction3D::Right => (0, 0, 1),
+            Direction3D::Up => (0, 1, 0),
+            Direction3D::Back => (-1, 0, 0),
+            Direction3D::Front => (1, 0, 0),
+        }
+    }
+}
+
+/// An array that is indexed by a direction.
+/// By default, the array only contains the directions parallel to the axes.
+/// A DirArray<T, 8> also contains the diagonal directions, and can be indexed
+/// by a Direction8.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct DirArray<T, const N: usize = 4> {
+    data: [T; N],
+}
+
+/// A DirArray is serialized as the sequence of its values.
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize, const N: usize> serde::Serialize for DirArray<T, N> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.data.iter())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, T: serde::Deserialize<'de>, const N: usize> serde::Deserialize<'de> for DirArray<T, N> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use std::convert::TryInto;
+
+        let data = Vec::<T>::deserialize(deserializer)?;
+        let len = data.len();
+        let data = data.try_into().map_err(|_| {
+            serde::de::Error::invalid_length(len, &format!("{} values", N).as_str())
+        })?;
+        Ok(DirArray { data })
+    }
 }
 
 impl<T> DirArray<T> {
@@ -76,16 +224,94 @@ impl<T> DirArray<T> {
         }
     }
 
+    /// Return an iterator on the values, each given with its direction.
+    /// The directions are given in the order Down, Left, Right, Up.
+    pub fn iter_directions(&self) -> impl Iterator<Item = (Direction, &T)> {
+        IntoIterator::into_iter(Direction::directions()).zip(self.data.iter())
+    }
+
+    /// Return an iterator on mutable references to the values, each given
+    /// with its direction, in the same order as iter_directions.
+    pub fn iter_directions_mut(&mut self) -> impl Iterator<Item = (Direction, &mut T)> {
+        IntoIterator::into_iter(Direction::directions()).zip(self.data.iter_mut())
+    }
+}
+
+impl<T> DirArray<T, 8> {
+    /// Create a new array containing the diagonal directions, given a default
+    /// value that will be assigned to each direction.
+    pub fn new_with_diagonals(default: &T) -> DirArray<T, 8>
+    where
+        T: Clone,
+    {
+        DirArray::new_generator_with_diagonals(|_| default.clone())
+    }
+
+    /// Create a new array containing the diagonal directions, where the values
+    /// assigned to each direction is given by the given generator
+    pub fn new_generator_with_diagonals<F: Fn(Direction8) -> T>(generator: F) -> DirArray<T, 8> {
+        DirArray {
+            data: Direction8::directions().map(generator),
+        }
+    }
+
+    /// Return an iterator on the values, each given with its direction.
+    /// The directions are given in the order of Direction8::directions.
+    pub fn iter_directions(&self) -> impl Iterator<Item = (Direction8, &T)> {
+        IntoIterator::into_iter(Direction8::directions()).zip(self.data.iter())
+    }
+
+    /// Return an iterator on mutable references to the values, each given
+    /// with its direction, in the same order as iter_directions.
+    pub fn iter_directions_mut(&mut self) -> impl Iterator<Item = (Direction8, &mut T)> {
+        IntoIterator::into_iter(Direction8::directions()).zip(self.data.iter_mut())
+    }
+}
+
+impl<T> DirArray<T, 6> {
+    /// Create a new array containing the 3D directions, where the values
+    /// assigned to each direction is given by the given generator
+    pub fn new_generator_3d<F: Fn(Direction3D) -> T>(generator: F) -> DirArray<T, 6> {
+        DirArray {
+            data: Direction3D::directions().map(generator),
+        }
+    }
+
+    /// Return an iterator on the values, each given with its direction.
+    /// The directions are given in the order of Direction3D::directions.
+    pub fn iter_directions(&self) -> impl Iterator<Item = (Direction3D, &T)> {
+        IntoIterator::into_iter(Direction3D::directions()).zip(self.data.iter())
+    }
+}
+
+impl<T, const N: usize> DirArray<T, N> {
+    /// Create a new array where the value assigned to the direction of index
+    /// i, in the order of the directions, is given by the given generator.
+    pub fn new_generator_indexed<F: FnMut(usize) -> T>(generator: F) -> DirArray<T, N> {
+        DirArray {
+            data: std::array::from_fn(generator),
+        }
+    }
+
+    /// Get the value assigned to the direction of index i.
+    pub fn get(&self, i: usize) -> &T {
+        &self.data[i]
+    }
+
+    /// Get a mutable reference to the value assigned to the direction of index i.
+    pub fn get_mut(&mut self, i: usize) -> &mut T {
+        &mut self.data[i]
+    }
+
     /// Modify the data according to the given closure.
-    pub fn map<U, F: Fn(T) -> U>(self, f: F) -> DirArray<U> {
-        let [v0, v1, v2, v3] = self.data;
+    pub fn map<U, F: Fn(T) -> U>(self, f: F) -> DirArray<U, N> {
         DirArray {
-            data: [f(v0), f(v1), f(v2), f(v3)],
+            data: self.data.map(f),
         }
     }
 }
 
-impl<T> Index<Direction> for DirArray<T> {
+impl<T, const N: usize> Index<Direction> for DirArray<T, N> {
     type Output = T;
 
     fn index(&self, dir: Direction) -> &Self::Output {
@@ -93,7 +319,7 @@ impl<T> Index<Direction> for DirArray<T> {
     }
 }
 
-impl<'a, T> IntoIterator for &'a DirArray<T> {
+impl<'a, T, const N: usize> IntoIterator for &'a DirArray<T, N> {
     type Item = &'a T;
     type IntoIter = Iter<'a, T>;
 
@@ -102,7 +328,7 @@ impl<'a, T> IntoIterator for &'a DirArray<T> {
     }
 }
 
-impl<'a, T> IntoIterator for &'a mut DirArray<T> {
+impl<'a, T, const N: usize> IntoIterator for &'a mut DirArray<T, N> {
     type Item = &'a mut T;
     type IntoIter = IterMut<'a, T>;
 
@@ -111,12 +337,40 @@ impl<'a, T> IntoIterator for &'a mut DirArray<T> {
     }
 }
 
-impl<T> IndexMut<Direction> for DirArray<T> {
+impl<T, const N: usize> IndexMut<Direction> for DirArray<T, N> {
     fn index_mut(&mut self, dir: Direction) -> &mut Self::Output {
         &mut self.data[dir as u8 as usize]
     }
 }
 
+impl<T> Index<Direction3D> for DirArray<T, 6> {
+    type Output = T;
+
+    fn index(&self, dir: Direction3D) -> &Self::Output {
+        &self.data[dir as u8 as usize]
+    }
+}
+
+impl<T> IndexMut<Direction3D> for DirArray<T, 6> {
+    fn index_mut(&mut self, dir: Direction3D) -> &mut Self::Output {
+        &mut self.data[dir as u8 as usize]
+    }
+}
+
+impl<T> Index<Direction8> for DirArray<T, 8> {
+    type Output = T;
+
+    fn index(&self, dir: Direction8) -> &Self::Output {
+        &self.data[dir as u8 as usize]
+    }
+}
+
+impl<T> IndexMut<Direction8> for DirArray<T, 8> {
+    fn index_mut(&mut self, dir: Direction8) -> &mut Self::Output {
+        &mut self.data[dir as u8 as usize]
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -150,4 +404,82 @@ mod test {
         assert_eq!(array[Down], 2);
         assert_eq!(array[Right], 3);
     }
+
+    #[test]
+    fn test_opposite_involution() {
+        for &dir in &Direction8::directions() {
+            assert_eq!(dir.opposite().opposite(), dir);
+            let (dy, dx) = dir.get_coordinates();
+            assert_eq!(dir.opposite().get_coordinates(), (-dy, -dx));
+        }
+    }
+
+    #[test]
+    fn test_generator_with_diagonals() {
+        let array = DirArray::new_generator_with_diagonals(|dir| dir.get_coordinates());
+        for &dir in &Direction8::directions() {
+            assert_eq!(array[dir], dir.get_coordinates());
+        }
+        for &dir in &Direction::directions() {
+            assert_eq!(array[dir], dir.get_coordinates());
+            assert_eq!(
+                Direction8::from(dir).get_coordinates(),
+                dir.get_coordinates()
+            );
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_dir_array_serde() {
+        let array = DirArray::new_generator(|direction| direction as usize);
+        let json = serde_json::to_string(&array).unwrap();
+        assert_eq!(json, "[0,1,2,3]");
+        assert_eq!(
+            serde_json::from_str::<DirArray<usize>>(&json).unwrap(),
+            array
+        );
+        assert!(serde_json::from_str::<DirArray<usize, 8>>(&json).is_err());
+    }
+
+    #[test]
+    fn test_iter_directions() {
+        let mut array = DirArray::new_generator(|dir| dir as usize);
+        for (dir, value) in array.iter_directions() {
+            assert_eq!(array[dir], *value);
+        }
+        let directions: Vec<_> = array.iter_directions().map(|(dir, _)| dir).collect();
+        assert_eq!(directions, Direction::directions().to_vec());
+
+        for (dir, value) in array.iter_directions_mut() {
+            *value = dir.opposite() as usize;
+        }
+        assert_eq!(array[Down], Up as usize);
+
+        let array = DirArray::new_generator_with_diagonals(|dir| dir as usize);
+        assert_eq!(array.iter_directions().count(), 8);
+        for (dir, value) in array.iter_directions() {
+            assert_eq!(array[dir], *value);
+        }
+    }
+
+    #[test]
+    fn test_direction_3d() {
+        for &dir in &Direction3D::directions() {
+            assert_eq!(dir.opposite().opposite(), dir);
+            let (z, y, x) = dir.get_coordinates();
+            let (oz, oy, ox) = dir.opposite().get_coordinates();
+            assert_eq!((z + oz, y + oy, x + ox), (0, 0, 0));
+            assert_eq!(Direction3D::directions()[dir as usize], dir);
+        }
+
+        let mut array = DirArray::new_generator_3d(|dir| dir.get_coordinates());
+        for (dir, value) in array.iter_directions() {
+            assert_eq!(*value, dir.get_coordinates());
+        }
+        array[Direction3D::Front] = (0, 0, 0);
+        assert_eq!(*array.get(Direction3D::Front as usize), (0, 0, 0));
+        let indexed: DirArray<usize, 6> = DirArray::new_generator_indexed(|i| i);
+        assert_eq!(indexed[Direction3D::Back], 4);
+    }
 }
@@ -1,7 +1,8 @@
-#![feature(test)]
-
 pub mod direction;
+pub mod model;
+pub mod neighborhood;
 pub mod overlapping_wfc;
+pub mod overlapping_wfc_3d;
 pub mod propagator;
 pub mod tile;
 pub mod tiling_wfc;
@@ -9,4 +10,10 @@ pub mod utils;
 pub mod wave;
 pub mod wfc;
 
+/// The floating point type used for weights and entropies.
+#[cfg(not(feature = "f64"))]
 pub type Real = f32;
+
+/// The floating point type used for weights and entropies.
+#[cfg(feature = "f64")]
+pub type Real = f64;
@@ -0,0 +1,64 @@
+//! A common interface for the WFC models generating 2D outputs.
+
+use crate::utils::vec2d::*;
+
+/// A WFC model generating outputs made of values of type T, such as
+/// OverlappingWFC and TilingWFC.
+pub trait WfcModel<T> {
+    /// Run the wfc algorithm, and return the output if it succeeded.
+    fn run(&mut self) -> Option<Vec2D<T>>;
+
+    /// Reset the WFC algorithm with a new seed.
+    fn restart(&mut self, seed: [u8; 16]);
+
+    /// Get the height of the outputs.
+    fn height(&self) -> usize;
+
+    /// Get the width of the outputs.
+    fn width(&self) -> usize;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::overlapping_wfc::*;
+    use crate::tile::*;
+    use crate::tiling_wfc::*;
+
+    #[test]
+    fn test_dyn_model() {
+        let input = Vec2D::from_vec(vec![0, 1, 1, 0], 2, 2);
+        let options = OverlappingWFCOptions::builder()
+            .periodic_input(true)
+            .periodic_output(true)
+            .out_height(4)
+            .out_width(6)
+            .symmetry(1)
+            .pattern_size(2)
+            .build()
+            .unwrap();
+        let overlapping = OverlappingWFC::from_u64_seed(input, options, 0);
+
+        let tiles = vec![
+            Tile::new(Vec2D::new(2, 2, &0), Symmetry::X, 1.0),
+            Tile::new(Vec2D::new(2, 2, &1), Symmetry::X, 1.0),
+        ];
+        let options = TilingWFCOptions { is_periodic: false };
+        let tiling = TilingWFC::from_u64_seed(tiles, &[[0, 0, 1, 0]], 3, 5, options, 0).unwrap();
+
+        let mut models: Vec<Box<dyn WfcModel<u8>>> = vec![Box::new(overlapping), Box::new(tiling)];
+        let sizes: Vec<_> = models.iter().map(|m| (m.height(), m.width())).collect();
+        assert_eq!(sizes, vec![(4, 6), (6, 10)]);
+        for model in &mut models {
+            let output = model.run().unwrap();
+            assert_eq!(
+                (output.height(), output.width()),
+                (model.height(), model.width())
+            );
+            model.restart([1; 16]);
+            let first = model.run();
+            model.restart([1; 16]);
+            assert_eq!(model.run(), first);
+        }
+    }
+}
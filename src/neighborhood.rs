@@ -0,0 +1,163 @@
+//! Contain the Neighborhood struct, that gives the neighbors of the cells of a
+//! 2D or 3D grid stored in a wave.
+
+/// The (z, y, x) vectors of the directions of a 2D grid, in the order of
+/// Direction::directions.
+const DIRECTIONS_2D: [(isize, isize, isize); 4] = [(0, -1, 0), (0, 0, -1), (0, 0, 1), (0, 1, 0)];
+
+/// The (z, y, x) vectors of the directions of a 3D grid, in the order of
+/// Direction3D::directions.
+const DIRECTIONS_3D: [(isize, isize, isize); 6] = [
+    (0, -1, 0),
+    (0, 0, -1),
+    (0, 0, 1),
+    (0, 1, 0),
+    (-1, 0, 0),
+    (1, 0, 0),
+];
+
+/// The neighbors of the cells of a grid of size (depth, height, width).
+/// The cell (z, y, x) of the grid is stored in the cell (z * height + y, x) of
+/// the wave, so that a 2D grid is a grid of depth 1.
+/// The directions are identified by their index, which is their index in the
+/// DirArray containing the compatibilities of the patterns.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Neighborhood {
+    depth: usize,
+    height: usize,
+    width: usize,
+    /// The (z, y, x) vector of each direction.
+    directions: &'static [(isize, isize, isize)],
+}
+
+impl Neighborhood {
+    /// Create the neighborhood of a 2D grid, where the directions are the
+    /// ones of Direction.
+    pub fn new_2d(height: usize, width: usize) -> Self {
+        Neighborhood {
+            depth: 1,
+            height,
+            width,
+            directions: &DIRECTIONS_2D,
+        }
+    }
+
+    /// Create the neighborhood of a 3D grid, where the directions are the
+    /// ones of Direction3D.
+    pub fn new_3d(depth: usize, height: usize, width: usize) -> Self {
+        Neighborhood {
+            depth,
+            height,
+            width,
+            directions: &DIRECTIONS_3D,
+        }
+    }
+
+    /// Get the size of the grid, as (depth, height, width).
+    pub fn size(&self) -> (usize, usize, usize) {
+        (self.depth, self.height, self.width)
+    }
+
+    /// Get the number of rows of the wave storing the grid.
+    pub fn rows(&self) -> usize {
+        self.depth * self.height
+    }
+
+    /// Get the number of columns of the wave storing the grid.
+    pub fn cols(&self) -> usize {
+        self.width
+    }
+
+    /// Get the number of directions.
+    pub fn n_directions(&self) -> usize {
+        self.directions.len()
+    }
+
+    /// Get the index of the opposite of the direction of index direction.
+    pub fn opposite(&self, direction: usize) -> usize {
+        let (dz, dy, dx) = self.directions[direction];
+        self.directions
+            .iter()
+            .position(|&vector| vector == (-dz, -dy, -dx))
+            .expect("every direction has an opposite")
+    }
+
+    /// Get the cell of the wave that is the neighbor of the cell (i, j) in the
+    /// direction of index direction, or None if it is outside of a non
+    /// toric grid.
+    #[inline]
+    pub fn neighbor(
+        &self,
+        (i, j): (usize, usize),
+        direction: usize,
+        is_toric: bool,
+    ) -> Option<(usize, usize)> {
+        let (dz, dy, dx) = self.directions[direction];
+        let shift = |coordinate: usize, d: isize, size: usize| {
+            let coordinate = coordinate as isize + d;
+            if is_toric {
+                Some(((coordinate + size as isize) % size as isize) as usize)
+            } else if coordinate < 0 || coordinate >= size as isize {
+                None
+            } else {
+                Some(coordinate as usize)
+            }
+        };
+        let x = shift(j, dx, self.width)?;
+        // Avoid the divisions on a 2D grid, where i is the y coordinate.
+        if self.depth == 1 {
+            return Some((shift(i, dy, self.height)?, x));
+        }
+        let z = shift(i / self.height, dz, self.depth)?;
+        let y = shift(i % self.height, dy, self.height)?;
+        Some((z * self.height + y, x))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::direction::{Direction, Direction3D};
+
+    #[test]
+    fn test_directions() {
+        for &direction in &Direction::directions() {
+            let (dy, dx) = direction.get_coordinates();
+            assert_eq!(DIRECTIONS_2D[direction as usize], (0, dy, dx));
+            let neighborhood = Neighborhood::new_2d(2, 2);
+            assert_eq!(
+                neighborhood.opposite(direction as usize),
+                direction.opposite() as usize
+            );
+        }
+        for &direction in &Direction3D::directions() {
+            assert_eq!(
+                DIRECTIONS_3D[direction as usize],
+                direction.get_coordinates()
+            );
+            let neighborhood = Neighborhood::new_3d(2, 2, 2);
+            assert_eq!(
+                neighborhood.opposite(direction as usize),
+                direction.opposite() as usize
+            );
+        }
+    }
+
+    #[test]
+    fn test_neighbor() {
+        let neighborhood = Neighborhood::new_3d(2, 3, 4);
+        assert_eq!((neighborhood.rows(), neighborhood.cols()), (6, 4));
+        // The cell (z, y, x) = (0, 2, 1) is stored in the cell (2, 1).
+        let front = Direction3D::Front as usize;
+        let up = Direction3D::Up as usize;
+        assert_eq!(neighborhood.neighbor((2, 1), front, false), Some((5, 1)));
+        assert_eq!(neighborhood.neighbor((2, 1), up, false), None);
+        assert_eq!(neighborhood.neighbor((2, 1), up, true), Some((0, 1)));
+        assert_eq!(neighborhood.neighbor((5, 1), front, true), Some((2, 1)));
+
+        let neighborhood = Neighborhood::new_2d(3, 4);
+        let left = Direction::Left as usize;
+        assert_eq!(neighborhood.neighbor((1, 0), left, false), None);
+        assert_eq!(neighborhood.neighbor((1, 0), left, true), Some((1, 3)));
+    }
+}
@@ -1,21 +1,214 @@
 //! Contains the OverlappingWFC struct, which is used to apply the overlapping WFC on a 2D image
 
 use crate::direction::*;
+use crate::model::WfcModel;
 use crate::utils::vec2d::*;
-use crate::wfc::WFC;
+use crate::wave::{Heuristic, WaveError};
+use crate::wfc::{derive_seed, random_seed, seed_from_u64, ProgressInfo, WFC};
+use crate::Real;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 use std::collections::hash_map::{DefaultHasher, HashMap};
 use std::hash::{BuildHasherDefault, Hash};
 
-/// The available options used for overlappingWFC
+/// The available options used for overlappingWFC.
+/// They are created with [OverlappingWFCOptions::builder], which checks them.
 #[derive(Clone, Copy, Debug)]
+#[non_exhaustive]
 pub struct OverlappingWFCOptions {
     pub periodic_input: bool,
     pub periodic_output: bool,
     pub out_height: usize,
     pub out_width: usize,
     pub symmetry: usize,
-    pub pattern_size: usize,
-    pub ground: bool,
+    pub pattern_height: usize,
+    pub pattern_width: usize,
+    /// The edge of the output where the ground pattern is placed, if any.
+    /// The edge is given as seen in the output image: Down is the last row,
+    /// Up is the first row, Left is the first column, and Right is the last column.
+    /// The ground pattern is then excluded from every other cell.
+    pub ground_edge: Option<Direction>,
+    /// The way the number of occurrences of a pattern is turned into its weight.
+    pub weighting: PatternWeighting,
+}
+
+/// The transformation applied on the number of occurrences of a pattern in the
+/// input to get its weight.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PatternWeighting {
+    /// The weight is the number of occurrences.
+    Count,
+    /// The weight is ln(1 + number of occurrences), which favors less the
+    /// common patterns.
+    Log,
+    /// Every pattern has a weight of 1.
+    Uniform,
+}
+
+impl PatternWeighting {
+    /// Get the weight of a pattern given its number of occurrences.
+    pub fn weight(self, occurrences: usize) -> Real {
+        match self {
+            PatternWeighting::Count => occurrences as Real,
+            PatternWeighting::Log => (1.0 + occurrences as Real).ln(),
+            PatternWeighting::Uniform => 1.0,
+        }
+    }
+}
+
+impl OverlappingWFCOptions {
+    /// Get a builder for the options, with default values.
+    pub fn builder() -> OverlappingWFCOptionsBuilder {
+        OverlappingWFCOptionsBuilder::new()
+    }
+
+    /// Check that the options can be used to create an OverlappingWFC.
+    pub fn validate(&self) -> Result<(), OptionsError> {
+        if self.pattern_height == 0 || self.pattern_width == 0 {
+            return Err(OptionsError::EmptyPattern);
+        }
+        if self.pattern_height > self.out_height {
+            return Err(OptionsError::PatternTooHigh);
+        }
+        if self.pattern_width > self.out_width {
+            return Err(OptionsError::PatternTooWide);
+        }
+        if ![1, 2, 4, 8].contains(&self.symmetry) {
+            return Err(OptionsError::InvalidSymmetry);
+        }
+        if self.symmetry > 2 && self.pattern_height != self.pattern_width {
+            return Err(OptionsError::RotatedRectangularPattern);
+        }
+        Ok(())
+    }
+}
+
+/// The invariants that OverlappingWFCOptions may fail to respect.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OptionsError {
+    /// The pattern height or width is 0.
+    EmptyPattern,
+    /// The pattern height is bigger than the output height.
+    PatternTooHigh,
+    /// The pattern width is bigger than the output width.
+    PatternTooWide,
+    /// The pattern depth is bigger than the output depth, for the 3D options.
+    PatternTooDeep,
+    /// The input is not periodic, and is smaller than the patterns, so that
+    /// no pattern can be extracted from it.
+    InputTooSmall,
+    /// The symmetry is not 1, 2, 4, or 8.
+    InvalidSymmetry,
+    /// The symmetry contains rotations, but the patterns are not square.
+    RotatedRectangularPattern,
+    /// The output is not periodic, but the operation needs a periodic output,
+    /// see [OverlappingWFC::run_tiled].
+    NonPeriodicOutput,
+}
+
+/// Builder for OverlappingWFCOptions.
+/// The default values are the ones used by the original WFC implementation.
+#[derive(Clone, Copy, Debug)]
+pub struct OverlappingWFCOptionsBuilder {
+    options: OverlappingWFCOptions,
+}
+
+impl Default for OverlappingWFCOptionsBuilder {
+    fn default() -> Self {
+        OverlappingWFCOptionsBuilder::new()
+    }
+}
+
+impl OverlappingWFCOptionsBuilder {
+    /// Create a builder with the default values.
+    pub fn new() -> Self {
+        OverlappingWFCOptionsBuilder {
+            options: OverlappingWFCOptions {
+                periodic_input: true,
+                periodic_output: false,
+                out_height: 48,
+                out_width: 48,
+                symmetry: 8,
+                pattern_height: 3,
+                pattern_width: 3,
+                ground_edge: None,
+                weighting: PatternWeighting::Count,
+            },
+        }
+    }
+
+    /// Set if the input is toric.
+    pub fn periodic_input(mut self, periodic_input: bool) -> Self {
+        self.options.periodic_input = periodic_input;
+        self
+    }
+
+    /// Set if the output is toric.
+    pub fn periodic_output(mut self, periodic_output: bool) -> Self {
+        self.options.periodic_output = periodic_output;
+        self
+    }
+
+    /// Set the output height.
+    pub fn out_height(mut self, out_height: usize) -> Self {
+        self.options.out_height = out_height;
+        self
+    }
+
+    /// Set the output width.
+    pub fn out_width(mut self, out_width: usize) -> Self {
+        self.options.out_width = out_width;
+        self
+    }
+
+    /// Set the number of symmetries of the patterns (1, 2, 4, or 8).
+    pub fn symmetry(mut self, symmetry: usize) -> Self {
+        self.options.symmetry = symmetry;
+        self
+    }
+
+    /// Set both the height and the width of the patterns.
+    pub fn pattern_size(mut self, pattern_size: usize) -> Self {
+        self.options.pattern_height = pattern_size;
+        self.options.pattern_width = pattern_size;
+        self
+    }
+
+    /// Set the height of the patterns.
+    pub fn pattern_height(mut self, pattern_height: usize) -> Self {
+        self.options.pattern_height = pattern_height;
+        self
+    }
+
+    /// Set the width of the patterns.
+    pub fn pattern_width(mut self, pattern_width: usize) -> Self {
+        self.options.pattern_width = pattern_width;
+        self
+    }
+
+    /// Set if the ground pattern is placed on the Down edge.
+    #[deprecated(note = "use OverlappingWFCOptionsBuilder::ground_edge")]
+    pub fn ground(self, ground: bool) -> Self {
+        self.ground_edge(if ground { Some(Direction::Down) } else { None })
+    }
+
+    /// Set the edge where the ground pattern is placed.
+    pub fn ground_edge(mut self, ground_edge: Option<Direction>) -> Self {
+        self.options.ground_edge = ground_edge;
+        self
+    }
+
+    /// Set the way the number of occurrences of a pattern is turned into its weight.
+    pub fn weighting(mut self, weighting: PatternWeighting) -> Self {
+        self.options.weighting = weighting;
+        self
+    }
+
+    /// Get the options, or the first invariant they fail to respect.
+    pub fn build(self) -> Result<OverlappingWFCOptions, OptionsError> {
+        self.options.validate()?;
+        Ok(self.options)
+    }
 }
 
 /// Class used for the overlapping WFC
@@ -24,27 +217,189 @@ pub struct OverlappingWFC<T> {
     options: OverlappingWFCOptions,
     patterns: Vec<Vec2D<T>>,
     input: Vec2D<T>,
+    /// The seed given at the last creation or restart.
+    seed: [u8; 16],
+    /// The ground patterns given to init_ground_set, which are applied again
+    /// on restart.
+    ground_set: Vec<usize>,
 }
 
 impl<T: Eq + Hash + Clone> OverlappingWFC<T> {
     /// Given an image, create a WFC object for the overlapping algorithm.
+    /// Panic if the options are invalid, see [OverlappingWFC::try_new].
     pub fn new(
         input: Vec2D<T>,
         options: OverlappingWFCOptions,
         seed: [u8; 16],
     ) -> OverlappingWFC<T> {
-        assert!(options.pattern_size <= options.out_height);
-        assert!(options.pattern_size <= options.out_width);
+        OverlappingWFC::try_new(input, options, seed).unwrap()
+    }
+
+    /// Given an image, create a WFC object for the overlapping algorithm.
+    /// Return the first invariant the options fail to respect, if any, or
+    /// OptionsError::InputTooSmall if the input is not periodic and smaller
+    /// than the patterns.
+    pub fn try_new(
+        input: Vec2D<T>,
+        options: OverlappingWFCOptions,
+        seed: [u8; 16],
+    ) -> Result<OverlappingWFC<T>, OptionsError> {
+        options.validate()?;
+        if !options.periodic_input
+            && (input.height() < options.pattern_height || input.width() < options.pattern_width)
+        {
+            return Err(OptionsError::InputTooSmall);
+        }
+        Ok(OverlappingWFC::new_with_metric(
+            input,
+            options,
+            seed,
+            |a, b| a == b,
+        ))
+    }
+
+    /// Given an image, create a WFC object for the overlapping algorithm,
+    /// where two patterns can overlap if the pixels in the overlapping region
+    /// are considered equal by the metric, instead of being exactly equal.
+    /// This changes the pattern-matching semantics: the patterns are still
+    /// extracted with an exact comparison, but two patterns may be placed
+    /// next to each other even if they disagree slightly on some pixels.
+    /// The output pixels are then taken from the pattern placed in the cell
+    /// deciding them.
+    pub fn new_with_metric<F>(
+        input: Vec2D<T>,
+        options: OverlappingWFCOptions,
+        seed: [u8; 16],
+        metric: F,
+    ) -> OverlappingWFC<T>
+    where
+        F: Fn(&T, &T) -> bool,
+    {
         let patterns = get_patterns(
             &input,
             options.periodic_input,
-            options.pattern_size,
+            options.pattern_height,
+            options.pattern_width,
             options.symmetry,
         );
+        OverlappingWFC::from_patterns(input, patterns, options, seed, |patterns| {
+            precompute_compatible(patterns, &metric)
+        })
+    }
 
-        let (patterns, weights): (Vec<_>, _) =
-            patterns.into_iter().map(|(p, w)| (p, w as f32)).unzip();
-        let compatible = precompute_compatible(&patterns);
+    /// Given an image, create a WFC object for the overlapping algorithm, where
+    /// the patterns are compared through their canonicalized form, given by
+    /// applying canon on each pixel. The patterns having the same canonicalized
+    /// form are merged, and their weights are summed. The merged pattern keeps
+    /// the pixels of the first pattern extracted, or of the ground pattern.
+    pub fn new_with_canonicalizer<K, F>(
+        input: Vec2D<T>,
+        options: OverlappingWFCOptions,
+        seed: [u8; 16],
+        canon: F,
+    ) -> OverlappingWFC<T>
+    where
+        K: Eq + Hash,
+        F: Fn(&T) -> K,
+    {
+        let ground = options
+            .ground_edge
+            .map(|edge| get_ground_pattern(&input, &options, edge));
+        let mut ids: HashMap<_, _, BuildHasherDefault<DefaultHasher>> = HashMap::default();
+        let mut patterns: Vec<(Vec2D<T>, usize)> = vec![];
+        for (pattern, occurrences) in get_patterns(
+            &input,
+            options.periodic_input,
+            options.pattern_height,
+            options.pattern_width,
+            options.symmetry,
+        ) {
+            let id = *ids.entry(pattern.map(&canon)).or_insert(patterns.len());
+            if id == patterns.len() {
+                patterns.push((pattern, occurrences));
+            } else {
+                if Some(&pattern) == ground.as_ref() {
+                    patterns[id].0 = pattern;
+                }
+                patterns[id].1 += occurrences;
+            }
+        }
+        OverlappingWFC::from_patterns(input, patterns, options, seed, |patterns| {
+            precompute_compatible(patterns, &|a, b| canon(a) == canon(b))
+        })
+    }
+
+    /// Given several images, create a WFC object for the overlapping algorithm,
+    /// using the patterns of every image. The weight of a pattern is computed
+    /// from its number of occurrences summed over all the images.
+    /// The ground pattern, if any, is taken from the first image.
+    pub fn from_samples(
+        inputs: &[Vec2D<T>],
+        options: OverlappingWFCOptions,
+        seed: [u8; 16],
+    ) -> OverlappingWFC<T> {
+        assert!(!inputs.is_empty());
+        let mut patterns: HashMap<_, _, BuildHasherDefault<DefaultHasher>> = HashMap::default();
+        for input in inputs {
+            for (pattern, occurrences) in get_patterns(
+                input,
+                options.periodic_input,
+                options.pattern_height,
+                options.pattern_width,
+                options.symmetry,
+            ) {
+                *patterns.entry(pattern).or_insert(0) += occurrences;
+            }
+        }
+        let patterns = patterns.into_iter().collect();
+        OverlappingWFC::from_patterns(inputs[0].clone(), patterns, options, seed, |patterns| {
+            precompute_compatible(patterns, &|a, b| a == b)
+        })
+    }
+
+    /// Given an image, create a WFC object for the overlapping algorithm, where
+    /// the symmetry of each pattern is the one given by symmetry_mask at the
+    /// cell the pattern is anchored at, see [get_patterns_with_symmetry_mask].
+    /// The symmetry of the options is not used.
+    pub fn new_with_symmetry_mask(
+        input: Vec2D<T>,
+        options: OverlappingWFCOptions,
+        symmetry_mask: &Vec2D<u8>,
+        seed: [u8; 16],
+    ) -> OverlappingWFC<T> {
+        let patterns = get_patterns_with_symmetry_mask(
+            &input,
+            options.periodic_input,
+            options.pattern_height,
+            options.pattern_width,
+            symmetry_mask,
+        );
+        OverlappingWFC::from_patterns(input, patterns, options, seed, |patterns| {
+            precompute_compatible(patterns, &|a, b| a == b)
+        })
+    }
+
+    /// Create a WFC object for the overlapping algorithm, given the patterns
+    /// extracted from input and their number of occurrences, and the function
+    /// computing the compatibilities of the patterns.
+    fn from_patterns<C>(
+        input: Vec2D<T>,
+        patterns: Vec<(Vec2D<T>, usize)>,
+        options: OverlappingWFCOptions,
+        seed: [u8; 16],
+        compatible: C,
+    ) -> OverlappingWFC<T>
+    where
+        C: FnOnce(&[Vec2D<T>]) -> Vec<DirArray<Vec<usize>>>,
+    {
+        if let Err(error) = options.validate() {
+            panic!("Invalid options: {:?}", error);
+        }
+        let (patterns, weights): (Vec<_>, _) = patterns
+            .into_iter()
+            .map(|(p, w)| (p, options.weighting.weight(w)))
+            .unzip();
+        let compatible = compatible(&patterns);
 
         let wfc = WFC::new(
             options.periodic_output,
@@ -60,106 +415,615 @@ impl<T: Eq + Hash + Clone> OverlappingWFC<T> {
             options,
             patterns,
             input,
+            seed,
+            ground_set: vec![],
         };
-        if options.ground {
-            wfc.init_ground();
+        if let Some(edge) = options.ground_edge {
+            wfc.init_ground(edge);
         }
         wfc
     }
 
+    /// Given an image, create a WFC object for the overlapping algorithm,
+    /// using a u64 seed.
+    pub fn from_u64_seed(
+        input: Vec2D<T>,
+        options: OverlappingWFCOptions,
+        seed: u64,
+    ) -> OverlappingWFC<T> {
+        OverlappingWFC::new(input, options, seed_from_u64(seed))
+    }
+
+    /// Given an image, create a WFC object for the overlapping algorithm,
+    /// using a seed drawn from the thread random number generator.
+    /// The results are not reproducible, since the seed is different each time.
+    pub fn new_random(input: Vec2D<T>, options: OverlappingWFCOptions) -> OverlappingWFC<T> {
+        OverlappingWFC::new(input, options, random_seed())
+    }
+
     /// Reset the WFC algorithm
     pub fn restart(&mut self, seed: [u8; 16]) {
+        self.seed = seed;
         self.wfc.restart(seed);
-        if self.options.ground {
-            self.init_ground();
+        self.apply_ground();
+    }
+
+    /// Reset the WFC algorithm, using a u64 seed.
+    pub fn restart_u64(&mut self, seed: u64) {
+        self.restart(seed_from_u64(seed));
+    }
+
+    /// Apply the ground edge of the options, and the ground set given to
+    /// init_ground_set.
+    fn apply_ground(&mut self) {
+        if let Some(edge) = self.options.ground_edge {
+            self.init_ground(edge);
+        }
+        if !self.ground_set.is_empty() {
+            let ground_set = self.ground_set.clone();
+            self.init_ground_ids(Direction::Down, &ground_set);
         }
     }
 
-    /// Initialize the ground, given the ground pattern
-    fn init_ground(&mut self) {
-        let ground = get_ground_pattern(&self.input, &self.options);
-        let ground_id = self
-            .patterns
-            .iter()
-            .enumerate()
-            .find_map(|(i, x)| if *x == ground { Some(i) } else { None })
-            .unwrap();
+    /// Initialize the ground on the given edge, given the ground pattern
+    fn init_ground(&mut self, edge: Direction) {
+        let ground = get_ground_pattern(&self.input, &self.options, edge);
+        let ground_id = self.pattern_id(&ground).unwrap();
+        self.init_ground_ids(edge, &[ground_id]);
+    }
+
+    /// Allow only the given ground patterns on the bottom edge of the output,
+    /// and exclude them from every other cell. This generalizes the ground
+    /// edge option to several ground patterns, and should be called with no
+    /// ground edge set in the options. The ground patterns are applied again
+    /// when restarting.
+    pub fn init_ground_set(&mut self, ground_ids: &[usize]) {
+        self.ground_set = ground_ids.to_vec();
+        self.init_ground_ids(Direction::Down, ground_ids);
+    }
+
+    /// Allow only the ground patterns on the given edge, and exclude them from
+    /// every other cell.
+    fn init_ground_ids(&mut self, edge: Direction, ground_ids: &[usize]) {
+        let height = self.wfc.wave().rows();
+        let width = self.wfc.wave().cols();
 
-        let propagator = self.wfc.propagator();
-        let height = propagator.wave().height();
-        let width = propagator.wave().width();
-        for j in 0..width {
-            for p in 0..self.patterns.len() {
-                if p != ground_id {
-                    self.wfc.propagator().unset(height - 1, j, p);
+        for i in 0..height {
+            for j in 0..width {
+                let on_edge = is_on_edge(edge, i, j, height, width);
+                for p in 0..self.patterns.len() {
+                    if ground_ids.contains(&p) != on_edge {
+                        self.wfc.propagator().unset(i, j, p);
+                    }
                 }
             }
         }
+    }
 
-        for i in 0..height - 1 {
+    /// Remove the patterns that have a weight of 0, except the ground patterns,
+    /// and rebuild the underlying WFC. The ids of the remaining patterns may
+    /// change. This resets the algorithm with the last seed, and should thus be
+    /// called before run. The settings, the banned patterns and the patterns
+    /// forced with set_pattern are kept, with their new ids, except the ones
+    /// of the removed patterns.
+    /// Return WaveError::Impossible if the patterns forced with set_pattern
+    /// lead to a contradiction without the removed patterns.
+    pub fn prune_zero_weight_patterns(&mut self) -> Result<(), WaveError> {
+        let ground_id = self.options.ground_edge.map(|edge| {
+            let ground = get_ground_pattern(&self.input, &self.options, edge);
+            self.pattern_id(&ground).unwrap()
+        });
+        let weights = self.wfc.wave().weights().to_vec();
+        let kept: Vec<usize> = (0..self.patterns.len())
+            .filter(|&p| weights[p] != 0.0 || Some(p) == ground_id || self.ground_set.contains(&p))
+            .collect();
+        if kept.len() == self.patterns.len() {
+            return Ok(());
+        }
+
+        let mut new_ids = vec![None; self.patterns.len()];
+        for (new_id, &p) in kept.iter().enumerate() {
+            new_ids[p] = Some(new_id);
+        }
+        let compatibility = self.wfc.propagator().patterns_compatibility();
+        let compatibility = kept
+            .iter()
+            .map(|&p| {
+                compatibility[p]
+                    .clone()
+                    .map(|patterns| patterns.iter().filter_map(|&p2| new_ids[p2]).collect())
+            })
+            .collect();
+        let weights = kept.iter().map(|&p| weights[p]).collect();
+        self.patterns = kept.iter().map(|&p| self.patterns[p].clone()).collect();
+
+        let previous = std::mem::replace(
+            &mut self.wfc,
+            WFC::new(
+                self.options.periodic_output,
+                self.seed,
+                weights,
+                compatibility,
+                self.options.out_height,
+                self.options.out_width,
+            ),
+        );
+        self.ground_set = self.ground_set.iter().filter_map(|&p| new_ids[p]).collect();
+        self.apply_ground();
+        self.wfc.copy_settings(&previous, &new_ids)
+    }
+
+    /// Only allow the given patterns in the wave cells on the given edge, and
+    /// propagate the information. The edge is given as for the ground edge.
+    /// This should be called after the creation or a restart, and before run.
+    /// Return WaveError::Impossible if this leads to a contradiction.
+    pub fn constrain_border(
+        &mut self,
+        edge: Direction,
+        allowed: &[usize],
+    ) -> Result<(), WaveError> {
+        let height = self.wfc.wave().rows();
+        let width = self.wfc.wave().cols();
+        for i in 0..height {
             for j in 0..width {
-                self.wfc.propagator().unset(i, j, ground_id);
+                if is_on_edge(edge, i, j, height, width) {
+                    for p in 0..self.patterns.len() {
+                        if !allowed.contains(&p) {
+                            self.wfc.propagator().unset(i, j, p);
+                        }
+                    }
+                }
             }
         }
+        self.wfc.wave().check_contradiction()
+    }
+
+    /// Set the heuristic used to choose the next cell to collapse.
+    /// The heuristic is kept when restarting.
+    pub fn set_heuristic(&mut self, heuristic: Heuristic) {
+        self.wfc.set_heuristic(heuristic);
+    }
+
+    /// Enable or disable frequency matching, see [WFC::set_frequency_matching].
+    /// The setting is kept when restarting.
+    pub fn set_frequency_matching(&mut self, frequency_matching: bool) {
+        self.wfc.set_frequency_matching(frequency_matching);
+    }
+
+    /// Set the maximal random noise added to the entropy of each cell, see
+    /// [WFC::set_entropy_noise]. The noise is kept when restarting.
+    pub fn set_entropy_noise(&mut self, noise: Real) {
+        self.wfc.set_entropy_noise(noise);
+    }
+
+    /// Set the temperature of the choice of the patterns, see
+    /// [WFC::set_temperature]. The temperature is kept when restarting.
+    pub fn set_temperature(&mut self, temperature: Real) {
+        self.wfc.set_temperature(temperature);
+    }
+
+    /// Forbid a pattern to appear in the output, see [WFC::ban_pattern].
+    /// The ban is kept when restarting.
+    pub fn ban_pattern(&mut self, pattern: usize) {
+        self.wfc.ban_pattern(pattern);
     }
 
     /// Run the wfc algorithm with overlapping.
     pub fn run(&mut self) -> Option<Vec2D<T>> {
-        self.wfc.run().map(|patterns| self.to_image(&patterns))
+        self.run_with_progress(|_| ())
+    }
+
+    /// Run the wfc algorithm with overlapping, and call progress after each step.
+    pub fn run_with_progress<F: FnMut(ProgressInfo)>(&mut self, progress: F) -> Option<Vec2D<T>> {
+        self.wfc
+            .run_with_progress(progress)
+            .map(|patterns| self.to_image(&patterns))
+    }
+
+    /// Run the wfc algorithm with overlapping to generate n outputs.
+    /// Each output is computed with at most max_attempts_each attempts, and
+    /// is skipped if every attempt fails. The seed of the k-th attempt, counting
+    /// the attempts of every output, is derived from the last seed given to new
+    /// or restart and from k, so that the results are reproducible, and do not
+    /// depend on max_attempts_each when no attempt fails.
+    /// The outputs are not deduplicated: the same output may be returned
+    /// several times, especially when few outputs are possible.
+    pub fn run_n(&mut self, n: usize, max_attempts_each: usize) -> Vec<Vec2D<T>> {
+        let seed = self.seed;
+        let mut outputs = vec![];
+        let mut index = 0;
+        for _ in 0..n {
+            for _ in 0..max_attempts_each {
+                self.restart(derive_seed(seed, index));
+                index += 1;
+                if let Some(output) = self.run() {
+                    outputs.push(output);
+                    break;
+                }
+            }
+        }
+        self.seed = seed;
+        outputs
+    }
+
+    /// Run the wfc algorithm with overlapping until it succeeds, with at most
+    /// max_attempts attempts. The seed of attempt a is derived from base_seed
+    /// and a, so that the result is reproducible.
+    /// Return None if every attempt failed.
+    pub fn run_until_success(
+        &mut self,
+        max_attempts: usize,
+        base_seed: [u8; 16],
+    ) -> Option<Vec2D<T>> {
+        for attempt in 0..max_attempts {
+            self.restart(derive_seed(base_seed, attempt as u64));
+            if let Some(output) = self.run() {
+                return Some(output);
+            }
+        }
+        None
+    }
+
+    /// Run the wfc algorithm with overlapping once, and return the output
+    /// repeated reps_y times vertically and reps_x times horizontally.
+    /// The output should be periodic, so that the copies join seamlessly.
+    /// Return OptionsError::NonPeriodicOutput if it is not, or None if the
+    /// algorithm failed.
+    pub fn run_tiled(
+        &mut self,
+        reps_y: usize,
+        reps_x: usize,
+    ) -> Result<Option<Vec2D<T>>, OptionsError> {
+        if !self.options.periodic_output {
+            return Err(OptionsError::NonPeriodicOutput);
+        }
+        Ok(self.run().map(|output| output.tiled(reps_y, reps_x)))
+    }
+
+    /// Run the wfc algorithm with overlapping, and return the id of the
+    /// pattern chosen in each cell of the wave.
+    /// See [OverlappingWFC::patterns] to get the pattern associated with an id.
+    pub fn run_ids(&mut self) -> Option<Vec2D<usize>> {
+        self.wfc.run()
+    }
+
+    /// Regenerate the rectangular region of the wave of size (height, width)
+    /// and upper leftmost cell (y, x), keeping the other cells fixed.
+    /// The algorithm is restarted with seed, every cell outside of the region
+    /// is set to the pattern it has in base, which contains a pattern id for
+    /// each cell of the wave, and the cells of the region are then collapsed.
+    /// Return the pattern ids of the whole wave, or WaveError::Impossible if
+    /// the fixed cells are inconsistent, or if a contradiction was found.
+    pub fn regenerate_region(
+        &mut self,
+        base: &Vec2D<usize>,
+        y: usize,
+        x: usize,
+        height: usize,
+        width: usize,
+        seed: [u8; 16],
+    ) -> Result<Vec2D<usize>, WaveError> {
+        let wave = self.wfc.wave();
+        assert_eq!((base.height(), base.width()), (wave.rows(), wave.cols()));
+        assert!(y + height <= base.height() && x + width <= base.width());
+
+        self.restart(seed);
+        for ((i, j), &pattern) in base.iter_enumerate() {
+            let in_region = (y..y + height).contains(&i) && (x..x + width).contains(&j);
+            if !in_region {
+                self.set_pattern(i, j, pattern)?;
+            }
+        }
+        self.wfc.run_result()
+    }
+
+    /// Restart the algorithm with seed, set the pattern of every cell that
+    /// has a pattern id in template, and run the algorithm on the other cells.
+    /// template should have the size of the output.
+    /// Return None if the pinned patterns lead to a contradiction, or if the
+    /// algorithm failed.
+    pub fn run_from_template(
+        &mut self,
+        template: &Vec2D<Option<usize>>,
+        seed: [u8; 16],
+    ) -> Option<Vec2D<T>> {
+        assert_eq!(
+            (template.height(), template.width()),
+            (self.options.out_height, self.options.out_width)
+        );
+        self.restart(seed);
+        for ((i, j), pattern) in template.iter_enumerate() {
+            if let Some(pattern) = *pattern {
+                self.set_pattern(i, j, pattern).ok()?;
+            }
+        }
+        self.run()
+    }
+
+    /// Do a step of the wfc algorithm.
+    /// Return WaveError::Finished once every cell is determined, and
+    /// WaveError::Impossible if a contradiction was found.
+    pub fn step(&mut self) -> Result<(), WaveError> {
+        self.wfc.step()
+    }
+
+    /// Get the values that the output pixel (y, x) can still take, each with
+    /// the weight of the pattern placing it. A value appears once per possible
+    /// pattern placing it.
+    pub fn pixel_candidates(&self, y: usize, x: usize) -> Vec<(&T, Real)> {
+        let ((i, j), (di, dj)) = self.pixel_to_pattern_coordinates(y, x);
+        let wave = self.wfc.wave();
+        wave.cell(i, j)
+            .zip(wave.weights())
+            .enumerate()
+            .filter(|(_, (possible, _))| *possible)
+            .map(|(p, (_, weight))| (&self.patterns[p][di][dj], *weight))
+            .collect()
+    }
+
+    /// Get the patterns extracted from the input, indexed by their id.
+    pub fn patterns(&self) -> &[Vec2D<T>] {
+        &self.patterns
+    }
+
+    /// Get the weights of the patterns, indexed by their id.
+    ///
+    /// ```
+    /// use fast_wfc::overlapping_wfc::{OverlappingWFC, OverlappingWFCOptions};
+    /// use fast_wfc::utils::vec2d::Vec2D;
+    ///
+    /// let input = Vec2D::from_vec((0..9).collect(), 3, 3);
+    /// let options = OverlappingWFCOptions::builder()
+    ///     .periodic_input(false)
+    ///     .symmetry(1)
+    ///     .pattern_size(2)
+    ///     .build()
+    ///     .unwrap();
+    /// let wfc = OverlappingWFC::from_u64_seed(input, options, 0);
+    /// assert_eq!(wfc.patterns().len(), 4);
+    /// assert_eq!(wfc.pattern_weights(), &[1.0; 4]);
+    /// ```
+    pub fn pattern_weights(&self) -> &[Real] {
+        self.wfc.wave().weights()
+    }
+
+    /// Count the number of times each pattern is used in result_ids, which
+    /// contains a pattern id for each cell of the wave, as returned by run_ids.
+    pub fn pattern_usage(&self, result_ids: &Vec2D<usize>) -> Vec<usize> {
+        let mut usage = vec![0; self.patterns.len()];
+        for &pattern in result_ids {
+            usage[pattern] += 1;
+        }
+        usage
+    }
+
+    /// Get the fraction of the patterns that are used at least once in
+    /// result_ids. A low value usually means that the output is repetitive.
+    pub fn pattern_coverage(&self, result_ids: &Vec2D<usize>) -> Real {
+        let usage = self.pattern_usage(result_ids);
+        let used = usage.iter().filter(|&&count| count > 0).count();
+        used as Real / usage.len() as Real
+    }
+
+    /// Get the compatibilities of the patterns: adjacency()[pattern1][dir]
+    /// contains the patterns that can be placed in direction dir of pattern1.
+    pub fn adjacency(&self) -> &[DirArray<Vec<usize>>] {
+        self.wfc.adjacency()
+    }
+
+    /// Get the id of a pattern given its content, if it was extracted from the input.
+    pub fn pattern_id(&self, pattern: &Vec2D<T>) -> Option<usize> {
+        self.patterns.iter().position(|p| p == pattern)
+    }
+
+    /// Change the weight of a pattern, which is by default its number of
+    /// occurrences in the input. This should be called before run, or after
+    /// restart, since the entropy of every cell is recomputed.
+    /// The new weight is kept when restarting.
+    pub fn set_pattern_weight(&mut self, pattern: usize, weight: Real) {
+        self.wfc.set_pattern_weight(pattern, weight);
+    }
+
+    /// Force the pattern in the wave cell (y, x), and propagate the information.
+    /// Return WaveError::Impossible if this leads to a contradiction.
+    pub fn set_pattern(&mut self, y: usize, x: usize, pattern: usize) -> Result<(), WaveError> {
+        self.wfc.observe(y, x, pattern)
+    }
+
+    /// Force the output pixel (y, x) to be equal to value, by removing
+    /// every pattern that would place another value in this pixel.
+    /// Return WaveError::Impossible if this leads to a contradiction.
+    pub fn pin_pixel(&mut self, y: usize, x: usize, value: &T) -> Result<(), WaveError> {
+        let ((i, j), (di, dj)) = self.pixel_to_pattern_coordinates(y, x);
+        for p in 0..self.patterns.len() {
+            if self.patterns[p][di][dj] != *value {
+                self.wfc.propagator().unset(i, j, p);
+            }
+        }
+        self.wfc.propagator().wave().check_contradiction()
+    }
+
+    /// Get the value of the output pixel (i, j), given the pattern ids chosen
+    /// in each wave cell, as returned by run_ids. This is the pixel of the
+    /// pattern placed in the wave cell deciding it, which is not the cell
+    /// (i, j) on the bottom and right borders of a non periodic output.
+    pub fn pattern_pixel<'a>(&'a self, result_ids: &Vec2D<usize>, i: usize, j: usize) -> &'a T {
+        let ((y, x), (dy, dx)) = self.pixel_to_pattern_coordinates(i, j);
+        &self.patterns[result_ids[y][x]][dy][dx]
+    }
+
+    /// Get the wave cell that decides the value of the output pixel (y, x),
+    /// and the position of the pixel in the pattern placed in that cell.
+    fn pixel_to_pattern_coordinates(&self, y: usize, x: usize) -> ((usize, usize), (usize, usize)) {
+        let pattern_height = self.options.pattern_height;
+        let pattern_width = self.options.pattern_width;
+        if self.options.periodic_output {
+            ((y, x), (0, 0))
+        } else {
+            let (i, di) = if y < pattern_height {
+                (0, y)
+            } else {
+                (y - pattern_height + 1, pattern_height - 1)
+            };
+            let (j, dj) = if x < pattern_width {
+                (0, x)
+            } else {
+                (x - pattern_width + 1, pattern_width - 1)
+            };
+            ((i, j), (di, dj))
+        }
+    }
+
+    /// Get the current output, even if some cells are not yet determined.
+    /// Each pixel is computed by blend from the values it can still take,
+    /// each given with the weight of the pattern placing it (see
+    /// [OverlappingWFC::pixel_candidates]). A pixel of a decided cell is thus
+    /// given a single value, and a pixel in a contradiction is given none.
+    pub fn to_image_partial<F>(&self, blend: F) -> Vec2D<T>
+    where
+        F: Fn(&[(&T, Real)]) -> T,
+    {
+        Vec2D::from_generator(self.options.out_height, self.options.out_width, |y, x| {
+            blend(&self.pixel_candidates(y, x))
+        })
     }
 
     /// Return the result image, given the selected patterns for each cell.
+    /// The wave always has the size of the output. With a periodic output,
+    /// each pixel is thus the upper leftmost pixel of the pattern of its cell,
+    /// and the other pixels of the pattern are given by the neighboring cells.
     fn to_image(&self, output_patterns: &Vec2D<usize>) -> Vec2D<T> {
         let height = self.options.out_height;
         let width = self.options.out_width;
-        let pattern_size = self.options.pattern_size;
-        if self.options.periodic_output {
-            Vec2D::from_generator(height, width, |i, j| {
-                self.patterns[output_patterns[i][j]][0][0].clone()
-            })
-        } else {
-            Vec2D::from_generator(height, width, |i, j| {
-                let (i, di) = if i < pattern_size {
-                    (0, i)
-                } else {
-                    (i - pattern_size + 1, pattern_size - 1)
-                };
-                let (j, dj) = if j < pattern_size {
-                    (0, j)
-                } else {
-                    (j - pattern_size + 1, pattern_size - 1)
-                };
-                self.patterns[output_patterns[i][j]][di][dj].clone()
-            })
-        }
+        assert_eq!(
+            (output_patterns.height(), output_patterns.width()),
+            (height, width)
+        );
+        Vec2D::from_generator(height, width, |y, x| {
+            self.pattern_pixel(output_patterns, y, x).clone()
+        })
     }
 }
 
-/// Precompute the is_compatible function for a set of patterns.
-fn precompute_compatible<T: PartialEq>(patterns: &[Vec2D<T>]) -> Vec<DirArray<Vec<usize>>> {
+#[cfg(feature = "parallel")]
+impl<T: Eq + Hash + Clone + Sync> OverlappingWFC<T> {
+    /// Given an image, create a WFC object for the overlapping algorithm, as
+    /// with new, but compute the compatibility of each pattern on a separate
+    /// thread. The patterns and their ids are the same as with new.
+    pub fn new_parallel(
+        input: Vec2D<T>,
+        options: OverlappingWFCOptions,
+        seed: [u8; 16],
+    ) -> OverlappingWFC<T> {
+        let patterns = get_patterns(
+            &input,
+            options.periodic_input,
+            options.pattern_height,
+            options.pattern_width,
+            options.symmetry,
+        );
+        OverlappingWFC::from_patterns(input, patterns, options, seed, |patterns| {
+            precompute_compatible_parallel(patterns, &|a, b| a == b)
+        })
+    }
+}
+
+impl<T: Eq + Hash + Clone> WfcModel<T> for OverlappingWFC<T> {
+    fn run(&mut self) -> Option<Vec2D<T>> {
+        OverlappingWFC::run(self)
+    }
+
+    fn restart(&mut self, seed: [u8; 16]) {
+        OverlappingWFC::restart(self, seed)
+    }
+
+    fn height(&self) -> usize {
+        self.options.out_height
+    }
+
+    fn width(&self) -> usize {
+        self.options.out_width
+    }
+}
+
+/// Check if the cell (i, j) of a wave of size height * width is on the given edge.
+fn is_on_edge(edge: Direction, i: usize, j: usize, height: usize, width: usize) -> bool {
+    match edge {
+        Direction::Down => i == height - 1,
+        Direction::Up => i == 0,
+        Direction::Left => j == 0,
+        Direction::Right => j == width - 1,
+    }
+}
+
+/// Precompute the is_compatible_with function for a set of patterns.
+fn precompute_compatible<T, F>(patterns: &[Vec2D<T>], metric: &F) -> Vec<DirArray<Vec<usize>>>
+where
+    F: Fn(&T, &T) -> bool,
+{
     patterns
         .iter()
-        .map(|pattern1| {
-            DirArray::new_generator(|direction| {
-                patterns
-                    .iter()
-                    .enumerate()
-                    .filter_map(|(id, pattern2)| {
-                        if is_compatible(pattern1, pattern2, direction) {
-                            Some(id)
-                        } else {
-                            None
-                        }
-                    })
-                    .collect()
-            })
-        })
+        .map(|pattern1| compatible_patterns(pattern1, patterns, metric))
         .collect()
 }
 
+/// Precompute the is_compatible_with function for a set of patterns, where
+/// the compatibility of each pattern is computed on a separate thread.
+#[cfg(feature = "parallel")]
+fn precompute_compatible_parallel<T, F>(
+    patterns: &[Vec2D<T>],
+    metric: &F,
+) -> Vec<DirArray<Vec<usize>>>
+where
+    T: Sync,
+    F: Fn(&T, &T) -> bool + Sync,
+{
+    patterns
+        .par_iter()
+        .map(|pattern1| compatible_patterns(pattern1, patterns, metric))
+        .collect()
+}
+
+/// Get the ids of the patterns that can be placed in each direction of pattern1.
+fn compatible_patterns<T, F>(
+    pattern1: &Vec2D<T>,
+    patterns: &[Vec2D<T>],
+    metric: &F,
+) -> DirArray<Vec<usize>>
+where
+    F: Fn(&T, &T) -> bool,
+{
+    DirArray::new_generator(|direction| {
+        patterns
+            .iter()
+            .enumerate()
+            .filter_map(|(id, pattern2)| {
+                if is_compatible_with(pattern1, pattern2, direction, metric) {
+                    Some(id)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    })
+}
+
 /// Check if pattern1 is compatible with pattern2, when pattern2 is the neighbor
 /// in direction dir of pattern1.
+#[cfg(test)]
 fn is_compatible<T: PartialEq>(pattern1: &Vec2D<T>, pattern2: &Vec2D<T>, dir: Direction) -> bool {
+    is_compatible_with(pattern1, pattern2, dir, &|a: &T, b: &T| a == b)
+}
+
+/// Check if pattern1 is compatible with pattern2, when pattern2 is the neighbor
+/// in direction dir of pattern1, and when two pixels are considered equal if
+/// metric returns true.
+fn is_compatible_with<T, F: Fn(&T, &T) -> bool>(
+    pattern1: &Vec2D<T>,
+    pattern2: &Vec2D<T>,
+    dir: Direction,
+    metric: &F,
+) -> bool {
     assert!(pattern1.width() == pattern2.width());
     assert!(pattern1.height() == pattern2.height());
     assert!(pattern1.height() >= 1);
@@ -173,12 +1037,13 @@ fn is_compatible<T: PartialEq>(pattern1: &Vec2D<T>, pattern2: &Vec2D<T>, dir: Di
     let (y_min, y_max) = if dy < 0 {
         (0, (dy + pattern2.height() as isize) as usize)
     } else {
-        (dy as usize, pattern1.width())
+        (dy as usize, pattern1.height())
     };
 
     for y in y_min..y_max {
         for x in x_min..x_max {
-            if pattern1[y][x] != pattern2[(y as isize - dy) as usize][(x as isize - dx) as usize] {
+            let pixel2 = &pattern2[(y as isize - dy) as usize][(x as isize - dx) as usize];
+            if !metric(&pattern1[y][x], pixel2) {
                 return false;
             }
         }
@@ -187,54 +1052,56 @@ fn is_compatible<T: PartialEq>(pattern1: &Vec2D<T>, pattern2: &Vec2D<T>, dir: Di
     true
 }
 
-/// Get the list of patterns in the input, as well as the number of time they appear in the input.
-pub fn get_patterns<T>(
+/// Get the list of patterns in the input, as well as the number of time they appear in the input.
+/// Symmetries with rotations (4 and 8) can only be used with square patterns.
+pub fn get_patterns<T>(
+    input: &Vec2D<T>,
+    periodic: bool,
+    pattern_height: usize,
+    pattern_width: usize,
+    symmetry: usize,
+) -> Vec<(Vec2D<T>, usize)>
+where
+    T: Clone + Hash + Eq,
+{
+    PatternExtractor::new(input, periodic, symmetry).patterns(pattern_height, pattern_width)
+}
+
+/// Get the list of patterns in the input, as well as the number of time they appear in the input,
+/// using for each pattern the symmetry given by the mask at the cell the pattern is anchored at.
+/// The mask should have the same size as the input, and contain only 1, 2, 4 or 8.
+/// Symmetries with rotations (4 and 8) can only be used with square patterns.
+pub fn get_patterns_with_symmetry_mask<T>(
     input: &Vec2D<T>,
     periodic: bool,
-    pattern_size: usize,
-    symmetry: usize,
+    pattern_height: usize,
+    pattern_width: usize,
+    symmetry_mask: &Vec2D<u8>,
 ) -> Vec<(Vec2D<T>, usize)>
 where
     T: Clone + Hash + Eq,
 {
+    assert_eq!(symmetry_mask.height(), input.height());
+    assert_eq!(symmetry_mask.width(), input.width());
     let mut patterns: HashMap<_, _, BuildHasherDefault<DefaultHasher>> = HashMap::default();
 
-    let max_i = if periodic {
-        input.height()
-    } else {
-        input.height() - pattern_size + 1
-    };
-
-    let max_j = if periodic {
-        input.width()
+    let (max_i, max_j) = if periodic {
+        (input.height(), input.width())
     } else {
-        input.width() - pattern_size + 1
+        (
+            input.height() - pattern_height + 1,
+            input.width() - pattern_width + 1,
+        )
     };
 
     for i in 0..max_i {
         for j in 0..max_j {
-            let mut symmetries = Vec::new();
-            let pattern = input.get_sub_vec(i, j, pattern_size, pattern_size);
-            symmetries.push(pattern);
-
-            // We only support symmetry of size 1, 2, 4 and 8
-            if symmetry > 1 {
-                symmetries.push(symmetries[0].reflected());
-            }
-            if symmetry > 2 {
-                symmetries.push(symmetries[0].rotated());
-                symmetries.push(symmetries[2].reflected());
-            }
-            if symmetry > 4 {
-                symmetries.push(symmetries[2].rotated());
-                symmetries.push(symmetries[4].reflected());
-                symmetries.push(symmetries[4].rotated());
-                symmetries.push(symmetries[6].reflected());
-            }
-
-            for symmetry in symmetries {
-                let occurence = patterns.entry(symmetry).or_insert(0);
-                *occurence += 1;
+            let symmetry = symmetry_mask[i][j] as usize;
+            assert!([1, 2, 4, 8].contains(&symmetry));
+            assert!(symmetry <= 2 || pattern_height == pattern_width);
+            let pattern = input.get_sub_vec(i, j, pattern_height, pattern_width);
+            for pattern in symmetries(pattern, symmetry) {
+                *patterns.entry(pattern).or_insert(0) += 1;
             }
         }
     }
@@ -242,30 +1109,110 @@ where
     patterns.into_iter().collect()
 }
 
-/// Get the middle bottommost pattern of the input.
-/// If the input is toric, then this pattern is the one having only one pixel
-/// in the bottom, and options.pattern_size - 1 pixels in the top
-pub fn get_ground_pattern<T: Clone>(input: &Vec2D<T>, options: &OverlappingWFCOptions) -> Vec2D<T> {
-    if options.periodic_input {
-        input.get_sub_vec(
-            input.height() - 1,
-            (input.width() - options.pattern_size) / 2,
-            options.pattern_size,
-            options.pattern_size,
-        )
-    } else {
-        input.get_sub_vec(
-            input.height() - options.pattern_size,
-            (input.width() - options.pattern_size) / 2,
-            options.pattern_size,
-            options.pattern_size,
-        )
+/// Get the reflections and rotations of a pattern or of an input, in the
+/// order used for the symmetries of the patterns.
+/// The symmetry should be 1, 2, 4 or 8.
+fn symmetries<T: Clone>(pattern: Vec2D<T>, symmetry: usize) -> Vec<Vec2D<T>> {
+    let mut symmetries = vec![pattern];
+    if symmetry > 1 {
+        symmetries.push(symmetries[0].reflected());
+    }
+    if symmetry > 2 {
+        symmetries.push(symmetries[0].rotated());
+        symmetries.push(symmetries[2].reflected());
+    }
+    if symmetry > 4 {
+        symmetries.push(symmetries[2].rotated());
+        symmetries.push(symmetries[4].reflected());
+        symmetries.push(symmetries[4].rotated());
+        symmetries.push(symmetries[6].reflected());
+    }
+    symmetries
+}
+
+/// Extract the patterns of an input for several pattern sizes.
+/// The reflections and rotations of the input are computed once, so that the
+/// patterns of each size are directly read from them, instead of computing
+/// the symmetries of every pattern.
+pub struct PatternExtractor<T> {
+    /// The reflections and rotations of the input, in the order of the
+    /// symmetries of a pattern.
+    inputs: Vec<Vec2D<T>>,
+    /// Is the input toric.
+    periodic: bool,
+}
+
+impl<T: Clone + Hash + Eq> PatternExtractor<T> {
+    /// Create an extractor for the given input and symmetry.
+    /// The symmetry should be 1, 2, 4 or 8.
+    pub fn new(input: &Vec2D<T>, periodic: bool, symmetry: usize) -> Self {
+        PatternExtractor {
+            inputs: symmetries(input.clone(), symmetry),
+            periodic,
+        }
+    }
+
+    /// Get the list of patterns of the given size, as well as the number of
+    /// time they appear in the input, as returned by get_patterns.
+    /// Symmetries with rotations (4 and 8) can only be used with square patterns.
+    pub fn patterns(&self, pattern_height: usize, pattern_width: usize) -> Vec<(Vec2D<T>, usize)> {
+        assert!(self.inputs.len() <= 2 || pattern_height == pattern_width);
+        let mut patterns: HashMap<_, _, BuildHasherDefault<DefaultHasher>> = HashMap::default();
+
+        for input in &self.inputs {
+            let max_i = if self.periodic {
+                input.height()
+            } else {
+                input.height() - pattern_height + 1
+            };
+
+            let max_j = if self.periodic {
+                input.width()
+            } else {
+                input.width() - pattern_width + 1
+            };
+
+            for i in 0..max_i {
+                for j in 0..max_j {
+                    let pattern = input.get_sub_vec(i, j, pattern_height, pattern_width);
+                    let occurence = patterns.entry(pattern).or_insert(0);
+                    *occurence += 1;
+                }
+            }
+        }
+
+        patterns.into_iter().collect()
     }
 }
 
+/// Get the pattern of the input in the middle of the given edge.
+/// If the input is toric, then the pattern of the bottom edge is the one having
+/// only one pixel in the bottom, and options.pattern_height - 1 pixels in the top.
+/// The same goes for the right edge, with options.pattern_width - 1 pixels in the left.
+pub fn get_ground_pattern<T: Clone>(
+    input: &Vec2D<T>,
+    options: &OverlappingWFCOptions,
+    edge: Direction,
+) -> Vec2D<T> {
+    let pattern_height = options.pattern_height;
+    let pattern_width = options.pattern_width;
+    let middle_y = (input.height() - pattern_height) / 2;
+    let middle_x = (input.width() - pattern_width) / 2;
+    let (y, x) = match edge {
+        Direction::Down if options.periodic_input => (input.height() - 1, middle_x),
+        Direction::Down => (input.height() - pattern_height, middle_x),
+        Direction::Up => (0, middle_x),
+        Direction::Left => (middle_y, 0),
+        Direction::Right if options.periodic_input => (middle_y, input.width() - 1),
+        Direction::Right => (middle_y, input.width() - pattern_width),
+    };
+    input.get_sub_vec(y, x, pattern_height, pattern_width)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::propagator::patterns_without_neighbors;
 
     #[test]
     fn test_get_patterns() {
@@ -275,7 +1222,7 @@ mod test {
         let input = vec![0, 1, 2, 3, 4, 5, 6, 7, 8];
         let input = Vec2D::from_vec(input, 3, 3);
 
-        let patterns = get_patterns(&input, false, 2, 1);
+        let patterns = get_patterns(&input, false, 2, 2, 1);
         assert!(patterns
             .iter()
             .find(
@@ -311,7 +1258,7 @@ mod test {
         let input = vec![0, 1, 2, 3];
         let input = Vec2D::from_vec(input, 2, 2);
 
-        let patterns = get_patterns(&input, true, 2, 1);
+        let patterns = get_patterns(&input, true, 2, 2, 1);
         assert!(patterns
             .iter()
             .find(
@@ -347,7 +1294,7 @@ mod test {
         let input = vec![0, 1, 2, 3];
         let input = Vec2D::from_vec(input, 2, 2);
 
-        let patterns = get_patterns(&input, false, 2, 2);
+        let patterns = get_patterns(&input, false, 2, 2, 2);
         assert!(patterns
             .iter()
             .find(
@@ -364,6 +1311,49 @@ mod test {
         assert_eq!(patterns.len(), 2);
     }
 
+    #[test]
+    fn test_get_patterns_with_symmetry_mask() {
+        // 0 1 | 4 5
+        // 2 3 | 6 7
+        let input = Vec2D::from_vec(vec![0, 1, 4, 5, 2, 3, 6, 7], 2, 4);
+        let mask = Vec2D::from_vec(vec![1, 1, 8, 1, 1, 1, 1, 1], 2, 4);
+
+        let patterns = get_patterns_with_symmetry_mask(&input, false, 2, 2, &mask);
+        let count_with = |value: usize| {
+            patterns
+                .iter()
+                .filter(|(pattern, _)| pattern.iter().any(|&v| v == value))
+                .map(|(_, weight)| weight)
+                .sum::<usize>()
+        };
+        // The pattern anchored on the masked cell is not expanded.
+        assert_eq!(count_with(0), 1);
+        // The fully symmetric region yields its 8 variants.
+        assert_eq!(count_with(7), 8);
+        assert_eq!(patterns.len(), 1 + 1 + 8);
+
+        let mask = Vec2D::new(2, 4, &1);
+        let patterns = get_patterns_with_symmetry_mask(&input, false, 2, 2, &mask);
+        assert_eq!(patterns.len(), 3);
+        let options = OverlappingWFCOptions::builder()
+            .periodic_input(false)
+            .out_height(6)
+            .out_width(6)
+            .pattern_size(2)
+            .build()
+            .unwrap();
+        let mask = Vec2D::from_vec(vec![1, 1, 8, 1, 1, 1, 1, 1], 2, 4);
+        let wfc = OverlappingWFC::new_with_symmetry_mask(input.clone(), options, &mask, [0; 16]);
+        assert_eq!(wfc.patterns().len(), 1 + 1 + 8);
+        let mask = Vec2D::new(2, 4, &8);
+        let patterns = get_patterns_with_symmetry_mask(&input, false, 2, 2, &mask);
+        let full = get_patterns(&input, false, 2, 2, 8);
+        assert_eq!(patterns.len(), full.len());
+        for (pattern, weight) in &full {
+            assert!(patterns.contains(&(pattern.clone(), *weight)));
+        }
+    }
+
     #[test]
     fn test_get_patterns_multiple() {
         // 0 1 0
@@ -372,7 +1362,7 @@ mod test {
         let input = vec![0, 1, 0, 1, 0, 1, 0, 1, 0];
         let input = Vec2D::from_vec(input, 3, 3);
 
-        let patterns = get_patterns(&input, false, 2, 1);
+        let patterns = get_patterns(&input, false, 2, 2, 1);
         assert!(patterns
             .iter()
             .find(
@@ -422,4 +1412,847 @@ mod test {
 
         assert!(!is_compatible(&pattern1, &pattern2, Direction::Right))
     }
+
+    #[test]
+    fn test_pattern_pixel() {
+        let input = Vec2D::from_generator(3, 3, |i, j| i * 3 + j);
+        let options = OverlappingWFCOptions {
+            periodic_input: true,
+            periodic_output: false,
+            out_height: 5,
+            out_width: 6,
+            symmetry: 1,
+            pattern_height: 2,
+            pattern_width: 2,
+            ground_edge: None,
+            weighting: PatternWeighting::Count,
+        };
+        let mut wfc = OverlappingWFC::from_u64_seed(input, options, 0);
+        let pattern = wfc
+            .pattern_id(&Vec2D::from_vec(vec![4, 5, 7, 8], 2, 2))
+            .unwrap();
+        wfc.set_pattern(3, 4, pattern).unwrap();
+        let ids = wfc.run_ids().unwrap();
+
+        let image = wfc.to_image(&ids);
+        for ((i, j), value) in image.iter_enumerate() {
+            assert_eq!(wfc.pattern_pixel(&ids, i, j), value);
+        }
+        assert_eq!(*wfc.pattern_pixel(&ids, 4, 5), 8);
+        assert_eq!(*wfc.pattern_pixel(&ids, 3, 4), 4);
+    }
+
+    #[test]
+    fn test_pin_pixel() {
+        // 0 1
+        // 1 0
+        let input = Vec2D::from_vec(vec![0, 1, 1, 0], 2, 2);
+        let options = OverlappingWFCOptions {
+            periodic_input: true,
+            periodic_output: true,
+            out_height: 4,
+            out_width: 4,
+            symmetry: 1,
+            pattern_height: 2,
+            pattern_width: 2,
+            ground_edge: None,
+            weighting: PatternWeighting::Count,
+        };
+        let mut wfc = OverlappingWFC::from_u64_seed(input, options, 0);
+        assert!(wfc.pin_pixel(2, 1, &0).is_ok());
+        let output = wfc.run().unwrap();
+        assert_eq!(output[2][1], 0);
+        assert_eq!(output[0][0], 1);
+    }
+
+    #[test]
+    fn test_is_compatible_non_square() {
+        // 1 2 3
+        // 4 5 6
+        let pattern1 = Vec2D::from_vec(vec![1, 2, 3, 4, 5, 6], 2, 3);
+
+        // 4 5 6
+        // 7 8 9
+        let pattern2 = Vec2D::from_vec(vec![4, 5, 6, 7, 8, 9], 2, 3);
+
+        assert!(is_compatible(&pattern1, &pattern2, Direction::Up));
+        assert!(is_compatible(&pattern2, &pattern1, Direction::Down));
+        assert!(!is_compatible(&pattern1, &pattern2, Direction::Down));
+        assert!(!is_compatible(&pattern2, &pattern1, Direction::Up));
+    }
+
+    #[test]
+    fn test_ground_up() {
+        // 1 1 1
+        // 0 0 0
+        // 0 0 0
+        let input = Vec2D::from_vec(vec![1, 1, 1, 0, 0, 0, 0, 0, 0], 3, 3);
+        let options = OverlappingWFCOptions {
+            periodic_input: true,
+            periodic_output: true,
+            out_height: 4,
+            out_width: 4,
+            symmetry: 1,
+            pattern_height: 2,
+            pattern_width: 2,
+            ground_edge: Some(Direction::Up),
+            weighting: PatternWeighting::Count,
+        };
+        let ground = get_ground_pattern(&input, &options, Direction::Up);
+        assert_eq!(ground, Vec2D::from_vec(vec![1, 1, 0, 0], 2, 2));
+
+        let mut wfc = OverlappingWFC::from_u64_seed(input, options, 0);
+        let ground_id = wfc.patterns().iter().position(|p| *p == ground).unwrap();
+        let ids = wfc.run_ids().unwrap();
+        for ((i, _), id) in ids.iter_enumerate() {
+            assert_eq!(*id == ground_id, i == 0);
+        }
+    }
+
+    #[test]
+    fn test_init_ground_set() {
+        // 0 0 0 0
+        // 0 0 0 0
+        // 1 1 2 2
+        let input = Vec2D::from_vec(vec![0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 2, 2], 3, 4);
+        let options = OverlappingWFCOptions {
+            periodic_input: true,
+            periodic_output: true,
+            out_height: 4,
+            out_width: 8,
+            symmetry: 1,
+            pattern_height: 2,
+            pattern_width: 2,
+            ground_edge: None,
+            weighting: PatternWeighting::Count,
+        };
+        let mut wfc = OverlappingWFC::from_u64_seed(input, options, 0);
+        // The ground patterns are the ones with the last row of the input on top.
+        let ground_ids: Vec<_> = (0..wfc.patterns().len())
+            .filter(|&p| wfc.patterns()[p][0].iter().all(|&v| v != 0))
+            .collect();
+        assert_eq!(ground_ids.len(), 4);
+
+        // The ground set is applied again on restart.
+        wfc.init_ground_set(&ground_ids);
+        let mut bottom_values = vec![];
+        for seed in 0..20 {
+            wfc.restart_u64(seed);
+            let output = wfc.run().unwrap();
+            for ((i, _), &value) in output.iter_enumerate() {
+                assert_eq!(value != 0, i == 3);
+            }
+            bottom_values.extend_from_slice(&output[3]);
+        }
+        assert!(bottom_values.contains(&1));
+        assert!(bottom_values.contains(&2));
+    }
+
+    #[test]
+    fn test_restart() {
+        let input = Vec2D::from_rows(vec![
+            vec![1, 1, 1, 1],
+            vec![0, 0, 0, 0],
+            vec![0, 0, 2, 0],
+            vec![0, 0, 0, 0],
+        ])
+        .unwrap();
+        let options = OverlappingWFCOptions {
+            periodic_input: true,
+            periodic_output: true,
+            out_height: 6,
+            out_width: 6,
+            symmetry: 1,
+            pattern_height: 2,
+            pattern_width: 2,
+            ground_edge: Some(Direction::Up),
+            weighting: PatternWeighting::Count,
+        };
+        let ground = get_ground_pattern(&input, &options, Direction::Up);
+        let mut wfc = OverlappingWFC::from_u64_seed(input, options, 0);
+        let ground_id = wfc.pattern_id(&ground).unwrap();
+        let output = wfc.run_ids().unwrap();
+
+        // The ground is placed again after a restart.
+        wfc.restart(seed_from_u64(1));
+        let other_output = wfc.run_ids().unwrap();
+        for ((i, _), id) in other_output.iter_enumerate() {
+            assert_eq!(*id == ground_id, i == 0);
+        }
+
+        wfc.restart(seed_from_u64(0));
+        assert_eq!(wfc.run_ids().unwrap(), output);
+    }
+
+    #[test]
+    fn test_options_builder() {
+        let options = OverlappingWFCOptions::builder()
+            .out_height(10)
+            .ground_edge(Some(Direction::Down))
+            .build()
+            .unwrap();
+        assert_eq!(options.out_height, 10);
+        assert_eq!(options.out_width, 48);
+        assert_eq!((options.pattern_height, options.pattern_width), (3, 3));
+        assert_eq!(options.symmetry, 8);
+        assert!(options.periodic_input);
+        assert_eq!(options.ground_edge, Some(Direction::Down));
+        assert_eq!(options.weighting, PatternWeighting::Count);
+
+        #[allow(deprecated)]
+        let builder = OverlappingWFCOptions::builder().ground(true);
+        assert_eq!(builder.build().unwrap().ground_edge, Some(Direction::Down));
+    }
+
+    #[test]
+    fn test_try_new() {
+        let input = Vec2D::from_vec(vec![0, 1, 1, 0], 2, 2);
+        let mut options = OverlappingWFCOptions::builder()
+            .out_height(4)
+            .out_width(4)
+            .pattern_size(2)
+            .build()
+            .unwrap();
+        assert!(OverlappingWFC::try_new(input.clone(), options, [1; 16]).is_ok());
+
+        options.periodic_input = false;
+        options.pattern_height = 3;
+        options.pattern_width = 3;
+        assert_eq!(
+            OverlappingWFC::try_new(input.clone(), options, [1; 16]).err(),
+            Some(OptionsError::InputTooSmall)
+        );
+        options.periodic_input = true;
+        assert!(OverlappingWFC::try_new(input.clone(), options, [1; 16]).is_ok());
+
+        options.out_width = 1;
+        assert_eq!(
+            OverlappingWFC::try_new(input, options, [1; 16]).err(),
+            Some(OptionsError::PatternTooWide)
+        );
+    }
+
+    #[test]
+    fn test_options_builder_errors() {
+        let builder = OverlappingWFCOptions::builder();
+        assert_eq!(
+            builder.pattern_size(0).build().unwrap_err(),
+            OptionsError::EmptyPattern
+        );
+        assert_eq!(
+            builder.out_height(2).build().unwrap_err(),
+            OptionsError::PatternTooHigh
+        );
+        assert_eq!(
+            builder.out_width(2).build().unwrap_err(),
+            OptionsError::PatternTooWide
+        );
+        assert_eq!(
+            builder.symmetry(3).build().unwrap_err(),
+            OptionsError::InvalidSymmetry
+        );
+        assert_eq!(
+            builder.pattern_width(2).build().unwrap_err(),
+            OptionsError::RotatedRectangularPattern
+        );
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_new_parallel() {
+        let input = Vec2D::from_generator(4, 5, |i, j| (i * i + j) % 3);
+        let options = OverlappingWFCOptions::builder()
+            .out_height(6)
+            .out_width(6)
+            .pattern_size(2)
+            .build()
+            .unwrap();
+        let mut wfc = OverlappingWFC::new(input.clone(), options, [1; 16]);
+        let mut parallel_wfc = OverlappingWFC::new_parallel(input, options, [1; 16]);
+        assert_eq!(parallel_wfc.patterns(), wfc.patterns());
+        assert_eq!(parallel_wfc.adjacency(), wfc.adjacency());
+        assert_eq!(parallel_wfc.run_ids(), wfc.run_ids());
+    }
+
+    #[test]
+    fn test_run_n() {
+        // 0 1
+        // 1 0
+        let input = Vec2D::from_vec(vec![0, 1, 1, 0], 2, 2);
+        let options = OverlappingWFCOptions::builder()
+            .out_height(4)
+            .out_width(4)
+            .pattern_size(2)
+            .periodic_output(true)
+            .build()
+            .unwrap();
+        let mut wfc = OverlappingWFC::from_u64_seed(input, options, 0);
+        let outputs = wfc.run_n(3, 2);
+        assert_eq!(outputs.len(), 3);
+        assert_eq!(wfc.run_n(3, 2), outputs);
+        // Every attempt succeeds, so the outputs do not depend on the number
+        // of attempts allowed.
+        assert_eq!(wfc.run_n(3, 5), outputs);
+        assert_eq!(wfc.run_n(2, 1), outputs[..2].to_vec());
+    }
+
+    #[test]
+    fn test_run_until_success() {
+        // 0 1
+        // 1 0
+        let input = Vec2D::from_vec(vec![0, 1, 1, 0], 2, 2);
+        let options = OverlappingWFCOptions::builder()
+            .out_height(4)
+            .out_width(4)
+            .pattern_size(2)
+            .periodic_output(true)
+            .build()
+            .unwrap();
+        let mut wfc = OverlappingWFC::from_u64_seed(input, options, 0);
+        assert!(wfc.run_until_success(0, [1; 16]).is_none());
+        let output = wfc.run_until_success(3, [1; 16]).unwrap();
+        assert_eq!(wfc.run_until_success(3, [1; 16]), Some(output));
+    }
+
+    #[test]
+    fn test_get_patterns_rectangular() {
+        // 0 1 2 3
+        // 4 5 6 7
+        // 8 9 0 1
+        let input = Vec2D::from_vec(vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 0, 1], 3, 4);
+
+        let patterns = get_patterns(&input, false, 2, 3, 1);
+        let mut patterns: Vec<_> = patterns
+            .into_iter()
+            .map(|(pattern, weight)| {
+                assert_eq!((pattern.height(), pattern.width()), (2, 3));
+                (pattern.into_vec(), weight)
+            })
+            .collect();
+        patterns.sort();
+        assert_eq!(
+            patterns,
+            vec![
+                (vec![0, 1, 2, 4, 5, 6], 1),
+                (vec![1, 2, 3, 5, 6, 7], 1),
+                (vec![4, 5, 6, 8, 9, 0], 1),
+                (vec![5, 6, 7, 9, 0, 1], 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_run_rectangular() {
+        // 0 0 1 1
+        // 0 0 1 1
+        let input = Vec2D::from_vec(vec![0, 0, 1, 1, 0, 0, 1, 1], 2, 4);
+        let options = OverlappingWFCOptions::builder()
+            .out_height(5)
+            .out_width(7)
+            .pattern_height(1)
+            .pattern_width(3)
+            .symmetry(2)
+            .build()
+            .unwrap();
+        let mut wfc = OverlappingWFC::from_u64_seed(input, options, 0);
+        let output = wfc.run().unwrap();
+        assert_eq!((output.height(), output.width()), (5, 7));
+    }
+
+    #[test]
+    fn test_set_pattern_weight() {
+        // 0 1 2
+        let input = Vec2D::from_vec(vec![0, 1, 2], 1, 3);
+        let options = OverlappingWFCOptions::builder()
+            .out_height(8)
+            .out_width(8)
+            .pattern_size(1)
+            .build()
+            .unwrap();
+        let mut wfc = OverlappingWFC::from_u64_seed(input, options, 0);
+        let banned = wfc.pattern_id(&Vec2D::new(1, 1, &2)).unwrap();
+        wfc.set_pattern_weight(banned, 0.0);
+        for seed in 0..200 {
+            wfc.restart_u64(seed);
+            assert!(wfc
+                .wfc
+                .entropy_grid()
+                .iter()
+                .all(|entropy| entropy.is_finite()));
+            let output = wfc.run().unwrap();
+            assert!(output.iter().all(|value| *value != 2));
+        }
+    }
+
+    #[test]
+    fn test_new_with_metric() {
+        // 1 2 3
+        // 4 5 6
+        let pattern1 = Vec2D::from_vec(vec![1, 2, 3, 4, 5, 6], 2, 3);
+        // 3 0 0
+        // 7 0 0
+        let pattern2 = Vec2D::from_vec(vec![3, 0, 0, 7, 0, 0], 2, 3);
+        let close = |a: &i32, b: &i32| (a - b).abs() <= 1;
+        assert!(!is_compatible(&pattern1, &pattern2, Direction::Right));
+        let right = Direction::Right;
+        assert!(!is_compatible_with(&pattern1, &pattern2, right, &close));
+        // 2 3 0
+        // 5 7 0
+        let pattern2 = Vec2D::from_vec(vec![2, 3, 0, 5, 7, 0], 2, 3);
+        assert!(!is_compatible(&pattern1, &pattern2, right));
+        assert!(is_compatible_with(&pattern1, &pattern2, right, &close));
+
+        // 0 1 2
+        // 4 5 6
+        let input = Vec2D::from_vec(vec![0, 1, 2, 4, 5, 6], 2, 3);
+        let options = OverlappingWFCOptions::builder()
+            .out_height(6)
+            .out_width(6)
+            .pattern_size(2)
+            .symmetry(1)
+            .periodic_output(true)
+            .build()
+            .unwrap();
+        let wfc = OverlappingWFC::new_with_metric(input, options, [1; 16], close);
+        let count = |compatible: Vec<DirArray<Vec<usize>>>| {
+            let mut count = 0;
+            for direction in Direction::directions().iter() {
+                count += compatible
+                    .iter()
+                    .map(|c| c[*direction].len())
+                    .sum::<usize>();
+            }
+            count
+        };
+        let exact = precompute_compatible(wfc.patterns(), &|a: &i32, b: &i32| a == b);
+        let tolerant = precompute_compatible(wfc.patterns(), &close);
+        assert!(count(exact) < count(tolerant));
+    }
+
+    #[test]
+    fn test_pixel_candidates() {
+        // 0 1
+        // 1 0
+        let input = Vec2D::from_vec(vec![0, 1, 1, 0], 2, 2);
+        let options = OverlappingWFCOptions::builder()
+            .out_height(4)
+            .out_width(4)
+            .pattern_size(2)
+            .symmetry(1)
+            .periodic_output(true)
+            .build()
+            .unwrap();
+        let mut wfc = OverlappingWFC::from_u64_seed(input, options, 0);
+        let mut candidates = wfc.pixel_candidates(1, 2);
+        candidates.sort_by_key(|(value, _)| **value);
+        assert_eq!(candidates, vec![(&0, 2.0), (&1, 2.0)]);
+
+        assert!(wfc.step().is_ok());
+        assert_eq!(wfc.pixel_candidates(1, 2).len(), 1);
+    }
+
+    #[test]
+    fn test_to_image_partial() {
+        // 0 0 9
+        let input = Vec2D::from_vec(vec![0, 0, 9], 1, 3);
+        let options = OverlappingWFCOptions::builder()
+            .out_height(2)
+            .out_width(2)
+            .pattern_size(1)
+            .symmetry(1)
+            .build()
+            .unwrap();
+        let mut wfc = OverlappingWFC::from_u64_seed(input, options, 0);
+        let average = |candidates: &[(&i32, Real)]| {
+            let total: Real = candidates.iter().map(|(_, weight)| weight).sum();
+            let sum: Real = candidates.iter().map(|(v, w)| **v as Real * w).sum();
+            (sum / total) as i32
+        };
+        assert_eq!(wfc.to_image_partial(average), Vec2D::new(2, 2, &3));
+
+        assert!(wfc
+            .set_pattern(0, 1, wfc.pattern_id(&Vec2D::new(1, 1, &9)).unwrap())
+            .is_ok());
+        let image = wfc.to_image_partial(average);
+        assert_eq!(image.into_vec(), vec![3, 9, 3, 3]);
+    }
+
+    #[test]
+    fn test_weighting() {
+        // 0 1 0
+        // 1 0 1
+        // 0 1 0
+        let input = Vec2D::from_vec(vec![0, 1, 0, 1, 0, 1, 0, 1, 0], 3, 3);
+        let builder = OverlappingWFCOptions::builder()
+            .out_height(4)
+            .out_width(4)
+            .pattern_size(2)
+            .symmetry(1)
+            .periodic_input(false);
+        let weights = |weighting| {
+            let options = builder.weighting(weighting).build().unwrap();
+            let wfc = OverlappingWFC::from_u64_seed(input.clone(), options, 0);
+            wfc.wfc.wave().weights().to_vec()
+        };
+        assert_eq!(weights(PatternWeighting::Count), vec![2.0, 2.0]);
+        assert_eq!(weights(PatternWeighting::Uniform), vec![1.0, 1.0]);
+        let log = (3.0 as Real).ln();
+        assert_eq!(weights(PatternWeighting::Log), vec![log, log]);
+    }
+
+    #[test]
+    fn test_constrain_border() {
+        // 0 1 2
+        let input = Vec2D::from_vec(vec![0, 1, 2], 1, 3);
+        let options = OverlappingWFCOptions::builder()
+            .out_height(5)
+            .out_width(5)
+            .pattern_size(1)
+            .build()
+            .unwrap();
+        let mut wfc = OverlappingWFC::from_u64_seed(input, options, 0);
+        let top = wfc.pattern_id(&Vec2D::new(1, 1, &2)).unwrap();
+        assert!(wfc.constrain_border(Direction::Up, &[top]).is_ok());
+        let output = wfc.run().unwrap();
+        assert!(output[0].iter().all(|value| *value == 2));
+
+        wfc.restart_u64(1);
+        assert!(matches!(
+            wfc.constrain_border(Direction::Left, &[]),
+            Err(WaveError::Impossible { x: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn test_prune_zero_weight_patterns() {
+        // 0 1 2
+        let input = Vec2D::from_vec(vec![0, 1, 2], 1, 3);
+        let options = OverlappingWFCOptions::builder()
+            .out_height(6)
+            .out_width(6)
+            .pattern_size(1)
+            .build()
+            .unwrap();
+        let mut wfc = OverlappingWFC::from_u64_seed(input, options, 0);
+        let pruned = Vec2D::new(1, 1, &1);
+        wfc.set_pattern_weight(wfc.pattern_id(&pruned).unwrap(), 0.0);
+        wfc.prune_zero_weight_patterns().unwrap();
+        assert_eq!(wfc.patterns().len(), 2);
+        assert_eq!(wfc.pattern_id(&pruned), None);
+        let output = wfc.run().unwrap();
+        assert!(output.iter().all(|value| *value != 1));
+    }
+
+    #[test]
+    fn test_prune_keeps_settings() {
+        // 0 1 2 3
+        let input = Vec2D::from_vec(vec![0, 1, 2, 3], 1, 4);
+        let options = OverlappingWFCOptions::builder()
+            .out_height(6)
+            .out_width(6)
+            .pattern_size(1)
+            .build()
+            .unwrap();
+        let mut wfc = OverlappingWFC::from_u64_seed(input, options, 0);
+        let id = |wfc: &OverlappingWFC<i32>, value| wfc.pattern_id(&Vec2D::new(1, 1, &value));
+        wfc.set_heuristic(Heuristic::Scanline);
+        wfc.set_frequency_matching(true);
+        wfc.set_entropy_noise(0.5);
+        wfc.set_temperature(2.0);
+        wfc.set_pattern_weight(id(&wfc, 0).unwrap(), 0.0);
+        wfc.ban_pattern(id(&wfc, 1).unwrap());
+        wfc.set_pattern(2, 3, id(&wfc, 3).unwrap()).unwrap();
+        wfc.prune_zero_weight_patterns().unwrap();
+
+        assert_eq!(wfc.patterns().len(), 3);
+        assert_eq!(id(&wfc, 0), None);
+        assert_eq!(wfc.wfc.heuristic(), Heuristic::Scanline);
+        assert!(wfc.wfc.frequency_matching());
+        assert_eq!(wfc.wfc.entropy_noise(), 0.5);
+        assert_eq!(wfc.wfc.temperature(), 2.0);
+        assert_eq!(wfc.wfc.banned_patterns(), &[id(&wfc, 1).unwrap()]);
+        assert_eq!(wfc.wfc.observations(), &[(2, 3, id(&wfc, 3).unwrap())]);
+        let output = wfc.run().unwrap();
+        assert!(output.iter().all(|value| *value != 0 && *value != 1));
+        assert_eq!(output[2][3], 3);
+    }
+
+    #[test]
+    fn test_pattern_extractor() {
+        // The patterns of each symmetry of the input are the symmetries of
+        // the patterns of the input.
+        let input = Vec2D::from_vec((0..20).map(|i| i * i % 7).collect(), 4, 5);
+        for &periodic in &[false, true] {
+            for &symmetry in &[1, 2, 4, 8] {
+                let extractor = PatternExtractor::new(&input, periodic, symmetry);
+                for size in 1..4 {
+                    let mut expected = HashMap::new();
+                    for i in 0..input.height() - if periodic { 0 } else { size - 1 } {
+                        for j in 0..input.width() - if periodic { 0 } else { size - 1 } {
+                            let mut symmetries = vec![input.get_sub_vec(i, j, size, size)];
+                            for k in 1..symmetry {
+                                let previous = &symmetries[(k - 1) & !1];
+                                symmetries.push(if k % 2 == 1 {
+                                    previous.reflected()
+                                } else {
+                                    previous.rotated()
+                                });
+                            }
+                            for pattern in symmetries {
+                                *expected.entry(pattern).or_insert(0) += 1;
+                            }
+                        }
+                    }
+                    let patterns: HashMap<_, _> =
+                        extractor.patterns(size, size).into_iter().collect();
+                    assert_eq!(patterns, expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_adjacency() {
+        // 0 1 2
+        // 3 4 5
+        let input = Vec2D::from_vec(vec![0, 1, 2, 3, 4, 5], 2, 3);
+        let options = OverlappingWFCOptions::builder()
+            .periodic_input(false)
+            .symmetry(1)
+            .pattern_size(2)
+            .build()
+            .unwrap();
+        let wfc = OverlappingWFC::from_u64_seed(input, options, 0);
+        let left = wfc
+            .pattern_id(&Vec2D::from_vec(vec![0, 1, 3, 4], 2, 2))
+            .unwrap();
+        let right = 1 - left;
+        let adjacency = wfc.adjacency();
+        assert_eq!(adjacency[left][Direction::Right], vec![right]);
+        assert_eq!(adjacency[right][Direction::Left], vec![left]);
+        assert!(adjacency[left][Direction::Up].is_empty());
+
+        let mut expected = vec![
+            (left, Direction::Down),
+            (left, Direction::Left),
+            (left, Direction::Up),
+            (right, Direction::Down),
+            (right, Direction::Right),
+            (right, Direction::Up),
+        ];
+        expected.sort_by_key(|&(pattern, direction)| (pattern, direction as usize));
+        assert_eq!(patterns_without_neighbors(adjacency), expected);
+    }
+
+    #[test]
+    fn test_regenerate_region() {
+        let input = Vec2D::from_vec(vec![0, 0, 1, 0, 1, 1, 1, 0, 0], 3, 3);
+        let options = OverlappingWFCOptions::builder()
+            .periodic_output(true)
+            .out_height(6)
+            .out_width(6)
+            .symmetry(1)
+            .pattern_size(2)
+            .build()
+            .unwrap();
+        let mut wfc = OverlappingWFC::from_u64_seed(input, options, 0);
+        let base = wfc.run_ids().unwrap();
+
+        let output = wfc
+            .regenerate_region(&base, 2, 3, 2, 2, seed_from_u64(1))
+            .unwrap();
+        for ((i, j), &pattern) in output.iter_enumerate() {
+            if !(2..4).contains(&i) || !(3..5).contains(&j) {
+                assert_eq!(pattern, base[i][j]);
+            }
+            for &direction in &[Direction::Right, Direction::Up] {
+                let (di, dj) = direction.get_coordinates();
+                let neighbor =
+                    output[(i as isize + di) as usize % 6][(j as isize + dj) as usize % 6];
+                assert!(wfc.adjacency()[pattern][direction].contains(&neighbor));
+            }
+        }
+    }
+
+    #[test]
+    fn test_to_image_periodic() {
+        let input = Vec2D::from_vec(vec![0, 0, 1, 0, 1, 1, 1, 0, 0], 3, 3);
+        let options = OverlappingWFCOptions::builder()
+            .periodic_output(true)
+            .out_height(5)
+            .out_width(7)
+            .symmetry(2)
+            .pattern_size(2)
+            .build()
+            .unwrap();
+        let mut wfc = OverlappingWFC::from_u64_seed(input, options, 0);
+        let ids = wfc.run_ids().unwrap();
+        let image = wfc.to_image(&ids);
+        assert_eq!((image.height(), image.width()), (5, 7));
+        // The pattern of each cell is the toric window of the image starting there.
+        for ((i, j), &id) in ids.iter_enumerate() {
+            assert_eq!(image.get_sub_vec(i, j, 2, 2), wfc.patterns()[id]);
+        }
+    }
+
+    #[test]
+    fn test_run_from_template() {
+        let input = Vec2D::from_vec(vec![0, 0, 1, 0, 1, 1, 1, 0, 0], 3, 3);
+        let options = OverlappingWFCOptions::builder()
+            .periodic_output(true)
+            .out_height(6)
+            .out_width(6)
+            .symmetry(2)
+            .pattern_size(2)
+            .build()
+            .unwrap();
+        let mut wfc = OverlappingWFC::from_u64_seed(input, options, 0);
+        let base = wfc.run_ids().unwrap();
+        let template =
+            Vec2D::from_generator(6, 6, |i, j| if i == j { Some(base[i][j]) } else { None });
+        wfc.run_from_template(&template, seed_from_u64(1)).unwrap();
+        for i in 0..6 {
+            assert!(wfc.wfc.wave().is_collapsed(i, i));
+            assert!(wfc.wfc.wave().get(i, i, base[i][i]));
+        }
+
+        // Pin two copies of a pattern that cannot be placed on its own right.
+        let pattern = (0..wfc.patterns().len())
+            .find(|&p| !wfc.adjacency()[p][Direction::Right].contains(&p))
+            .unwrap();
+        let template =
+            Vec2D::from_generator(
+                6,
+                6,
+                |i, j| {
+                    if i == 0 && j < 2 {
+                        Some(pattern)
+                    } else {
+                        None
+                    }
+                },
+            );
+        assert!(wfc.run_from_template(&template, seed_from_u64(1)).is_none());
+    }
+
+    #[test]
+    fn test_pattern_usage() {
+        let input = Vec2D::from_vec((0..9).collect(), 3, 3);
+        let options = OverlappingWFCOptions::builder()
+            .periodic_input(false)
+            .symmetry(1)
+            .pattern_size(2)
+            .build()
+            .unwrap();
+        let wfc = OverlappingWFC::from_u64_seed(input, options, 0);
+        let result_ids = Vec2D::from_vec(vec![2, 2, 0, 2], 2, 2);
+        assert_eq!(wfc.pattern_usage(&result_ids), vec![1, 0, 3, 0]);
+        assert_eq!(wfc.pattern_coverage(&result_ids), 0.5);
+    }
+
+    #[test]
+    fn test_from_samples() {
+        let inputs = [
+            Vec2D::from_vec(vec![0, 1, 1, 2], 2, 2),
+            Vec2D::from_vec(vec![1, 2, 3, 3], 2, 2),
+        ];
+        let options = OverlappingWFCOptions::builder()
+            .periodic_input(false)
+            .symmetry(1)
+            .pattern_size(1)
+            .out_height(4)
+            .out_width(4)
+            .build()
+            .unwrap();
+        let wfc = OverlappingWFC::from_samples(&inputs, options, [0; 16]);
+        assert_eq!(wfc.patterns().len(), 4);
+        for &(value, weight) in &[(0, 1.0), (1, 3.0), (2, 2.0), (3, 2.0)] {
+            let id = wfc.pattern_id(&Vec2D::new(1, 1, &value)).unwrap();
+            assert_eq!(wfc.pattern_weights()[id], weight);
+        }
+    }
+
+    #[test]
+    fn test_single_pattern() {
+        let input = Vec2D::new(4, 4, &0);
+        let options = OverlappingWFCOptions::builder()
+            .out_height(6)
+            .out_width(5)
+            .build()
+            .unwrap();
+        let mut wfc = OverlappingWFC::from_u64_seed(input, options, 0);
+        assert_eq!(wfc.patterns().len(), 1);
+        assert!(matches!(wfc.step(), Err(WaveError::Finished)));
+        for &heuristic in &[
+            Heuristic::MinEntropy,
+            Heuristic::MinEntropyFirst,
+            Heuristic::Scanline,
+            Heuristic::MostConstrained,
+        ] {
+            wfc.restart_u64(0);
+            wfc.set_heuristic(heuristic);
+            assert_eq!(wfc.run(), Some(Vec2D::new(6, 5, &0)));
+        }
+    }
+
+    #[test]
+    fn test_new_with_canonicalizer() {
+        // 0 1
+        // 2 3
+        let input = Vec2D::from_vec(vec![0, 1, 2, 3], 2, 2);
+        let options = OverlappingWFCOptions::builder()
+            .periodic_input(false)
+            .symmetry(1)
+            .pattern_size(1)
+            .out_height(4)
+            .out_width(4)
+            .build()
+            .unwrap();
+        // 2 and 3 are considered equal, so their patterns are merged.
+        let canon = |&value: &usize| value.min(2);
+        let mut wfc = OverlappingWFC::new_with_canonicalizer(input, options, [0; 16], canon);
+        assert_eq!(wfc.patterns().len(), 3);
+        let merged = (0..3).find(|&id| wfc.patterns()[id][0][0] >= 2).unwrap();
+        assert_eq!(wfc.pattern_weights()[merged], 2.0);
+        assert!(wfc.run().is_some());
+    }
+
+    #[test]
+    fn test_run_tiled() {
+        let input = Vec2D::from_vec(vec![0, 0, 1, 0, 1, 1, 1, 2, 2], 3, 3);
+        let options = OverlappingWFCOptions::builder()
+            .out_height(5)
+            .out_width(4)
+            .symmetry(2)
+            .pattern_size(2)
+            .periodic_output(true)
+            .build()
+            .unwrap();
+        let mut wfc = OverlappingWFC::from_u64_seed(input, options, 0);
+        let output = wfc.run_tiled(2, 3).unwrap().unwrap();
+        assert_eq!((output.height(), output.width()), (10, 12));
+        // Every window, including the ones crossing the seams, is a pattern.
+        for i in 0..output.height() - 1 {
+            for j in 0..output.width() - 1 {
+                let window = output.get_sub_vec(i, j, 2, 2);
+                assert!(wfc.pattern_id(&window).is_some());
+            }
+        }
+    }
+
+    #[test]
+    fn test_run_tiled_non_periodic() {
+        let input = Vec2D::from_vec(vec![0, 1, 1, 0], 2, 2);
+        let options = OverlappingWFCOptions::builder()
+            .out_height(4)
+            .out_width(4)
+            .pattern_size(2)
+            .build()
+            .unwrap();
+        let mut wfc = OverlappingWFC::from_u64_seed(input, options, 0);
+        assert_eq!(
+            wfc.run_tiled(2, 2).err(),
+            Some(OptionsError::NonPeriodicOutput)
+        );
+    }
 }
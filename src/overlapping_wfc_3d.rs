@@ -0,0 +1,384 @@
+//! Contains the OverlappingWFC3D struct, which is used to apply the overlapping
+//! WFC on a 3D volume, for instance to generate voxels.
+
+use crate::direction::{DirArray, Direction3D};
+use crate::neighborhood::Neighborhood;
+use crate::overlapping_wfc::OptionsError;
+use crate::utils::vec2d::Vec2D;
+use crate::utils::vec3d::Vec3D;
+use crate::wave::Heuristic;
+use crate::wfc::{seed_from_u64, WFC};
+use crate::Real;
+use rand_xorshift::XorShiftRng;
+use std::collections::hash_map::{DefaultHasher, HashMap};
+use std::hash::{BuildHasherDefault, Hash};
+
+/// The available options used for OverlappingWFC3D.
+/// The patterns are cubes of size pattern_size, and are not rotated nor reflected.
+#[derive(Clone, Copy, Debug)]
+pub struct OverlappingWFC3DOptions {
+    pub periodic_input: bool,
+    pub periodic_output: bool,
+    pub out_depth: usize,
+    pub out_height: usize,
+    pub out_width: usize,
+    pub pattern_size: usize,
+}
+
+impl OverlappingWFC3DOptions {
+    /// Check that the options can be used to create an OverlappingWFC3D.
+    pub fn validate(&self) -> Result<(), OptionsError> {
+        if self.pattern_size == 0 {
+            return Err(OptionsError::EmptyPattern);
+        }
+        if self.pattern_size > self.out_depth {
+            return Err(OptionsError::PatternTooDeep);
+        }
+        if self.pattern_size > self.out_height {
+            return Err(OptionsError::PatternTooHigh);
+        }
+        if self.pattern_size > self.out_width {
+            return Err(OptionsError::PatternTooWide);
+        }
+        Ok(())
+    }
+}
+
+/// Class used for the overlapping WFC on a 3D volume.
+/// When the output is not periodic, the grid of patterns is smaller than the
+/// output by pattern_size - 1 on each axis, so that every pattern fits inside
+/// the output.
+/// The grid is stored in the rows and columns of the wave of a WFC with a 3D
+/// neighborhood, see [Neighborhood].
+pub struct OverlappingWFC3D<T> {
+    wfc: WFC<XorShiftRng, 6>,
+    options: OverlappingWFC3DOptions,
+    patterns: Vec<Vec3D<T>>,
+}
+
+impl<T: Eq + Hash + Clone> OverlappingWFC3D<T> {
+    /// Given a volume, create a WFC object for the overlapping algorithm.
+    /// Panic if the options are invalid, see [OverlappingWFC3D::try_new].
+    pub fn new(
+        input: Vec3D<T>,
+        options: OverlappingWFC3DOptions,
+        seed: [u8; 16],
+    ) -> OverlappingWFC3D<T> {
+        OverlappingWFC3D::try_new(input, options, seed).unwrap()
+    }
+
+    /// Given a volume, create a WFC object for the overlapping algorithm.
+    /// Return the first invariant the options fail to respect, if any, or
+    /// OptionsError::InputTooSmall if the input is not periodic and smaller
+    /// than the patterns.
+    pub fn try_new(
+        input: Vec3D<T>,
+        options: OverlappingWFC3DOptions,
+        seed: [u8; 16],
+    ) -> Result<OverlappingWFC3D<T>, OptionsError> {
+        options.validate()?;
+        let input_size = input.depth().min(input.height()).min(input.width());
+        if !options.periodic_input && input_size < options.pattern_size {
+            return Err(OptionsError::InputTooSmall);
+        }
+        let patterns = get_patterns_3d(&input, options.periodic_input, options.pattern_size);
+
+        let (patterns, weights): (Vec<_>, _) =
+            patterns.into_iter().map(|(p, w)| (p, w as Real)).unzip();
+        let compatible = precompute_compatible_3d(&patterns);
+
+        let margin = if options.periodic_output {
+            0
+        } else {
+            options.pattern_size - 1
+        };
+        let neighborhood = Neighborhood::new_3d(
+            options.out_depth - margin,
+            options.out_height - margin,
+            options.out_width - margin,
+        );
+        let wfc = WFC::new_with_neighborhood(
+            options.periodic_output,
+            seed,
+            weights,
+            compatible,
+            neighborhood,
+        );
+
+        Ok(OverlappingWFC3D {
+            wfc,
+            options,
+            patterns,
+        })
+    }
+
+    /// Given a volume, create a WFC object for the overlapping algorithm,
+    /// using a u64 seed.
+    pub fn from_u64_seed(
+        input: Vec3D<T>,
+        options: OverlappingWFC3DOptions,
+        seed: u64,
+    ) -> OverlappingWFC3D<T> {
+        OverlappingWFC3D::new(input, options, seed_from_u64(seed))
+    }
+
+    /// Reset the WFC algorithm
+    pub fn restart(&mut self, seed: [u8; 16]) {
+        self.wfc.restart(seed);
+    }
+
+    /// Get the patterns extracted from the input, indexed by their id.
+    pub fn patterns(&self) -> &[Vec3D<T>] {
+        &self.patterns
+    }
+
+    /// Set the heuristic used to choose the next cell to collapse.
+    /// The heuristic is kept when restarting.
+    pub fn set_heuristic(&mut self, heuristic: Heuristic) {
+        self.wfc.set_heuristic(heuristic);
+    }
+
+    /// Forbid a pattern to appear in the output, see [WFC::ban_pattern].
+    /// The ban is kept when restarting.
+    pub fn ban_pattern(&mut self, pattern: usize) {
+        self.wfc.ban_pattern(pattern);
+    }
+
+    /// Get the underlying WFC, whose wave stores the grid of patterns.
+    pub fn wfc(&mut self) -> &mut WFC<XorShiftRng, 6> {
+        &mut self.wfc
+    }
+
+    /// Run the wfc algorithm with overlapping.
+    pub fn run(&mut self) -> Option<Vec3D<T>> {
+        self.wfc.run().map(|patterns| self.to_volume(&patterns))
+    }
+
+    /// Run the wfc algorithm with overlapping, backtracking on contradictions,
+    /// see [WFC::run_with_backtracking].
+    /// Return None if no solution exists.
+    pub fn run_with_backtracking(&mut self) -> Option<Vec3D<T>> {
+        self.wfc
+            .run_with_backtracking()
+            .map(|patterns| self.to_volume(&patterns))
+    }
+
+    /// Return the result volume, given the selected patterns for each cell of
+    /// the wave, where the cell (z, y, x) of the grid is the cell
+    /// (z * height + y, x) of the wave.
+    /// Each voxel is taken from the last cell on each axis whose pattern covers it.
+    fn to_volume(&self, output_patterns: &Vec2D<usize>) -> Vec3D<T> {
+        let (depth, height, width) = self.wfc.wave().neighborhood().size();
+        let cell = |coordinate: usize, size: usize| coordinate.min(size - 1);
+        Vec3D::new_generator(
+            self.options.out_depth,
+            self.options.out_height,
+            self.options.out_width,
+            |z, y, x| {
+                let i = cell(z, depth);
+                let j = cell(y, height);
+                let k = cell(x, width);
+                let pattern = &self.patterns[output_patterns[i * height + j][k]];
+                pattern.get(z - i, y - j, x - k).clone()
+            },
+        )
+    }
+}
+
+/// Precompute the is_compatible_3d function for a set of patterns.
+fn precompute_compatible_3d<T: PartialEq>(patterns: &[Vec3D<T>]) -> Vec<DirArray<Vec<usize>, 6>> {
+    patterns
+        .iter()
+        .map(|pattern1| {
+            DirArray::new_generator_3d(|direction| {
+                patterns
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, pattern2)| is_compatible_3d(pattern1, pattern2, direction))
+                    .map(|(id, _)| id)
+                    .collect()
+            })
+        })
+        .collect()
+}
+
+/// Check if pattern1 is compatible with pattern2, when pattern2 is the neighbor
+/// in direction dir of pattern1. Both patterns should be cubes of the same size.
+fn is_compatible_3d<T: PartialEq>(
+    pattern1: &Vec3D<T>,
+    pattern2: &Vec3D<T>,
+    dir: Direction3D,
+) -> bool {
+    let size = pattern1.depth() as isize;
+    let (dz, dy, dx) = dir.get_coordinates();
+    let range = |d: isize| {
+        if d < 0 {
+            0..size + d
+        } else {
+            d..size
+        }
+    };
+
+    for z in range(dz) {
+        for y in range(dy) {
+            for x in range(dx) {
+                let value1 = pattern1.get(z as usize, y as usize, x as usize);
+                let value2 = pattern2.get((z - dz) as usize, (y - dy) as usize, (x - dx) as usize);
+                if value1 != value2 {
+                    return false;
+                }
+            }
+        }
+    }
+
+    true
+}
+
+/// Get the list of cubic patterns in the input, as well as the number of time
+/// they appear in the input.
+/// Panic if the input is not periodic and smaller than the patterns.
+pub fn get_patterns_3d<T>(
+    input: &Vec3D<T>,
+    periodic: bool,
+    pattern_size: usize,
+) -> Vec<(Vec3D<T>, usize)>
+where
+    T: Clone + Hash + Eq,
+{
+    let input_size = input.depth().min(input.height()).min(input.width());
+    assert!(
+        periodic || input_size >= pattern_size,
+        "the input is smaller than the patterns"
+    );
+    let mut patterns: HashMap<_, _, BuildHasherDefault<DefaultHasher>> = HashMap::default();
+
+    let max = |size: usize| {
+        if periodic {
+            size
+        } else {
+            size - pattern_size + 1
+        }
+    };
+
+    for i in 0..max(input.depth()) {
+        for j in 0..max(input.height()) {
+            for k in 0..max(input.width()) {
+                let pattern =
+                    Vec3D::new_generator(pattern_size, pattern_size, pattern_size, |di, dj, dk| {
+                        input
+                            .get(
+                                (i + di) % input.depth(),
+                                (j + dj) % input.height(),
+                                (k + dk) % input.width(),
+                            )
+                            .clone()
+                    });
+                let occurence = patterns.entry(pattern).or_insert(0);
+                *occurence += 1;
+            }
+        }
+    }
+
+    patterns.into_iter().collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_get_patterns_3d() {
+        let input = Vec3D::from_vec((0..8).collect(), 2, 2, 2);
+        let patterns = get_patterns_3d(&input, false, 2);
+        assert_eq!(patterns, vec![(input.clone(), 1)]);
+
+        let patterns = get_patterns_3d(&input, true, 2);
+        assert_eq!(patterns.len(), 8);
+        assert!(patterns.iter().all(|(_, weight)| *weight == 1));
+
+        let patterns = get_patterns_3d(&input, false, 1);
+        assert_eq!(patterns.len(), 8);
+    }
+
+    #[test]
+    fn test_input_too_small() {
+        let input = Vec3D::from_vec((0..8).collect(), 2, 2, 2);
+        let mut options = OverlappingWFC3DOptions {
+            periodic_input: false,
+            periodic_output: false,
+            out_depth: 4,
+            out_height: 4,
+            out_width: 4,
+            pattern_size: 3,
+        };
+        let result = OverlappingWFC3D::try_new(input.clone(), options, [1; 16]);
+        assert_eq!(result.err(), Some(OptionsError::InputTooSmall));
+
+        options.periodic_input = true;
+        assert!(OverlappingWFC3D::try_new(input.clone(), options, [1; 16]).is_ok());
+        options.out_depth = 2;
+        let result = OverlappingWFC3D::try_new(input, options, [1; 16]);
+        assert_eq!(result.err(), Some(OptionsError::PatternTooDeep));
+    }
+
+    #[test]
+    fn test_is_compatible_3d() {
+        let pattern1 = Vec3D::from_vec(vec![0, 0, 0, 0, 1, 1, 1, 1], 2, 2, 2);
+        let pattern2 = Vec3D::from_vec(vec![1, 1, 1, 1, 0, 0, 0, 0], 2, 2, 2);
+        assert!(is_compatible_3d(&pattern1, &pattern2, Direction3D::Front));
+        assert!(is_compatible_3d(&pattern2, &pattern1, Direction3D::Back));
+        assert!(!is_compatible_3d(&pattern1, &pattern1, Direction3D::Front));
+        assert!(!is_compatible_3d(&pattern1, &pattern2, Direction3D::Up));
+        assert!(is_compatible_3d(&pattern1, &pattern1, Direction3D::Up));
+    }
+
+    #[test]
+    fn test_run_layers() {
+        // The layers alternate between 0 and 1 along z.
+        let input = Vec3D::new_generator(2, 3, 3, |z, _, _| z);
+        let options = OverlappingWFC3DOptions {
+            periodic_input: true,
+            periodic_output: false,
+            out_depth: 5,
+            out_height: 4,
+            out_width: 3,
+            pattern_size: 2,
+        };
+        let mut wfc = OverlappingWFC3D::from_u64_seed(input, options, 0);
+        assert_eq!(wfc.patterns().len(), 2);
+        let output = wfc.run().unwrap();
+        assert_eq!((output.depth(), output.height(), output.width()), (5, 4, 3));
+        let first = *output.get(0, 0, 0);
+        for z in 0..5 {
+            for y in 0..4 {
+                for x in 0..3 {
+                    assert_eq!(*output.get(z, y, x), (first + z) % 2);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_backtracking_and_ban() {
+        // The layers alternate between 0 and 1 along z, so that the output is
+        // impossible once a pattern is banned.
+        let input = Vec3D::new_generator(2, 3, 3, |z, _, _| z);
+        let options = OverlappingWFC3DOptions {
+            periodic_input: true,
+            periodic_output: false,
+            out_depth: 4,
+            out_height: 3,
+            out_width: 3,
+            pattern_size: 2,
+        };
+        let mut wfc = OverlappingWFC3D::from_u64_seed(input, options, 0);
+        wfc.set_heuristic(Heuristic::Scanline);
+        let output = wfc.run_with_backtracking().unwrap();
+        let first = *output.get(0, 0, 0);
+        assert_eq!(*output.get(3, 2, 2), (first + 1) % 2);
+
+        wfc.restart([1; 16]);
+        wfc.ban_pattern(0);
+        assert!(wfc.run_with_backtracking().is_none());
+    }
+}
@@ -1,28 +1,89 @@
 //! Contain the Propagator stuct, that propagates information through the wave.
 
 use crate::direction::*;
+use crate::neighborhood::Neighborhood;
 use crate::utils::vec3d::Vec3D;
-use crate::wave::Wave;
+use crate::wave::{Wave, WaveError, WaveState};
 use crate::Real;
 
 /// Propagator is a wrapper around Wave, that ensure that the constraints between
 /// neighbors are respected.
-pub struct Propagator {
+/// N is the number of directions of the neighborhood of the wave, which is 4
+/// for a 2D grid and 6 for a 3D grid, see [Neighborhood].
+pub struct Propagator<const N: usize = 4> {
     /// The wave we propagate information in.
     wave: Wave,
     /// Is the wave toric.
     is_toric: bool,
+    /// The neighbors of each cell of the wave.
+    neighborhood: Neighborhood,
+    /// opposites[dir] is the index of the direction opposite to dir.
+    opposites: DirArray<usize, N>,
     /// patterns_compatibility[pattern1][dir][pattern2] is true
     /// if pattern1 can be placed in direction dir of pattern2.
-    patterns_compatibility: Vec<DirArray<Vec<usize>>>,
+    patterns_compatibility: Vec<DirArray<Vec<usize>, N>>,
     /// compatible[y][x][pattern][dir] contains the number of distincts patterns
     /// in the wave that can be placed in the cell at direction dir of (y,x), without
     /// being in contradiction with pattern placed in (y,x). If wave[y][x][pattern]
     /// is false, then compatible[y][x][pattern] has every element negative or null.
-    compatible: Vec3D<DirArray<isize>>,
+    compatible: Vec3D<DirArray<isize, N>>,
     /// The set of tuples (y, x, pattern) that should be propagated.
     /// Such a tuple should be propagated if wave[y][x][pattern] is set to false.
     propagating_queue: Vec<(usize, usize, usize)>,
+    /// The changes of compatible done since the trail was enabled, with the
+    /// cell (y, x) and the pattern changed. None if they are not recorded.
+    trail: Option<Vec<(usize, usize, usize, CompatibleChange<N>)>>,
+}
+
+/// A change of the compatible counts of a pattern in a cell, recorded so
+/// that it can be undone.
+#[derive(Clone, Copy, Debug)]
+enum CompatibleChange<const N: usize> {
+    /// The count in the direction of the given index was decremented.
+    Decremented(usize),
+    /// Every count was set to 0, and had the given values before.
+    Cleared(DirArray<isize, N>),
+}
+
+/// A position in the changes recorded by a propagator, see [Propagator::mark].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TrailMark {
+    /// The number of removals recorded by the wave.
+    wave: usize,
+    /// The number of changes of compatible recorded by the propagator.
+    compatible: usize,
+}
+
+/// A copy of the state of a propagator, that can be used to restore
+/// the propagator to a previous state.
+#[derive(Clone)]
+pub struct PropagatorSnapshot<const N: usize = 4> {
+    /// The saved wave.
+    wave: Wave,
+    /// The saved compatible counts.
+    compatible: Vec3D<DirArray<isize, N>>,
+    /// The saved propagating queue.
+    propagating_queue: Vec<(usize, usize, usize)>,
+}
+
+/// The state of a propagator, without the weights and the compatibilities
+/// of the patterns. It can be serialized, contrary to a PropagatorSnapshot.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PropagatorState<const N: usize = 4> {
+    /// The saved wave state.
+    wave: WaveState,
+    /// The saved compatible counts.
+    compatible: Vec3D<DirArray<isize, N>>,
+    /// The saved propagating queue.
+    propagating_queue: Vec<(usize, usize, usize)>,
+}
+
+impl<const N: usize> PropagatorState<N> {
+    /// Get the size of the saved wave, as (rows, cols, patterns).
+    pub fn size(&self) -> (usize, usize, usize) {
+        self.wave.size()
+    }
 }
 
 impl Propagator {
@@ -35,42 +96,174 @@ impl Propagator {
         patterns_compatibility: Vec<DirArray<Vec<usize>>>,
         is_toric: bool,
     ) -> Propagator {
+        let neighborhood = Neighborhood::new_2d(height, width);
+        Propagator::new_with_neighborhood(neighborhood, weights, patterns_compatibility, is_toric)
+    }
+}
+
+impl<const N: usize> Propagator<N> {
+    /// Create a new Propagator for a grid with the given neighborhood, given
+    /// the weights of the patterns, and the possible combinations of pair of
+    /// patterns in each direction of the neighborhood.
+    /// Panic if the neighborhood does not have N directions.
+    pub fn new_with_neighborhood(
+        neighborhood: Neighborhood,
+        weights: Vec<Real>,
+        patterns_compatibility: Vec<DirArray<Vec<usize>, N>>,
+        is_toric: bool,
+    ) -> Propagator<N> {
+        assert_eq!(neighborhood.n_directions(), N);
         let n_patterns = weights.len();
-        let wave = Wave::new(height, width, weights);
+        let wave = Wave::new_with_neighborhood(neighborhood, weights);
+        let opposites =
+            DirArray::new_generator_indexed(|direction| neighborhood.opposite(direction));
 
-        let compatible = Vec3D::new_generator(height, width, n_patterns, |_, _, pattern| {
-            DirArray::new_generator(|direction| {
-                patterns_compatibility[pattern][direction.opposite()].len() as isize
-            })
-        });
+        let compatible = Vec3D::new_generator(
+            neighborhood.rows(),
+            neighborhood.cols(),
+            n_patterns,
+            |_, _, pattern| initial_compatible(&patterns_compatibility[pattern], &opposites),
+        );
 
         Propagator {
             wave,
             is_toric,
+            neighborhood,
+            opposites,
             patterns_compatibility,
             compatible,
             propagating_queue: vec![],
+            trail: None,
         }
     }
 
     /// Reset the propagator by setting every element in the wave to true.
+    /// The recorded changes are dropped, see [Propagator::mark].
     pub fn reset(&mut self) {
         self.wave.reset();
+        if let Some(trail) = &mut self.trail {
+            trail.clear();
+        }
 
         //let patterns_compatibility = &mut self.patterns_compatibility;
-        let height = self.wave().height();
-        let width = self.wave().width();
+        let height = self.wave().rows();
+        let width = self.wave().cols();
         let compatible = &mut self.compatible;
         let patterns_compatibility = &self.patterns_compatibility;
         for i in 0..height {
             for j in 0..width {
                 for (pattern, val) in compatible[(i, j)].iter_mut().enumerate() {
-                    *val = DirArray::new_generator(|direction| {
-                        patterns_compatibility[pattern][direction.opposite()].len() as isize
-                    });
+                    *val = initial_compatible(&patterns_compatibility[pattern], &self.opposites);
+                }
+            }
+        }
+    }
+
+    /// Save the current state of the propagator.
+    pub fn snapshot(&self) -> PropagatorSnapshot<N> {
+        PropagatorSnapshot {
+            wave: self.wave.clone(),
+            compatible: self.compatible.clone(),
+            propagating_queue: self.propagating_queue.clone(),
+        }
+    }
+
+    /// Restore the propagator to a previously saved state.
+    pub fn restore(&mut self, snapshot: &PropagatorSnapshot<N>) {
+        self.wave.clone_from(&snapshot.wave);
+        self.compatible.clone_from(&snapshot.compatible);
+        self.propagating_queue
+            .clone_from(&snapshot.propagating_queue);
+    }
+
+    /// Start recording the changes done to the propagator, and return a mark
+    /// that can be given to [Propagator::undo] to revert the changes done
+    /// after it. Contrary to a snapshot, only the changes are stored, so a mark
+    /// can be taken at each step of a search.
+    pub fn mark(&mut self) -> TrailMark {
+        if self.trail.is_none() {
+            self.trail = Some(vec![]);
+            self.wave.set_trailing(true);
+        }
+        TrailMark {
+            wave: self.wave.trail_len(),
+            compatible: self.trail.as_ref().map_or(0, Vec::len),
+        }
+    }
+
+    /// Revert the changes done since mark was returned by [Propagator::mark].
+    pub fn undo(&mut self, mark: TrailMark) {
+        self.wave.undo(mark.wave);
+        if let Some(trail) = &mut self.trail {
+            while trail.len() > mark.compatible {
+                let (y, x, pattern, change) = trail.pop().unwrap();
+                let value = self.compatible.get_mut(y, x, pattern);
+                match change {
+                    CompatibleChange::Decremented(direction) => *value.get_mut(direction) += 1,
+                    CompatibleChange::Cleared(previous) => *value = previous,
                 }
             }
         }
+        self.propagating_queue.clear();
+    }
+
+    /// Stop recording the changes done to the propagator, and drop the
+    /// recorded ones. The marks taken before can no longer be used.
+    pub fn clear_trail(&mut self) {
+        self.trail = None;
+        self.wave.set_trailing(false);
+    }
+
+    /// Save the state of the propagator, without the weights and the
+    /// compatibilities of the patterns.
+    pub fn state(&self) -> PropagatorState<N> {
+        PropagatorState {
+            wave: self.wave.state(),
+            compatible: self.compatible.clone(),
+            propagating_queue: self.propagating_queue.clone(),
+        }
+    }
+
+    /// Restore the propagator to a saved state. The weights and compatibilities
+    /// of the patterns are kept.
+    /// Panic if the state does not have the dimensions of the propagator.
+    pub fn set_state(&mut self, state: PropagatorState<N>) {
+        let weights = self.wave.weights().to_vec();
+        let wave = Wave::from_state_with_neighborhood(state.wave, self.neighborhood, weights);
+        assert_eq!(
+            (wave.rows(), wave.cols()),
+            (self.wave.rows(), self.wave.cols())
+        );
+        self.wave = wave;
+        self.compatible = state.compatible;
+        self.propagating_queue = state.propagating_queue;
+        self.trail = None;
+    }
+
+    /// Change the weight of a pattern in the wave.
+    /// The recorded changes are dropped, see [Propagator::mark].
+    pub fn set_weight(&mut self, pattern: usize, weight: Real) {
+        self.wave.set_weight(pattern, weight);
+        if let Some(trail) = &mut self.trail {
+            trail.clear();
+        }
+    }
+
+    /// Get the compatibilities of the patterns: patterns_compatibility()[pattern1][dir]
+    /// contains the patterns that can be placed in direction dir of pattern1.
+    pub fn patterns_compatibility(&self) -> &[DirArray<Vec<usize>, N>] {
+        &self.patterns_compatibility
+    }
+
+    /// Return true if the wave is toric.
+    pub fn is_toric(&self) -> bool {
+        self.is_toric
+    }
+
+    /// Set if the wave is toric. The propagator should then be reset, since
+    /// the compatible counts of the cells near the borders depend on it.
+    pub fn set_toric(&mut self, is_toric: bool) {
+        self.is_toric = is_toric;
     }
 
     /// Return a reference to the owned wave
@@ -81,11 +274,35 @@ impl Propagator {
     /// Remove pattern from the wave on cell (i, j).
     /// This means that pattern cannot be placed in cell (i, j).
     pub fn unset(&mut self, y: usize, x: usize, pattern: usize) {
+        self.remove(y, x, pattern);
+        self.propagate();
+    }
+
+    /// Remove every pattern but pattern from the wave on cell (y, x), and
+    /// propagate the information.
+    /// Return WaveError::Impossible if the wave contains a contradiction
+    /// afterwards, which is the case if pattern could not be placed in (y, x).
+    pub fn collapse(&mut self, y: usize, x: usize, pattern: usize) -> Result<(), WaveError> {
+        for other in 0..self.patterns_compatibility.len() {
+            if other != pattern {
+                self.remove(y, x, other);
+            }
+        }
+        self.propagate();
+        self.wave.check_contradiction()
+    }
+
+    /// Remove pattern from the wave on cell (y, x), and queue the information
+    /// so it is propagated by the next call to propagate.
+    fn remove(&mut self, y: usize, x: usize, pattern: usize) {
         if self.wave.get(y, x, pattern) {
             self.wave.unset(y, x, pattern);
-            *self.compatible.get_mut(y, x, pattern) = DirArray::new(&0);
+            let value = self.compatible.get_mut(y, x, pattern);
+            if let Some(trail) = &mut self.trail {
+                trail.push((y, x, pattern, CompatibleChange::Cleared(*value)));
+            }
+            *value = DirArray::new_generator_indexed(|_| 0);
             self.propagating_queue.push((y, x, pattern));
-            self.propagate();
         }
     }
 
@@ -94,43 +311,41 @@ impl Propagator {
         // We propagate as long as we have things to propagate.
         // (y1, x1) is the cell where pattern was set to false in the wave.
         while let Some((y1, x1, pattern)) = self.propagating_queue.pop() {
-            for direction in &Direction::directions() {
-                let (dy, dx) = direction.get_coordinates();
-
+            for (direction, compatible_patterns) in
+                self.patterns_compatibility[pattern].into_iter().enumerate()
+            {
                 // The coordinate of a neighboring cell
-                let (y2, x2) = if self.is_toric {
-                    (
-                        (y1 as isize + dy + self.wave.height() as isize) as usize
-                            % self.wave.height(),
-                        (x1 as isize + dx + self.wave.width() as isize) as usize
-                            % self.wave.width(),
-                    )
-                } else {
-                    let (y2, x2) = (y1 as isize + dy, x1 as isize + dx);
-                    if x2 < 0 || x2 >= self.wave.width() as isize {
-                        continue;
-                    }
-                    if y2 < 0 || y2 >= self.wave.height() as isize {
-                        continue;
-                    }
-                    (y2 as usize, x2 as usize)
+                let (y2, x2) = match self
+                    .neighborhood
+                    .neighbor((y1, x1), direction, self.is_toric)
+                {
+                    Some(cell) => cell,
+                    None => continue,
                 };
 
                 // We iterate on every pattern that could be placed in the (y2, x2) cell,
                 // without being in contradiction with pattern in (y1, x1)
-                for &pattern2 in &self.patterns_compatibility[pattern][*direction] {
+                for &pattern2 in compatible_patterns {
                     // We decrease the number of compatible patterns in the opposite
                     // direction. If the pattern was discarded from the wave, the element is
                     // negative.
                     let value = self.compatible.get_mut(y2, x2, pattern2);
-                    value[*direction] -= 1;
+                    let count = value.get_mut(direction);
+                    *count -= 1;
+                    let count = *count;
+                    if let Some(trail) = &mut self.trail {
+                        trail.push((y2, x2, pattern2, CompatibleChange::Decremented(direction)));
+                    }
 
                     // If the elemnt was set to 0 with this operation, we need to remove the
                     // pattern from the wave, and propagate the newly acquired information.
-                    if value[*direction] == 0 {
+                    if count == 0 {
                         // We can't call self.unset here, because self is already borrowed.
                         self.wave.unset(y2, x2, pattern2);
-                        *value = DirArray::new(&0);
+                        if let Some(trail) = &mut self.trail {
+                            trail.push((y2, x2, pattern2, CompatibleChange::Cleared(*value)));
+                        }
+                        *value = DirArray::new_generator_indexed(|_| 0);
                         self.propagating_queue.push((y2, x2, pattern2));
                     }
                 }
@@ -138,3 +353,164 @@ impl Propagator {
         }
     }
 }
+
+/// Get the initial compatible counts of a pattern, given the patterns that can
+/// be placed in each direction of it, and the opposite of each direction.
+fn initial_compatible<const N: usize>(
+    compatibility: &DirArray<Vec<usize>, N>,
+    opposites: &DirArray<usize, N>,
+) -> DirArray<isize, N> {
+    DirArray::new_generator_indexed(|direction| {
+        compatibility.get(*opposites.get(direction)).len() as isize
+    })
+}
+
+/// Get the patterns that cannot have any neighbor in some direction, given
+/// patterns_compatibility[pattern1][dir], the patterns that can be placed in
+/// direction dir of pattern1. These patterns can only be placed on the border
+/// of a non toric output.
+/// The result is sorted by pattern, and then by direction.
+pub fn patterns_without_neighbors(
+    patterns_compatibility: &[DirArray<Vec<usize>>],
+) -> Vec<(usize, Direction)> {
+    patterns_compatibility
+        .iter()
+        .enumerate()
+        .flat_map(|(pattern, compatible)| {
+            compatible
+                .iter_directions()
+                .filter(|(_, patterns)| patterns.is_empty())
+                .map(move |(direction, _)| (pattern, direction))
+        })
+        .collect()
+}
+
+/// Get the patterns that can never be placed in an infinite grid, given
+/// patterns_compatibility[pattern1][dir], the patterns that can be placed in
+/// direction dir of pattern1.
+/// A pattern is dead if, in some direction, every pattern that can be placed
+/// next to it is dead. The dead patterns are returned in the order in which
+/// they are found, each with the direction in which it has no living neighbor.
+pub fn dead_patterns(patterns_compatibility: &[DirArray<Vec<usize>>]) -> Vec<(usize, Direction)> {
+    let mut alive = vec![true; patterns_compatibility.len()];
+    let mut dead = vec![];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (pattern, compatible) in patterns_compatibility.iter().enumerate() {
+            if !alive[pattern] {
+                continue;
+            }
+            let dead_direction = compatible
+                .iter_directions()
+                .find(|(_, patterns)| !patterns.iter().any(|&p| alive[p]));
+            if let Some((direction, _)) = dead_direction {
+                alive[pattern] = false;
+                dead.push((pattern, direction));
+                changed = true;
+            }
+        }
+    }
+    dead
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_set_toric() {
+        // Pattern 0 can only be placed next to pattern 1.
+        let compatibility = vec![
+            DirArray::new(&vec![1]),
+            DirArray::new(&vec![0, 1, 2]),
+            DirArray::new(&vec![1, 2]),
+        ];
+        let weights = vec![1.0, 1.0, 1.0];
+        let mut propagator = Propagator::new(4, 4, weights, compatibility, false);
+        assert!(!propagator.is_toric());
+        propagator.unset(0, 0, 1);
+        let non_toric = *propagator.compatible.get(3, 0, 0);
+        assert!(propagator.wave().get(3, 0, 0));
+
+        propagator.set_toric(true);
+        assert!(propagator.is_toric());
+        propagator.reset();
+        propagator.unset(0, 0, 1);
+        let toric = *propagator.compatible.get(3, 0, 0);
+        assert!(!propagator.wave().get(3, 0, 0));
+        assert_ne!(non_toric, toric);
+    }
+
+    #[test]
+    fn test_collapse() {
+        // Pattern 0 and 1 have to alternate like a checkerboard.
+        let compatibility = vec![DirArray::new(&vec![1]), DirArray::new(&vec![0])];
+        let mut propagator = Propagator::new(2, 3, vec![1.0, 1.0], compatibility, false);
+        assert!(propagator.collapse(0, 0, 0).is_ok());
+        assert!(propagator.wave().get(1, 2, 1));
+        assert!(!propagator.wave().get(1, 2, 0));
+
+        assert!(!propagator.wave().get(0, 1, 0));
+        assert!(matches!(
+            propagator.collapse(0, 1, 0),
+            Err(WaveError::Impossible { y: 0, x: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_undo() {
+        // Pattern 0 and 1 have to alternate like a checkerboard.
+        let compatibility = vec![DirArray::new(&vec![1]), DirArray::new(&vec![0])];
+        let mut propagator = Propagator::new(3, 3, vec![1.0, 2.0], compatibility, false);
+        let initial = propagator.state();
+        let mark = propagator.mark();
+        assert!(propagator.collapse(0, 0, 0).is_ok());
+        assert_eq!(propagator.wave().decided_counts(), &[5, 4]);
+        let collapsed = propagator.state();
+        let second_mark = propagator.mark();
+        propagator.unset(1, 1, 0);
+        assert!(propagator.wave().has_contradiction());
+
+        propagator.undo(second_mark);
+        assert_eq!(propagator.compatible, collapsed.compatible);
+        assert!(!propagator.wave().has_contradiction());
+        assert_eq!(propagator.wave().collapsed_cells(), 9);
+        assert_eq!(propagator.wave().decided_counts(), &[5, 4]);
+        propagator.undo(mark);
+        assert_eq!(propagator.compatible, initial.compatible);
+        assert_eq!(propagator.wave().collapsed_cells(), 0);
+        assert_eq!(propagator.wave().decided_counts(), &[0, 0]);
+        let wave = Wave::from_state(initial.wave, vec![1.0, 2.0]);
+        for y in 0..3 {
+            for x in 0..3 {
+                assert_eq!(propagator.wave().possibilities(y, x), 2);
+                assert_eq!(propagator.wave().get_entropy(y, x), wave.get_entropy(y, x));
+                assert!(!propagator.wave().is_on_frontier(y, x));
+            }
+        }
+    }
+
+    #[test]
+    fn test_dead_patterns() {
+        // Pattern 2 has no neighbors, and pattern 1 can only have pattern 2
+        // on its right, so pattern 0 only has dead neighbors.
+        let mut compatibility = vec![
+            DirArray::new(&vec![1]),
+            DirArray::new(&vec![0, 1]),
+            DirArray::new(&vec![]),
+        ];
+        compatibility[1][Direction::Right] = vec![2];
+        assert_eq!(
+            dead_patterns(&compatibility),
+            vec![
+                (2, Direction::Down),
+                (1, Direction::Right),
+                (0, Direction::Down)
+            ]
+        );
+
+        compatibility[1][Direction::Right] = vec![0, 1];
+        assert_eq!(dead_patterns(&compatibility), vec![(2, Direction::Down)]);
+    }
+}
@@ -62,9 +62,7 @@ pub fn generate_action_map(symmetry: Symmetry) -> Vec<Vec<usize>> {
     let size = rotation_map.len();
     let mut action_map = vec![vec![0; size]; 8];
 
-    for i in 0..size {
-        action_map[0][i] = i;
-    }
+    action_map[0] = (0..size).collect();
 
     for a in 1..4 {
         for i in 0..size {
@@ -85,6 +83,51 @@ pub fn generate_action_map(symmetry: Symmetry) -> Vec<Vec<usize>> {
     action_map
 }
 
+/// The direction in which the orientations of a tile are numbered.
+/// With Anticlockwise, orientation i is obtained by i rotations of 90°
+/// anticlockwise, and with Clockwise by i rotations of 90° clockwise.
+/// The reflected orientations of the P symmetry follow the same rule, starting
+/// from orientation 4.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RotationSense {
+    Anticlockwise,
+    Clockwise,
+}
+
+/// Generate the permutation associating a clockwise orientation to the
+/// anticlockwise orientation representing the same object.
+/// The permutation is its own inverse.
+fn generate_clockwise_map(symmetry: Symmetry) -> Vec<usize> {
+    match symmetry {
+        Symmetry::X => vec![0],
+        Symmetry::I | Symmetry::Backslash => vec![0, 1],
+        Symmetry::T | Symmetry::L => vec![0, 3, 2, 1],
+        Symmetry::P => vec![0, 3, 2, 1, 4, 7, 6, 5],
+    }
+}
+
+/// Generate the map associating an orientation and an action to the resulting
+/// orientation, when the orientations are numbered in the given rotation sense.
+/// The actions are the same as in [generate_action_map].
+pub fn generate_action_map_with_sense(symmetry: Symmetry, sense: RotationSense) -> Vec<Vec<usize>> {
+    let action_map = generate_action_map(symmetry);
+    match sense {
+        RotationSense::Anticlockwise => action_map,
+        RotationSense::Clockwise => {
+            let clockwise_map = generate_clockwise_map(symmetry);
+            action_map
+                .iter()
+                .map(|orientations| {
+                    clockwise_map
+                        .iter()
+                        .map(|&i| clockwise_map[orientations[i]])
+                        .collect()
+                })
+                .collect()
+        }
+    }
+}
+
 /// Generate all distincts orientations of a 2D array given its symmetry type.
 pub fn generate_oriented<T>(data: Vec2D<T>, symmetry: Symmetry) -> Vec<Vec2D<T>>
 where
@@ -96,36 +139,26 @@ where
             let rotated = data.rotated();
             vec![data, rotated]
         }
-        Symmetry::T | Symmetry::L => {
-            let mut oriented = vec![data];
-            for _ in 0..3 {
-                oriented.push(oriented.last().unwrap().rotated())
-            }
-            oriented
-        }
+        Symmetry::T | Symmetry::L => (0..4).map(|turns| data.rotated_by(turns)).collect(),
         Symmetry::P => {
-            let mut oriented = vec![data];
-            for _ in 0..3 {
-                oriented.push(oriented.last().unwrap().rotated())
-            }
-
-            oriented.push(oriented.last().unwrap().reflected());
-            for _ in 0..3 {
-                oriented.push(oriented.last().unwrap().rotated())
-            }
-            oriented
+            let reflected = data.rotated_by(3).reflected();
+            (0..4)
+                .map(|turns| data.rotated_by(turns))
+                .chain((0..4).map(|turns| reflected.rotated_by(turns)))
+                .collect()
         }
     }
 }
 
 /// 2D Objects that are reflections and rotations of themselves.
-/// Item i is obtained by doing action i on item 0.
-/// See [generate_action_map] to see what actions do.
+/// Item i is obtained by doing action i on item 0, when the orientations are
+/// numbered anticlockwise. See [generate_action_map] to see what actions do.
 #[derive(Clone)]
 pub struct Tile<T> {
     data: Vec<Vec2D<T>>,
     symmetry: Symmetry,
     weight: Real,
+    rotation_sense: RotationSense,
 }
 
 impl<T> Tile<T> {
@@ -134,11 +167,46 @@ impl<T> Tile<T> {
     where
         T: Clone,
     {
-        let oriented_data = generate_oriented(data, symmetry);
+        Tile::new_with_rotation_sense(data, symmetry, weight, RotationSense::Anticlockwise)
+    }
+
+    /// Create a new tile given a Vec2D representing an object, where the
+    /// orientations are numbered in the given rotation sense.
+    pub fn new_with_rotation_sense(
+        data: Vec2D<T>,
+        symmetry: Symmetry,
+        weight: Real,
+        rotation_sense: RotationSense,
+    ) -> Tile<T>
+    where
+        T: Clone,
+    {
+        let mut oriented_data = generate_oriented(data, symmetry);
+        if rotation_sense == RotationSense::Clockwise {
+            // The permutation is its own inverse, so it is made of swaps.
+            for (i, &j) in generate_clockwise_map(symmetry).iter().enumerate() {
+                if i < j {
+                    oriented_data.swap(i, j);
+                }
+            }
+        }
         Tile {
             data: oriented_data,
             symmetry,
             weight,
+            rotation_sense,
+        }
+    }
+
+    /// Create a new tile given the Vec2D representing each orientation of the object.
+    /// Item i should be obtained by doing action i on item 0.
+    pub fn from_oriented(data: Vec<Vec2D<T>>, symmetry: Symmetry, weight: Real) -> Tile<T> {
+        assert_eq!(data.len(), symmetry.nb_of_possible_orientations());
+        Tile {
+            data,
+            symmetry,
+            weight,
+            rotation_sense: RotationSense::Anticlockwise,
         }
     }
 
@@ -152,10 +220,29 @@ impl<T> Tile<T> {
         self.symmetry
     }
 
+    /// Get the sense in which the orientations of the tile are numbered.
+    pub fn rotation_sense(&self) -> RotationSense {
+        self.rotation_sense
+    }
+
+    /// Get the map associating an orientation and an action to the resulting
+    /// orientation, see [generate_action_map_with_sense].
+    pub fn action_map(&self) -> Vec<Vec<usize>> {
+        generate_action_map_with_sense(self.symmetry, self.rotation_sense)
+    }
+
     /// Get the weight of the tile
     pub fn weight(&self) -> Real {
         self.weight
     }
+
+    /// Set the weight of the tile.
+    /// The weight is shared between the orientations of the tile when the
+    /// tiling WFC is created.
+    pub fn set_weight(&mut self, weight: Real) {
+        assert!(weight >= 0.0, "tile weights should be non-negative");
+        self.weight = weight;
+    }
 }
 
 #[cfg(test)]
@@ -172,4 +259,23 @@ mod test {
         assert_eq!(oriented_data[2], Vec2D::from_vec(vec![2, 1], 1, 2));
         assert_eq!(oriented_data[3], Vec2D::from_vec(vec![1, 2], 2, 1));
     }
+
+    #[test]
+    fn test_clockwise_orientations() {
+        let data = Vec2D::from_vec(vec![1, 2], 1, 2);
+        let tile = Tile::new_with_rotation_sense(data, Symmetry::T, 1.0, RotationSense::Clockwise);
+        let oriented_data = tile.data();
+
+        assert_eq!(oriented_data[0], Vec2D::from_vec(vec![1, 2], 1, 2));
+        assert_eq!(oriented_data[1], Vec2D::from_vec(vec![1, 2], 2, 1));
+        assert_eq!(oriented_data[2], Vec2D::from_vec(vec![2, 1], 1, 2));
+        assert_eq!(oriented_data[3], Vec2D::from_vec(vec![2, 1], 2, 1));
+
+        // A rotation of 90° anticlockwise undoes a clockwise orientation.
+        let action_map = tile.action_map();
+        assert_eq!(action_map[1], vec![3, 0, 1, 2]);
+        for (orientation, data) in oriented_data.iter().enumerate() {
+            assert_eq!(oriented_data[action_map[1][orientation]], data.rotated());
+        }
+    }
 }
@@ -1,14 +1,44 @@
 //! A WFC algorithm for tiling problems.
 
 use crate::direction::*;
+use crate::model::WfcModel;
+use crate::propagator::dead_patterns;
 use crate::tile::*;
 use crate::utils::vec2d::*;
+use crate::wave::{Heuristic, WaveError};
 use crate::wfc::*;
 use crate::Real;
 
+#[cfg(feature = "xml")]
+pub mod xml;
+
 /// Options passed to the tiling WFC.
 pub struct TilingWFCOptions {
-    is_periodic: bool,
+    pub is_periodic: bool,
+}
+
+/// An invalid tile, or an invalid entry in the neighbors list given to
+/// TilingWFC. index is the position of the entry in the neighbors list.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TilingError {
+    /// The entry refers to a tile that does not exist.
+    UnknownTile { index: usize, tile: usize },
+    /// The entry refers to an orientation that the tile does not have.
+    UnknownOrientation {
+        index: usize,
+        tile: usize,
+        orientation: usize,
+    },
+    /// An orientation of the tile is not a square of the same size as the
+    /// first orientation of the first tile.
+    TileSizeMismatch { tile: usize, orientation: usize },
+}
+
+/// The reason why a set of tiles and neighbors cannot tile an infinite grid.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InfeasibleReason {
+    /// The oriented tile can never have a valid neighbor in the direction.
+    DeadTile { tile: usize, direction: Direction },
 }
 
 /// The data needed for the WFc algorithm
@@ -17,12 +47,19 @@ pub struct TilingWFC<T> {
     tiles: Vec<Tile<T>>,
     /// Associate an oriented tile id to its tile number, and its orientation
     id_to_oriented_tiles: Vec<(usize, usize)>,
+    /// oriented_tile_ids[tile][orientation] is the oriented tile id of the tile
+    /// with the given orientation
+    oriented_tile_ids: Vec<Vec<usize>>,
     /// The underlying WFC problem
     wfc: WFC,
 }
 
 impl<T: Copy> TilingWFC<T> {
-    /// Create a new structure for a tiling WFC problem
+    /// Create a new structure for a tiling WFC problem.
+    /// Each neighbor entry is [tile1, orientation1, tile2, orientation2], and
+    /// an error is returned if one of them does not exist.
+    /// Every tile should be a square of the same size, tiles of different
+    /// sizes are not supported.
     pub fn new(
         tiles: Vec<Tile<T>>,
         neighbors: &[[usize; 4]],
@@ -30,7 +67,9 @@ impl<T: Copy> TilingWFC<T> {
         width: usize,
         options: TilingWFCOptions,
         seed: [u8; 16],
-    ) -> TilingWFC<T> {
+    ) -> Result<TilingWFC<T>, TilingError> {
+        validate_tiles(&tiles)?;
+        validate_neighbors(neighbors, &tiles)?;
         let (id_to_oriented_tiles, oriented_tile_ids) = generate_oriented_tile_ids(&tiles);
         let propagator =
             generate_propagator(neighbors, &tiles, &id_to_oriented_tiles, &oriented_tile_ids);
@@ -43,16 +82,58 @@ impl<T: Copy> TilingWFC<T> {
             width,
         );
 
-        TilingWFC {
+        Ok(TilingWFC {
             tiles,
             id_to_oriented_tiles,
+            oriented_tile_ids,
             wfc,
-        }
+        })
+    }
+
+    /// Create a new structure for a tiling WFC problem, using a u64 seed.
+    pub fn from_u64_seed(
+        tiles: Vec<Tile<T>>,
+        neighbors: &[[usize; 4]],
+        height: usize,
+        width: usize,
+        options: TilingWFCOptions,
+        seed: u64,
+    ) -> Result<TilingWFC<T>, TilingError> {
+        TilingWFC::new(
+            tiles,
+            neighbors,
+            height,
+            width,
+            options,
+            seed_from_u64(seed),
+        )
     }
 
-    /// Translate the generic WFC result into the concatenation of the tiles
-    fn id_to_tiling(&self, ids: Vec2D<usize>) -> Vec2D<T> {
-        let size = self.tiles[0].data()[0].height();
+    /// Create a new structure for a tiling WFC problem, using a seed drawn
+    /// from the thread random number generator.
+    /// The results are not reproducible, since the seed is different each time.
+    pub fn new_random(
+        tiles: Vec<Tile<T>>,
+        neighbors: &[[usize; 4]],
+        height: usize,
+        width: usize,
+        options: TilingWFCOptions,
+    ) -> Result<TilingWFC<T>, TilingError> {
+        TilingWFC::new(tiles, neighbors, height, width, options, random_seed())
+    }
+
+    /// Get the height and width of the tiles, which are all squares of the
+    /// same size.
+    pub fn tile_size(&self) -> usize {
+        self.tiles.first().map_or(0, |tile| tile.data()[0].height())
+    }
+
+    /// Translate the oriented tile ids chosen in each cell, as returned by
+    /// run_ids, into the concatenation of the tiles, in row-major order.
+    /// The tiling of the transposed ids can be obtained by transposing ids
+    /// before calling this function.
+    pub fn id_to_tiling(&self, ids: &Vec2D<usize>) -> Vec2D<T> {
+        let size = self.tile_size();
         let mut tiling = Vec2D::new(
             size * ids.height(),
             size * ids.width(),
@@ -76,13 +157,229 @@ impl<T: Copy> TilingWFC<T> {
 
     /// Run the wfc algorithm
     pub fn run(&mut self) -> Option<Vec2D<T>> {
-        self.wfc.run().map(|x| self.id_to_tiling(x))
+        self.run_with_progress(|_| ())
+    }
+
+    /// Run the wfc algorithm, and call progress after each step.
+    pub fn run_with_progress<F: FnMut(ProgressInfo)>(&mut self, progress: F) -> Option<Vec2D<T>> {
+        self.wfc
+            .run_with_progress(progress)
+            .map(|x| self.id_to_tiling(&x))
+    }
+
+    /// Run the wfc algorithm, and return the oriented tile id chosen in each cell.
+    /// See [TilingWFC::id_to_oriented_tiles] to get the tile and orientation
+    /// associated with an id.
+    pub fn run_ids(&mut self) -> Option<Vec2D<usize>> {
+        self.wfc.run()
+    }
+
+    /// Get the problem tiles.
+    pub fn tiles(&self) -> &[Tile<T>] {
+        &self.tiles
+    }
+
+    /// Get the tile number and the orientation associated with each oriented tile id.
+    pub fn id_to_oriented_tiles(&self) -> &[(usize, usize)] {
+        &self.id_to_oriented_tiles
+    }
+
+    /// Get the oriented tile id of a tile with the given orientation, or None
+    /// if the tile or the orientation does not exist.
+    pub fn oriented_tile_id(&self, tile: usize, orientation: usize) -> Option<usize> {
+        self.oriented_tile_ids.get(tile)?.get(orientation).copied()
+    }
+
+    /// Force the tile with the given orientation in the cell (y, x), and
+    /// propagate the information. This should be called before run, or after
+    /// restart.
+    /// Return WaveError::Impossible if this leads to a contradiction.
+    /// Panic if the tile or the orientation does not exist.
+    pub fn set_tile(
+        &mut self,
+        y: usize,
+        x: usize,
+        tile: usize,
+        orientation: usize,
+    ) -> Result<(), WaveError> {
+        let id = self.oriented_tile_ids[tile][orientation];
+        self.wfc.observe(y, x, id)
+    }
+
+    /// Check that every pair of adjacent oriented tile ids in ids is allowed
+    /// by the neighbors rules, without running the algorithm.
+    /// Return the first cell and direction where the neighbor is not allowed.
+    /// Panic if ids contains an invalid oriented tile id.
+    pub fn validate(&self, ids: &Vec2D<usize>) -> Result<(), (usize, usize, Direction)> {
+        let adjacency = self.wfc.adjacency();
+        let (height, width) = (ids.height() as isize, ids.width() as isize);
+        for ((y, x), &id) in ids.iter_enumerate() {
+            for &direction in &Direction::directions() {
+                let (dy, dx) = direction.get_coordinates();
+                let (mut y2, mut x2) = (y as isize + dy, x as isize + dx);
+                if self.wfc.is_toric() {
+                    y2 = (y2 + height) % height;
+                    x2 = (x2 + width) % width;
+                } else if y2 < 0 || y2 >= height || x2 < 0 || x2 >= width {
+                    continue;
+                }
+                if !adjacency[id][direction].contains(&ids[y2 as usize][x2 as usize]) {
+                    return Err((y, x, direction));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the compatibilities of the oriented tiles: adjacency()[tile1][dir]
+    /// contains the oriented tiles that can be placed in direction dir of tile1.
+    /// The oriented tiles are described by id_to_oriented_tiles.
+    pub fn adjacency(&self) -> &[DirArray<Vec<usize>>] {
+        self.wfc.adjacency()
+    }
+
+    /// Check that every oriented tile can be placed in an infinite grid.
+    /// Return the first oriented tile found that can never have a valid
+    /// neighbor in some direction, either because no tile can be placed in
+    /// that direction, or because the tiles that could are themselves dead.
+    /// The oriented tiles are described by id_to_oriented_tiles.
+    pub fn check_feasible(&self) -> Result<(), InfeasibleReason> {
+        match dead_patterns(self.adjacency()).first() {
+            Some(&(tile, direction)) => Err(InfeasibleReason::DeadTile { tile, direction }),
+            None => Ok(()),
+        }
     }
 
     /// Reset the WFC algorithm
     pub fn restart(&mut self, seed: [u8; 16]) {
         self.wfc.restart(seed);
     }
+
+    /// Set the heuristic used to choose the next cell to collapse.
+    /// The heuristic is kept when restarting.
+    pub fn set_heuristic(&mut self, heuristic: Heuristic) {
+        self.wfc.set_heuristic(heuristic);
+    }
+
+    /// Reset the WFC algorithm, using a u64 seed.
+    pub fn restart_u64(&mut self, seed: u64) {
+        self.wfc.restart_u64(seed);
+    }
+
+    /// Scale the weights of the oriented tiles so that they sum to 1.
+    /// The probabilities of the tiles and the entropies are the same up to
+    /// rounding errors, since they do not depend on the scale of the weights,
+    /// but the sums computed for the entropies keep a magnitude close to 1,
+    /// which is more precise with f32 when the weights are large.
+    /// The scaled weights are kept after a restart.
+    pub fn normalize_weights(&mut self) {
+        let mut weights = self.wfc.wave().weights().to_vec();
+        normalize_weights(&mut weights);
+        for (id, weight) in weights.into_iter().enumerate() {
+            self.wfc.set_pattern_weight(id, weight);
+        }
+    }
+}
+
+impl<T: Copy> WfcModel<T> for TilingWFC<T> {
+    fn run(&mut self) -> Option<Vec2D<T>> {
+        TilingWFC::run(self)
+    }
+
+    fn restart(&mut self, seed: [u8; 16]) {
+        TilingWFC::restart(self, seed)
+    }
+
+    fn height(&self) -> usize {
+        self.wfc.wave().rows() * self.tile_size()
+    }
+
+    fn width(&self) -> usize {
+        self.wfc.wave().cols() * self.tile_size()
+    }
+}
+
+/// Add to a neighbors list the reverse of each of its entries.
+/// The reverse of [tile1, orientation1, tile2, orientation2] is the same
+/// adjacency rotated by 180°, where tile1 is now on the right of tile2.
+/// The entries that are already present are not duplicated.
+/// The propagator of TilingWFC already adds the reverse of every entry, so
+/// this is only useful to get the complete list of rules explicitly.
+/// Return an error if an entry references an unknown tile or orientation.
+pub fn expand_symmetric<T>(
+    neighbors: &[[usize; 4]],
+    tiles: &[Tile<T>],
+) -> Result<Vec<[usize; 4]>, TilingError> {
+    validate_neighbors(neighbors, tiles)?;
+    let mut expanded = Vec::with_capacity(2 * neighbors.len());
+    for &[tile1, orientation1, tile2, orientation2] in neighbors {
+        let half_turn1 = tiles[tile1].action_map()[2][orientation1];
+        let half_turn2 = tiles[tile2].action_map()[2][orientation2];
+        for neighbor in [
+            [tile1, orientation1, tile2, orientation2],
+            [tile2, half_turn2, tile1, half_turn1],
+        ] {
+            if !expanded.contains(&neighbor) {
+                expanded.push(neighbor);
+            }
+        }
+    }
+    Ok(expanded)
+}
+
+/// Draw separator lines between the tiles of a tiling, as returned by run,
+/// where each tile is a square of size tile_size.
+/// A line is inserted between each pair of consecutive rows and columns of
+/// tiles, but not on the border of the tiling.
+pub fn draw_tile_grid<T: Clone>(tiling: &Vec2D<T>, tile_size: usize, separator: T) -> Vec2D<T> {
+    assert!(tile_size > 0);
+    let lines = |size: usize| size.saturating_sub(1) / tile_size;
+    let height = tiling.height() + lines(tiling.height());
+    let width = tiling.width() + lines(tiling.width());
+    let step = tile_size + 1;
+    Vec2D::from_generator(height, width, |i, j| {
+        if i % step == tile_size || j % step == tile_size {
+            separator.clone()
+        } else {
+            tiling[i - i / step][j - j / step].clone()
+        }
+    })
+}
+
+/// Check that every orientation of every tile is a square of the same size.
+fn validate_tiles<T>(tiles: &[Tile<T>]) -> Result<(), TilingError> {
+    let size = tiles.first().map_or(0, |tile| tile.data()[0].height());
+    for (tile_id, tile) in tiles.iter().enumerate() {
+        for (orientation, data) in tile.data().iter().enumerate() {
+            if data.height() != size || data.width() != size {
+                return Err(TilingError::TileSizeMismatch {
+                    tile: tile_id,
+                    orientation,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Check that every tile and orientation in the neighbors list exist.
+fn validate_neighbors<T>(neighbors: &[[usize; 4]], tiles: &[Tile<T>]) -> Result<(), TilingError> {
+    for (index, neighbor) in neighbors.iter().enumerate() {
+        for &(tile, orientation) in &[(neighbor[0], neighbor[1]), (neighbor[2], neighbor[3])] {
+            let nb_orientations = match tiles.get(tile) {
+                Some(tile) => tile.data().len(),
+                None => return Err(TilingError::UnknownTile { index, tile }),
+            };
+            if orientation >= nb_orientations {
+                return Err(TilingError::UnknownOrientation {
+                    index,
+                    tile,
+                    orientation,
+                });
+            }
+        }
+    }
+    Ok(())
 }
 
 /// Generate mapping from id to oriented tiles and vice versa.
@@ -90,8 +387,7 @@ fn generate_oriented_tile_ids<T>(tiles: &[Tile<T>]) -> (Vec<(usize, usize)>, Vec
     let id_to_oriented_tile = tiles
         .iter()
         .enumerate()
-        .map(|(i, tile)| (0..tile.data().len()).map(move |j| (i, j)))
-        .flatten()
+        .flat_map(|(i, tile)| (0..tile.data().len()).map(move |j| (i, j)))
         .collect();
 
     let mut id = 0;
@@ -124,8 +420,8 @@ fn generate_propagator<T>(
         let tile2 = neighbor[2];
         let orientation2 = neighbor[3];
 
-        let action_map1 = generate_action_map(tiles[tile1].symmetry());
-        let action_map2 = generate_action_map(tiles[tile2].symmetry());
+        let action_map1 = tiles[tile1].action_map();
+        let action_map2 = tiles[tile2].action_map();
 
         let mut add = |action: usize, direction| {
             let temp_orientation1 = action_map1[action][orientation1];
@@ -133,7 +429,7 @@ fn generate_propagator<T>(
             let oriented_tile_id1 = oriented_tile_ids[tile1][temp_orientation1];
             let oriented_tile_id2 = oriented_tile_ids[tile2][temp_orientation2];
             dense_propagator[oriented_tile_id1][direction][oriented_tile_id2] = true;
-            dense_propagator[oriented_tile_id2][direction.opposite()][oriented_tile_id2] = true;
+            dense_propagator[oriented_tile_id2][direction.opposite()][oriented_tile_id1] = true;
         };
 
         add(0, Direction::Right);
@@ -165,9 +461,309 @@ fn generate_propagator<T>(
 fn get_tiles_weights<T>(tiles: &[Tile<T>]) -> Vec<Real> {
     tiles
         .iter()
-        .map(|tile| {
-            std::iter::repeat(tile.weight() / (tile.data().len() as f32)).take(tile.data().len())
-        })
-        .flatten()
+        .flat_map(|tile| vec![tile.weight() / (tile.data().len() as Real); tile.data().len()])
         .collect()
 }
+
+/// Divide the weights by their sum, so that they sum to 1.
+/// The weights are unchanged if they sum to 0.
+fn normalize_weights(weights: &mut [Real]) {
+    let sum: Real = weights.iter().sum();
+    if sum > 0.0 {
+        for weight in weights {
+            *weight /= sum;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_generate_propagator_symmetric() {
+        let tiles = vec![
+            Tile::new(Vec2D::new(1, 1, &0), Symmetry::X, 1.0),
+            Tile::new(Vec2D::new(1, 1, &1), Symmetry::X, 1.0),
+        ];
+        let (id_to_oriented_tiles, oriented_tile_ids) = generate_oriented_tile_ids(&tiles);
+        let propagator = generate_propagator(
+            &[[0, 0, 1, 0]],
+            &tiles,
+            &id_to_oriented_tiles,
+            &oriented_tile_ids,
+        );
+
+        assert_eq!(propagator[0][Direction::Right], vec![1]);
+        assert_eq!(propagator[1][Direction::Left], vec![0]);
+        for &direction in &Direction::directions() {
+            assert_eq!(propagator[0][direction], vec![1]);
+            assert_eq!(propagator[1][direction], vec![0]);
+        }
+    }
+
+    #[test]
+    fn test_tiles_weights() {
+        let mut tiles = vec![
+            Tile::new(Vec2D::new(1, 2, &0), Symmetry::T, 1.0),
+            Tile::new(Vec2D::new(1, 1, &1), Symmetry::X, 1.0),
+        ];
+        tiles[0].set_weight(2.0);
+        tiles[1].set_weight(3.0);
+        assert_eq!(get_tiles_weights(&tiles), vec![0.5, 0.5, 0.5, 0.5, 3.0]);
+    }
+
+    #[test]
+    fn test_normalize_weights() {
+        let tiles = vec![
+            Tile::new(Vec2D::new(1, 1, &0), Symmetry::T, 200.0),
+            Tile::new(Vec2D::new(1, 1, &1), Symmetry::X, 100.0),
+        ];
+        let weights = get_tiles_weights(&tiles);
+        let mut normalized = weights.clone();
+        normalize_weights(&mut normalized);
+        assert!((normalized.iter().sum::<Real>() - 1.0).abs() < 1e-6);
+        for (weight, normalized_weight) in weights.iter().zip(&normalized) {
+            assert!((weight / weights[4] - normalized_weight / normalized[4]).abs() < 1e-6);
+        }
+
+        let mut zeros = vec![0.0; 3];
+        normalize_weights(&mut zeros);
+        assert_eq!(zeros, vec![0.0; 3]);
+
+        // The weights sum to a power of two, so the normalization is exact,
+        // and the same tiles are chosen with the same seed.
+        let run = |normalize: bool| {
+            let tiles = vec![
+                Tile::new(Vec2D::new(1, 1, &0), Symmetry::X, 4.0),
+                Tile::new(Vec2D::new(1, 1, &1), Symmetry::X, 12.0),
+            ];
+            let neighbors = [[0, 0, 0, 0], [0, 0, 1, 0], [1, 0, 1, 0]];
+            let options = TilingWFCOptions { is_periodic: false };
+            let mut wfc = TilingWFC::from_u64_seed(tiles, &neighbors, 8, 8, options, 0).unwrap();
+            if normalize {
+                wfc.normalize_weights();
+                assert_eq!(wfc.wfc.wave().weights(), &[0.25, 0.75]);
+            }
+            wfc.run().unwrap()
+        };
+        assert_eq!(run(true), run(false));
+    }
+
+    #[test]
+    fn test_validate_neighbors() {
+        let tiles = vec![
+            Tile::new(Vec2D::new(1, 1, &0), Symmetry::X, 1.0),
+            Tile::new(Vec2D::new(1, 1, &1), Symmetry::T, 1.0),
+        ];
+        let options = || TilingWFCOptions { is_periodic: false };
+        let new = |neighbors: &[[usize; 4]]| {
+            TilingWFC::from_u64_seed(tiles.clone(), neighbors, 2, 2, options(), 0).map(|_| ())
+        };
+
+        assert_eq!(new(&[[0, 0, 1, 3], [1, 2, 1, 0]]), Ok(()));
+        assert_eq!(
+            new(&[[0, 0, 1, 0], [0, 0, 2, 0]]),
+            Err(TilingError::UnknownTile { index: 1, tile: 2 })
+        );
+        assert_eq!(
+            new(&[[0, 1, 1, 0]]),
+            Err(TilingError::UnknownOrientation {
+                index: 0,
+                tile: 0,
+                orientation: 1
+            })
+        );
+        assert_eq!(
+            new(&[[0, 0, 1, 4]]),
+            Err(TilingError::UnknownOrientation {
+                index: 0,
+                tile: 1,
+                orientation: 4
+            })
+        );
+    }
+
+    #[test]
+    fn test_expand_symmetric() {
+        let tiles = vec![
+            Tile::new(Vec2D::new(1, 1, &0), Symmetry::X, 1.0),
+            Tile::new(Vec2D::new(1, 2, &1), Symmetry::T, 1.0),
+        ];
+        let neighbors = [[0, 0, 1, 1], [1, 0, 1, 2]];
+        let expanded = expand_symmetric(&neighbors, &tiles).unwrap();
+        assert_eq!(expanded, vec![[0, 0, 1, 1], [1, 3, 0, 0], [1, 0, 1, 2]]);
+        assert_eq!(
+            expand_symmetric(&[[0, 0, 2, 0]], &tiles),
+            Err(TilingError::UnknownTile { index: 0, tile: 2 })
+        );
+        assert_eq!(
+            expand_symmetric(&[[0, 1, 1, 0]], &tiles),
+            Err(TilingError::UnknownOrientation {
+                index: 0,
+                tile: 0,
+                orientation: 1
+            })
+        );
+
+        let (id_to_oriented_tiles, oriented_tile_ids) = generate_oriented_tile_ids(&tiles);
+        assert_eq!(
+            generate_propagator(
+                &neighbors,
+                &tiles,
+                &id_to_oriented_tiles,
+                &oriented_tile_ids
+            ),
+            generate_propagator(&expanded, &tiles, &id_to_oriented_tiles, &oriented_tile_ids)
+        );
+    }
+
+    #[test]
+    fn test_check_feasible() {
+        let tiles = vec![
+            Tile::new(Vec2D::new(1, 1, &0), Symmetry::X, 1.0),
+            Tile::new(Vec2D::new(1, 1, &1), Symmetry::X, 1.0),
+            Tile::new(Vec2D::new(1, 1, &2), Symmetry::X, 1.0),
+        ];
+        let options = || TilingWFCOptions { is_periodic: true };
+        let wfc =
+            TilingWFC::from_u64_seed(tiles.clone(), &[[0, 0, 1, 0]], 2, 2, options(), 0).unwrap();
+        assert_eq!(
+            wfc.check_feasible(),
+            Err(InfeasibleReason::DeadTile {
+                tile: 2,
+                direction: Direction::Down
+            })
+        );
+
+        let neighbors = [[0, 0, 1, 0], [2, 0, 2, 0]];
+        let wfc = TilingWFC::from_u64_seed(tiles, &neighbors, 2, 2, options(), 0).unwrap();
+        assert_eq!(wfc.check_feasible(), Ok(()));
+    }
+
+    #[test]
+    fn test_id_to_tiling() {
+        let tiles = vec![
+            Tile::new(Vec2D::from_vec(vec![0, 1, 2, 3], 2, 2), Symmetry::X, 1.0),
+            Tile::new(Vec2D::new(2, 2, &4), Symmetry::X, 1.0),
+        ];
+        let neighbors = [[0, 0, 0, 0], [0, 0, 1, 0], [1, 0, 1, 0]];
+        let options = TilingWFCOptions { is_periodic: false };
+        let mut wfc = TilingWFC::from_u64_seed(tiles, &neighbors, 2, 3, options, 0).unwrap();
+        let ids = Vec2D::from_vec(vec![0, 1, 1, 1, 0, 1], 2, 3);
+        #[rustfmt::skip]
+        let expected = Vec2D::from_vec(
+            vec![
+                0, 1, 4, 4, 4, 4,
+                2, 3, 4, 4, 4, 4,
+                4, 4, 0, 1, 4, 4,
+                4, 4, 2, 3, 4, 4,
+            ],
+            4,
+            6,
+        );
+        assert_eq!(wfc.id_to_tiling(&ids), expected);
+        assert_eq!(wfc.id_to_tiling(&ids.transposed()).height(), 6);
+
+        let output = wfc.run().unwrap();
+        assert_eq!((output.height(), output.width()), (4, 6));
+    }
+
+    #[test]
+    fn test_set_tile() {
+        // Tile 1 can only be next to tile 0.
+        let tiles = vec![
+            Tile::new(Vec2D::new(1, 1, &0), Symmetry::X, 1.0),
+            Tile::new(Vec2D::new(1, 1, &1), Symmetry::X, 1.0),
+        ];
+        let neighbors = [[0, 0, 0, 0], [0, 0, 1, 0]];
+        let options = TilingWFCOptions { is_periodic: false };
+        let mut wfc = TilingWFC::from_u64_seed(tiles, &neighbors, 3, 3, options, 0).unwrap();
+        assert_eq!(wfc.oriented_tile_id(1, 0), Some(1));
+        assert_eq!(wfc.oriented_tile_id(1, 1), None);
+        assert_eq!(wfc.oriented_tile_id(2, 0), None);
+
+        for seed in 0..5 {
+            wfc.restart_u64(seed);
+            wfc.set_tile(2, 2, 1, 0).unwrap();
+            let output = wfc.run().unwrap();
+            assert_eq!(output[2][2], 1);
+        }
+
+        wfc.restart_u64(0);
+        wfc.set_tile(0, 0, 1, 0).unwrap();
+        assert!(wfc.set_tile(0, 1, 1, 0).is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        // Tile 1 can only be next to tile 0.
+        let tiles = vec![
+            Tile::new(Vec2D::new(1, 1, &0), Symmetry::X, 1.0),
+            Tile::new(Vec2D::new(1, 1, &1), Symmetry::X, 1.0),
+        ];
+        let neighbors = [[0, 0, 0, 0], [0, 0, 1, 0]];
+        let options = TilingWFCOptions { is_periodic: false };
+        let wfc = TilingWFC::from_u64_seed(tiles, &neighbors, 2, 2, options, 0).unwrap();
+
+        let valid = Vec2D::from_vec(vec![1, 0, 0, 1], 2, 2);
+        assert_eq!(wfc.validate(&valid), Ok(()));
+        let invalid = Vec2D::from_vec(vec![0, 0, 1, 1], 2, 2);
+        assert_eq!(wfc.validate(&invalid), Err((1, 0, Direction::Right)));
+    }
+
+    #[test]
+    fn test_tile_size_mismatch() {
+        let tiles = vec![
+            Tile::new(Vec2D::new(2, 2, &0), Symmetry::X, 1.0),
+            Tile::new(Vec2D::new(1, 2, &1), Symmetry::T, 1.0),
+        ];
+        let options = TilingWFCOptions { is_periodic: false };
+        assert_eq!(
+            TilingWFC::from_u64_seed(tiles, &[], 2, 2, options, 0).map(|_| ()),
+            Err(TilingError::TileSizeMismatch {
+                tile: 1,
+                orientation: 0
+            })
+        );
+    }
+
+    #[test]
+    fn test_tile_size() {
+        let tiles = vec![
+            Tile::new(Vec2D::new(2, 2, &0), Symmetry::X, 1.0),
+            Tile::new(Vec2D::new(2, 2, &1), Symmetry::X, 1.0),
+        ];
+        let options = TilingWFCOptions { is_periodic: false };
+        let wfc = TilingWFC::from_u64_seed(tiles, &[], 2, 2, options, 0).unwrap();
+        assert_eq!(wfc.tile_size(), 2);
+
+        let tiles = vec![
+            Tile::new(Vec2D::new(2, 2, &0), Symmetry::X, 1.0),
+            Tile::new(Vec2D::new(3, 3, &1), Symmetry::X, 1.0),
+        ];
+        assert_eq!(
+            TilingWFC::from_u64_seed(tiles, &[], 2, 2, TilingWFCOptions { is_periodic: false }, 0)
+                .map(|_| ()),
+            Err(TilingError::TileSizeMismatch {
+                tile: 1,
+                orientation: 0
+            })
+        );
+    }
+
+    #[test]
+    fn test_draw_tile_grid() {
+        // A 2x2 grid of 2x2 tiles, where tile (i, j) is filled with 2 * i + j.
+        let tiling = Vec2D::from_generator(4, 4, |y, x| 2 * (y / 2) + x / 2);
+        let grid = draw_tile_grid(&tiling, 2, 9);
+        let expected = vec![
+            0, 0, 9, 1, 1, //
+            0, 0, 9, 1, 1, //
+            9, 9, 9, 9, 9, //
+            2, 2, 9, 3, 3, //
+            2, 2, 9, 3, 3, //
+        ];
+        assert_eq!(grid, Vec2D::from_vec(expected, 5, 5));
+    }
+}
@@ -0,0 +1,344 @@
+//! Load tilesets written in the XML format used by the original WFC implementation.
+//! A tileset is a directory containing a data.xml file, describing the tiles and
+//! their neighbors, and an image for each tile.
+
+use crate::tile::*;
+use crate::utils::vec2d::*;
+use crate::Real;
+use quick_xml::events::attributes::Attribute;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::from_utf8;
+
+/// Error when loading a tileset.
+#[derive(Debug)]
+pub enum XmlError {
+    /// The XML file could not be read or parsed.
+    Xml(quick_xml::Error),
+    /// A mandatory attribute is missing in an element.
+    MissingAttribute(String),
+    /// An attribute has a value that cannot be parsed.
+    InvalidAttribute(String),
+    /// The symmetry letter does not correspond to a symmetry.
+    UnknownSymmetry(String),
+    /// The requested subset does not exist.
+    UnknownSubset(String),
+    /// A neighbor rule references a tile that is not in the tileset.
+    UnknownTile(String),
+}
+
+impl From<quick_xml::Error> for XmlError {
+    fn from(error: quick_xml::Error) -> Self {
+        XmlError::Xml(error)
+    }
+}
+
+/// The tiles and neighbors of a tileset, in the format expected by TilingWFC::new.
+pub struct TileSet<T> {
+    /// The tiles of the tileset.
+    pub tiles: Vec<Tile<T>>,
+    /// The name of each tile.
+    pub tile_names: Vec<String>,
+    /// The neighbor rules. [tile1, orientation1, tile2, orientation2] means that
+    /// tile2 with orientation2 can be placed at the right of tile1 with orientation1.
+    pub neighbors: Vec<[usize; 4]>,
+}
+
+/// A tile description, before its images are loaded.
+struct TileDescription {
+    name: String,
+    symmetry: Symmetry,
+    weight: Real,
+}
+
+/// The content of a data.xml file.
+#[derive(Default)]
+struct TileSetDescription {
+    /// Is there an image for each orientation of each tile.
+    unique: bool,
+    tiles: Vec<TileDescription>,
+    neighbors: Vec<(String, String)>,
+    subsets: HashMap<String, Vec<String>>,
+}
+
+/// Get the symmetry associated with a letter of the XML format.
+pub fn parse_symmetry(symmetry: &str) -> Result<Symmetry, XmlError> {
+    match symmetry {
+        "X" => Ok(Symmetry::X),
+        "I" => Ok(Symmetry::I),
+        "\\" => Ok(Symmetry::Backslash),
+        "T" => Ok(Symmetry::T),
+        "L" => Ok(Symmetry::L),
+        "P" => Ok(Symmetry::P),
+        _ => Err(XmlError::UnknownSymmetry(symmetry.to_string())),
+    }
+}
+
+/// Get the value of an attribute, or None if it is not present.
+fn get_attribute(element: &BytesStart, key: &str) -> Result<Option<String>, XmlError> {
+    for attribute in element.attributes() {
+        let Attribute { key: k, value } = attribute?;
+        if k == key.as_bytes() {
+            let value = from_utf8(&value)
+                .map_err(|_| XmlError::InvalidAttribute(key.to_string()))?
+                .to_string();
+            return Ok(Some(value));
+        }
+    }
+    Ok(None)
+}
+
+/// Get the value of an attribute, or an error if it is not present.
+fn get_required_attribute(element: &BytesStart, key: &str) -> Result<String, XmlError> {
+    get_attribute(element, key)?.ok_or_else(|| XmlError::MissingAttribute(key.to_string()))
+}
+
+/// Parse a data.xml file.
+fn parse_description(path: &Path) -> Result<TileSetDescription, XmlError> {
+    let mut reader = Reader::from_file(path)?;
+    reader.trim_text(true);
+    let mut buf = Vec::new();
+
+    let mut description = TileSetDescription::default();
+    // The subset whose tiles are currently read.
+    let mut current_subset = None;
+
+    loop {
+        match reader.read_event(&mut buf)? {
+            Event::Start(ref e) | Event::Empty(ref e) => match e.name() {
+                b"set" => {
+                    description.unique = get_attribute(e, "unique")?.as_deref() == Some("True");
+                }
+                b"subset" => {
+                    let name = get_required_attribute(e, "name")?;
+                    description.subsets.insert(name.clone(), vec![]);
+                    current_subset = Some(name);
+                }
+                b"tile" => {
+                    let name = get_required_attribute(e, "name")?;
+                    if let Some(subset) = &current_subset {
+                        description.subsets.get_mut(subset).unwrap().push(name);
+                    } else {
+                        let symmetry = get_attribute(e, "symmetry")?;
+                        let symmetry = parse_symmetry(symmetry.as_deref().unwrap_or("X"))?;
+                        let weight = match get_attribute(e, "weight")? {
+                            Some(weight) => weight
+                                .parse()
+                                .map_err(|_| XmlError::InvalidAttribute(weight))?,
+                            None => 1.0,
+                        };
+                        description.tiles.push(TileDescription {
+                            name,
+                            symmetry,
+                            weight,
+                        });
+                    }
+                }
+                b"neighbor" => {
+                    let left = get_required_attribute(e, "left")?;
+                    let right = get_required_attribute(e, "right")?;
+                    description.neighbors.push((left, right));
+                }
+                _ => (),
+            },
+            Event::End(ref e) if e.name() == b"subset" => current_subset = None,
+            Event::Eof => break,
+            _ => (),
+        }
+        buf.clear();
+    }
+
+    Ok(description)
+}
+
+/// Parse a tile reference of the form "name orientation", where the orientation
+/// is optional and defaults to 0.
+fn parse_tile_reference(reference: &str) -> Result<(&str, usize), XmlError> {
+    let mut split = reference.split(' ');
+    let name = split.next().unwrap();
+    let orientation = match split.next() {
+        Some(orientation) => orientation
+            .parse()
+            .map_err(|_| XmlError::InvalidAttribute(reference.to_string()))?,
+        None => 0,
+    };
+    Ok((name, orientation))
+}
+
+/// Load the tileset contained in a directory.
+/// If subset is given, only the tiles belonging to the subset are loaded, and
+/// the neighbor rules referencing the other tiles are skipped.
+/// Return XmlError::UnknownTile if a neighbor rule references a tile that is
+/// not in the tileset.
+/// load_image is called with the path of each image that should be loaded.
+/// For tilesets that have only one image per tile, the image of tile "name"
+/// is "name.png". Otherwise, the image of orientation i is "name i.png".
+pub fn load_tileset<T, F>(
+    directory: &Path,
+    subset: Option<&str>,
+    mut load_image: F,
+) -> Result<TileSet<T>, XmlError>
+where
+    T: Clone,
+    F: FnMut(&Path) -> Vec2D<T>,
+{
+    let description = parse_description(&directory.join("data.xml"))?;
+    let subset = match subset {
+        Some(subset) => Some(
+            description
+                .subsets
+                .get(subset)
+                .ok_or_else(|| XmlError::UnknownSubset(subset.to_string()))?,
+        ),
+        None => None,
+    };
+
+    let mut tiles = vec![];
+    let mut tile_names = vec![];
+    for tile in &description.tiles {
+        if let Some(subset) = subset {
+            if !subset.contains(&tile.name) {
+                continue;
+            }
+        }
+
+        let image_path = |suffix: String| -> PathBuf { directory.join(suffix + ".png") };
+        let oriented_tile = if description.unique {
+            let data = (0..tile.symmetry.nb_of_possible_orientations())
+                .map(|i| load_image(&image_path(format!("{} {}", tile.name, i))))
+                .collect();
+            Tile::from_oriented(data, tile.symmetry, tile.weight)
+        } else {
+            let data = load_image(&image_path(tile.name.clone()));
+            Tile::new(data, tile.symmetry, tile.weight)
+        };
+        tiles.push(oriented_tile);
+        tile_names.push(tile.name.clone());
+    }
+
+    let tile_ids: HashMap<_, _> = tile_names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| (name.as_str(), i))
+        .collect();
+
+    let mut neighbors = vec![];
+    for (left, right) in &description.neighbors {
+        let (left_name, left_orientation) = parse_tile_reference(left)?;
+        let (right_name, right_orientation) = parse_tile_reference(right)?;
+        for name in [left_name, right_name] {
+            if !description.tiles.iter().any(|tile| tile.name == name) {
+                return Err(XmlError::UnknownTile(name.to_string()));
+            }
+        }
+        // Neighbors may reference tiles that are not in the subset.
+        if let (Some(&left_id), Some(&right_id)) =
+            (tile_ids.get(left_name), tile_ids.get(right_name))
+        {
+            neighbors.push([left_id, left_orientation, right_id, right_orientation]);
+        }
+    }
+
+    Ok(TileSet {
+        tiles,
+        tile_names,
+        neighbors,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_tile_reference() {
+        assert_eq!(parse_tile_reference("corner").unwrap(), ("corner", 0));
+        assert_eq!(parse_tile_reference("corner 3").unwrap(), ("corner", 3));
+        assert!(parse_tile_reference("corner x").is_err());
+    }
+
+    /// Write a data.xml file in a new directory of the temporary directory.
+    fn write_tileset(name: &str, data: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("fast_wfc_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("data.xml"), data).unwrap();
+        directory
+    }
+
+    #[test]
+    fn test_load_tileset() {
+        let directory = write_tileset(
+            "load_tileset",
+            r#"<set unique="True">
+                <tiles>
+                    <tile name="empty"/>
+                    <tile name="line" symmetry="I" weight="2.5"/>
+                </tiles>
+                <neighbors>
+                    <neighbor left="empty" right="line 1"/>
+                    <neighbor left="line" right="line"/>
+                </neighbors>
+                <subsets>
+                    <subset name="lines">
+                        <tile name="line"/>
+                    </subset>
+                </subsets>
+            </set>"#,
+        );
+        // Load the tileset, and get the names of the loaded images.
+        let load = |subset| {
+            let mut loaded = vec![];
+            let tileset = load_tileset(&directory, subset, |path| {
+                loaded.push(path.file_name().unwrap().to_str().unwrap().to_string());
+                Vec2D::new(1, 1, &loaded.len())
+            });
+            (tileset, loaded)
+        };
+
+        let (tileset, loaded) = load(None);
+        let tileset = tileset.unwrap();
+        assert_eq!(tileset.tile_names, vec!["empty", "line"]);
+        assert!(matches!(tileset.tiles[0].symmetry(), Symmetry::X));
+        assert_eq!(tileset.tiles[0].weight(), 1.0);
+        assert!(matches!(tileset.tiles[1].symmetry(), Symmetry::I));
+        assert_eq!(tileset.tiles[1].weight(), 2.5);
+        assert_eq!(tileset.neighbors, vec![[0, 0, 1, 1], [1, 0, 1, 0]]);
+        assert_eq!(loaded, vec!["empty 0.png", "line 0.png", "line 1.png"]);
+
+        let (tileset, loaded) = load(Some("lines"));
+        let tileset = tileset.unwrap();
+        assert_eq!(tileset.tile_names, vec!["line"]);
+        assert_eq!(tileset.neighbors, vec![[0, 0, 0, 0]]);
+        assert_eq!(loaded, vec!["line 0.png", "line 1.png"]);
+
+        assert!(matches!(
+            load(Some("curves")).0,
+            Err(XmlError::UnknownSubset(_))
+        ));
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_load_tileset_unknown_tile() {
+        let directory = write_tileset(
+            "unknown_tile",
+            r#"<set>
+                <tiles>
+                    <tile name="empty"/>
+                </tiles>
+                <neighbors>
+                    <neighbor left="empty" right="emtpy"/>
+                </neighbors>
+            </set>"#,
+        );
+        let result = load_tileset(&directory, None, |_| Vec2D::new(1, 1, &0));
+        match result {
+            Err(XmlError::UnknownTile(name)) => assert_eq!(name, "emtpy"),
+            _ => panic!("expected an unknown tile"),
+        }
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,216 @@
+//! Contains the BitVec3D implementation, a 3D matrix of booleans where each
+//! boolean is stored in a single bit.
+
+/// The number of bits stored in a word.
+const WORD_SIZE: usize = 64;
+
+/// A 3D matrix of booleans, where each boolean is stored in a single bit.
+/// The booleans sharing the same first two coordinates are stored in
+/// consecutive words, starting at the beginning of a word.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "RawBitVec3D"))]
+pub struct BitVec3D {
+    depth: usize,
+    height: usize,
+    width: usize,
+    /// The number of words used to store the booleans sharing the same
+    /// first two coordinates.
+    #[cfg_attr(feature = "serde", serde(skip_serializing))]
+    words_per_line: usize,
+    data: Vec<u64>,
+}
+
+/// The serialized form of a BitVec3D, checked before being turned into one.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct RawBitVec3D {
+    depth: usize,
+    height: usize,
+    width: usize,
+    data: Vec<u64>,
+}
+
+#[cfg(feature = "serde")]
+impl std::convert::TryFrom<RawBitVec3D> for BitVec3D {
+    type Error = String;
+
+    fn try_from(raw: RawBitVec3D) -> Result<Self, Self::Error> {
+        let words_per_line = raw.width.div_ceil(WORD_SIZE);
+        let size = raw
+            .depth
+            .checked_mul(raw.height)
+            .and_then(|size| size.checked_mul(words_per_line));
+        if size != Some(raw.data.len()) {
+            return Err(format!(
+                "a {}x{}x{} BitVec3D cannot be stored in {} words",
+                raw.depth,
+                raw.height,
+                raw.width,
+                raw.data.len()
+            ));
+        }
+        let remaining_bits = raw.width % WORD_SIZE;
+        if remaining_bits != 0 {
+            let padding = !((1 << remaining_bits) - 1);
+            let last_words = raw.data.iter().skip(words_per_line - 1);
+            if last_words
+                .step_by(words_per_line)
+                .any(|word| word & padding != 0)
+            {
+                return Err("the padding bits of a BitVec3D should be 0".to_string());
+            }
+        }
+        Ok(BitVec3D {
+            depth: raw.depth,
+            height: raw.height,
+            width: raw.width,
+            words_per_line,
+            data: raw.data,
+        })
+    }
+}
+
+impl BitVec3D {
+    /// Create a matrix given its size, that is filled with a value
+    pub fn new(depth: usize, height: usize, width: usize, value: bool) -> BitVec3D {
+        let words_per_line = width.div_ceil(WORD_SIZE);
+        let mut vec = BitVec3D {
+            depth,
+            height,
+            width,
+            words_per_line,
+            data: vec![0; depth * height * words_per_line],
+        };
+        vec.fill(value);
+        vec
+    }
+
+    /// Set every element of the matrix to value.
+    pub fn fill(&mut self, value: bool) {
+        if !value || self.words_per_line == 0 {
+            for word in &mut self.data {
+                *word = 0;
+            }
+            return;
+        }
+
+        // The bits after the last element of a line are kept to 0.
+        let mut line = vec![!0; self.words_per_line];
+        let remaining_bits = self.width % WORD_SIZE;
+        if remaining_bits != 0 {
+            line[self.words_per_line - 1] = (1 << remaining_bits) - 1;
+        }
+        for chunk in self.data.chunks_exact_mut(self.words_per_line) {
+            chunk.copy_from_slice(&line);
+        }
+    }
+
+    /// Get the size of the first dimension.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Get the size of the second dimension.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Get the size of the third dimension.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Get the index of the word containing the element (i, j, k), and the
+    /// mask selecting the element in the word.
+    fn position(&self, i: usize, j: usize, k: usize) -> (usize, u64) {
+        debug_assert!(i < self.depth && j < self.height && k < self.width);
+        let index = (j + i * self.height) * self.words_per_line + k / WORD_SIZE;
+        (index, 1 << (k % WORD_SIZE))
+    }
+
+    /// Get the value of the element (i, j, k).
+    pub fn get(&self, i: usize, j: usize, k: usize) -> bool {
+        let (index, mask) = self.position(i, j, k);
+        self.data[index] & mask != 0
+    }
+
+    /// Set the element (i, j, k) to true.
+    pub fn set(&mut self, i: usize, j: usize, k: usize) {
+        let (index, mask) = self.position(i, j, k);
+        self.data[index] |= mask;
+    }
+
+    /// Set the element (i, j, k) to false.
+    pub fn clear(&mut self, i: usize, j: usize, k: usize) {
+        let (index, mask) = self.position(i, j, k);
+        self.data[index] &= !mask;
+    }
+
+    /// Return an iterator on the elements (i, j, k), for every k.
+    pub fn iter_line(&self, i: usize, j: usize) -> impl Iterator<Item = bool> + '_ {
+        let begin_index = (j + i * self.height) * self.words_per_line;
+        let line = &self.data[begin_index..begin_index + self.words_per_line];
+        (0..self.width).map(move |k| line[k / WORD_SIZE] & (1 << (k % WORD_SIZE)) != 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_set_clear() {
+        let mut vec = BitVec3D::new(2, 3, 70, false);
+        assert!(!vec.get(1, 2, 65));
+        vec.set(1, 2, 65);
+        assert!(vec.get(1, 2, 65));
+        assert!(!vec.get(1, 2, 1));
+        assert!(!vec.get(0, 2, 65));
+        vec.clear(1, 2, 65);
+        assert!(!vec.get(1, 2, 65));
+    }
+
+    #[test]
+    fn test_fill() {
+        let mut vec = BitVec3D::new(2, 3, 70, true);
+        assert!(vec.iter_line(1, 1).all(|b| b));
+        assert_eq!(vec.iter_line(1, 1).count(), 70);
+        vec.fill(false);
+        assert!(vec.iter_line(1, 1).all(|b| !b));
+    }
+
+    #[test]
+    fn test_iter_line() {
+        let mut vec = BitVec3D::new(2, 2, 3, false);
+        vec.set(1, 0, 2);
+        vec.set(1, 0, 0);
+        assert_eq!(
+            vec.iter_line(1, 0).collect::<Vec<_>>(),
+            vec![true, false, true]
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let mut vec = BitVec3D::new(2, 3, 70, false);
+        vec.set(1, 2, 65);
+        vec.set(0, 1, 3);
+        let json = serde_json::to_string(&vec).unwrap();
+        assert_eq!(serde_json::from_str::<BitVec3D>(&json).unwrap(), vec);
+
+        let empty = BitVec3D::new(0, 3, 70, true);
+        let json = serde_json::to_string(&empty).unwrap();
+        assert_eq!(serde_json::from_str::<BitVec3D>(&json).unwrap(), empty);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_invalid() {
+        let json = r#"{"depth":1,"height":2,"width":3,"data":[0]}"#;
+        assert!(serde_json::from_str::<BitVec3D>(json).is_err());
+        let json = r#"{"depth":1,"height":1,"width":3,"data":[8]}"#;
+        assert!(serde_json::from_str::<BitVec3D>(json).is_err());
+    }
+}
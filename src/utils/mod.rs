@@ -1,2 +1,3 @@
+pub mod bitvec3d;
 pub mod vec2d;
 pub mod vec3d;
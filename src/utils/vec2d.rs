@@ -6,12 +6,44 @@ use std::slice::{Iter, IterMut};
 /// A 2D matrix represented by a Vec.
 /// The Vec contains the values line after line.
 #[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "RawVec2D<T>"))]
 pub struct Vec2D<T> {
     height: usize,
     width: usize,
     data: Vec<T>,
 }
 
+/// The serialized form of a Vec2D, checked before being turned into one.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct RawVec2D<T> {
+    height: usize,
+    width: usize,
+    data: Vec<T>,
+}
+
+#[cfg(feature = "serde")]
+impl<T> std::convert::TryFrom<RawVec2D<T>> for Vec2D<T> {
+    type Error = String;
+
+    fn try_from(raw: RawVec2D<T>) -> Result<Self, Self::Error> {
+        if raw.height.checked_mul(raw.width) != Some(raw.data.len()) {
+            return Err(format!(
+                "a {}x{} Vec2D cannot hold {} elements",
+                raw.height,
+                raw.width,
+                raw.data.len()
+            ));
+        }
+        Ok(Vec2D {
+            height: raw.height,
+            width: raw.width,
+            data: raw.data,
+        })
+    }
+}
+
 impl<T> Vec2D<T> {
     /// Create a matrix given its height and width, that is filled with a value
     pub fn new(height: usize, width: usize, value: &T) -> Vec2D<T>
@@ -47,6 +79,19 @@ impl<T> Vec2D<T> {
         self.data.iter_mut()
     }
 
+    /// Create a Vec2D from its rows, inferring its size.
+    /// Return the index of the first row that does not have the same length as
+    /// the first one, if the rows are ragged.
+    pub fn from_rows(rows: Vec<Vec<T>>) -> Result<Vec2D<T>, usize> {
+        let height = rows.len();
+        let width = rows.first().map_or(0, Vec::len);
+        if let Some(ragged) = rows.iter().position(|row| row.len() != width) {
+            return Err(ragged);
+        }
+        let data = rows.into_iter().flatten().collect();
+        Ok(Vec2D::from_vec(data, height, width))
+    }
+
     /// Create a Vec2D using a generator function that will be called in all cells.
     pub fn from_generator<F: Fn(usize, usize) -> T>(
         height: usize,
@@ -55,12 +100,47 @@ impl<T> Vec2D<T> {
     ) -> Vec2D<T> {
         let generator = &generator;
         let vec = (0..height)
-            .map(|i| (0..width).map(move |j| generator(i, j)))
-            .flatten()
+            .flat_map(|i| (0..width).map(move |j| generator(i, j)))
             .collect();
         Vec2D::from_vec(vec, height, width)
     }
 
+    /// Create a Vec2D using a fallible generator function that will be called in
+    /// all cells, in row-major order. Return the first error returned by the
+    /// generator, if any.
+    pub fn try_from_generator<E, F: Fn(usize, usize) -> Result<T, E>>(
+        height: usize,
+        width: usize,
+        generator: F,
+    ) -> Result<Vec2D<T>, E> {
+        let generator = &generator;
+        let vec = (0..height)
+            .flat_map(|i| (0..width).map(move |j| generator(i, j)))
+            .collect::<Result<_, _>>()?;
+        Ok(Vec2D::from_vec(vec, height, width))
+    }
+
+    /// Create a Vec2D of the same size, by applying f on each element.
+    pub fn map<U, F: Fn(&T) -> U>(&self, f: F) -> Vec2D<U> {
+        let data = self.data.iter().map(f).collect();
+        Vec2D::from_vec(data, self.height, self.width)
+    }
+
+    /// Create a Vec2D of the same size, by applying f on each pair of elements
+    /// of self and other at the same position.
+    /// Both Vec2D should have the same size.
+    pub fn zip_map<U, V, F: Fn(&T, &U) -> V>(&self, other: &Vec2D<U>, f: F) -> Vec2D<V> {
+        assert_eq!(self.height, other.height);
+        assert_eq!(self.width, other.width);
+        let data = self
+            .data
+            .iter()
+            .zip(other.data.iter())
+            .map(|(a, b)| f(a, b))
+            .collect();
+        Vec2D::from_vec(data, self.height, self.width)
+    }
+
     /// Get the Vec2D data as a Vec.
     pub fn into_vec(self) -> Vec<T> {
         self.data
@@ -88,8 +168,7 @@ impl<T> Vec2D<T> {
         let data = self
             .data
             .chunks_exact(self.width)
-            .map(|s| s.iter().rev())
-            .flatten()
+            .flat_map(|s| s.iter().rev())
             .map(T::clone)
             .collect();
         Vec2D::from_vec(data, self.height, self.width)
@@ -115,6 +194,39 @@ impl<T> Vec2D<T> {
         new_vec
     }
 
+    /// Get the rotation of quarter_turns * 90° anticlockwise.
+    pub fn rotated_by(&self, quarter_turns: usize) -> Vec2D<T>
+    where
+        T: Clone,
+    {
+        match quarter_turns % 4 {
+            0 => self.clone(),
+            1 => self.rotated(),
+            2 => {
+                let data = self.data.iter().rev().cloned().collect();
+                Vec2D::from_vec(data, self.height, self.width)
+            }
+            _ => self.rotated_cw(),
+        }
+    }
+
+    /// Get the 90° clockwise rotation.
+    pub fn rotated_cw(&self) -> Vec2D<T>
+    where
+        T: Clone,
+    {
+        let height = self.height;
+        Vec2D::from_generator(self.width, height, |y, x| self[height - 1 - x][y].clone())
+    }
+
+    /// Get the transposition, where the element [y][x] is moved to [x][y].
+    pub fn transposed(&self) -> Vec2D<T>
+    where
+        T: Clone,
+    {
+        Vec2D::from_generator(self.width, self.height, |y, x| self[x][y].clone())
+    }
+
     /// Get a submatrix given its upper leftmost position, and its size.
     /// The matrices are here considered toric.
     pub fn get_sub_vec(&self, y: usize, x: usize, sub_height: usize, sub_width: usize) -> Vec2D<T>
@@ -136,20 +248,153 @@ impl<T> Vec2D<T> {
         sub_vec
     }
 
+    /// Get a submatrix given its upper leftmost position, and its size.
+    /// The matrices are here not considered toric, so None is returned if the
+    /// submatrix does not fit inside the matrix.
+    pub fn get_sub_vec_bounded(
+        &self,
+        y: usize,
+        x: usize,
+        sub_height: usize,
+        sub_width: usize,
+    ) -> Option<Vec2D<T>>
+    where
+        T: Clone,
+    {
+        if y + sub_height > self.height || x + sub_width > self.width {
+            return None;
+        }
+        Some(Vec2D::from_generator(sub_height, sub_width, |dy, dx| {
+            self[y + dy][x + dx].clone()
+        }))
+    }
+
+    /// Get the matrix made of self on the left and other on the right.
+    /// Return None if the matrices don't have the same height.
+    pub fn hconcat(&self, other: &Vec2D<T>) -> Option<Vec2D<T>>
+    where
+        T: Clone,
+    {
+        if self.height != other.height {
+            return None;
+        }
+        let width = self.width + other.width;
+        let mut data = Vec::with_capacity(self.height * width);
+        for i in 0..self.height {
+            data.extend_from_slice(&self[i]);
+            data.extend_from_slice(&other[i]);
+        }
+        Some(Vec2D::from_vec(data, self.height, width))
+    }
+
+    /// Get the matrix made of reps_y * reps_x copies of self, with reps_y
+    /// copies on each column and reps_x copies on each row.
+    pub fn tiled(&self, reps_y: usize, reps_x: usize) -> Vec2D<T>
+    where
+        T: Clone,
+    {
+        Vec2D::from_generator(self.height * reps_y, self.width * reps_x, |i, j| {
+            self[i % self.height][j % self.width].clone()
+        })
+    }
+
+    /// Get the matrix made of self on the top and other on the bottom.
+    /// Return None if the matrices don't have the same width.
+    pub fn vconcat(&self, other: &Vec2D<T>) -> Option<Vec2D<T>>
+    where
+        T: Clone,
+    {
+        if self.width != other.width {
+            return None;
+        }
+        let mut data = self.data.clone();
+        data.extend_from_slice(&other.data);
+        Some(Vec2D::from_vec(
+            data,
+            self.height + other.height,
+            self.width,
+        ))
+    }
+
     /// Return an iterator which gives the cell index (i,j) as well as the value.
+    /// The cells are visited in row-major order, which is guaranteed since the
+    /// randomness used by the WFC depends on it.
     pub fn iter_enumerate(&self) -> impl Iterator<Item = ((usize, usize), &T)> {
+        let width = self.width;
+        self.data
+            .iter()
+            .enumerate()
+            .map(move |(k, v)| ((k / width, k % width), v))
+    }
+
+    /// Return an iterator which gives the cell index (i,j) as well as a mutable
+    /// reference to the value, in row-major order.
+    pub fn iter_enumerate_mut(&mut self) -> impl Iterator<Item = ((usize, usize), &mut T)> {
+        let width = self.width;
         self.data
-            .chunks_exact(self.width())
+            .iter_mut()
             .enumerate()
-            .map(|(i, s)| s.iter().enumerate().map(move |(j, v)| ((i, j), v)))
-            .flatten()
+            .map(move |(k, v)| ((k / width, k % width), v))
+    }
+
+    /// Get the row y, which is the same as self[y].
+    pub fn row(&self, y: usize) -> &[T] {
+        &self[y]
+    }
+
+    /// Get a copy of the column x.
+    pub fn column(&self, x: usize) -> Vec<T>
+    where
+        T: Clone,
+    {
+        assert!(x < self.width);
+        self.data
+            .iter()
+            .skip(x)
+            .step_by(self.width)
+            .cloned()
+            .collect()
+    }
+
+    /// Return an iterator on copies of the columns, from left to right.
+    pub fn columns(&self) -> impl Iterator<Item = Vec<T>> + '_
+    where
+        T: Clone,
+    {
+        (0..self.width).map(move |x| self.column(x))
+    }
+}
+
+impl Vec2D<usize> {
+    /// Get a hash of the size and the content of the matrix, that does not
+    /// depend on the platform or on the Rust version.
+    /// The hash is the 64 bits FNV-1a hash of the height, the width, and the
+    /// values, each written as a little-endian u64.
+    pub fn content_hash(&self) -> u64 {
+        const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+        const PRIME: u64 = 0x0100_0000_01b3;
+        let size = [self.height, self.width];
+        size.iter()
+            .chain(self.data.iter())
+            .flat_map(|&value| (value as u64).to_le_bytes())
+            .fold(OFFSET_BASIS, |hash, byte| {
+                (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+            })
     }
 }
 
+/// Get a row of the Vec2D.
+/// In debug builds, an out of bounds row panics with the height of the Vec2D.
 impl<T> Index<usize> for Vec2D<T> {
     type Output = [T];
 
     fn index(&self, i: usize) -> &Self::Output {
+        debug_assert!(
+            i < self.height,
+            "row {} out of bounds for Vec2D of height {}",
+            i,
+            self.height
+        );
         let begin_index = i * self.width;
         let end_index = (i + 1) * self.width;
         &self.data[begin_index..end_index]
@@ -158,6 +403,12 @@ impl<T> Index<usize> for Vec2D<T> {
 
 impl<T> IndexMut<usize> for Vec2D<T> {
     fn index_mut(&mut self, i: usize) -> &mut Self::Output {
+        debug_assert!(
+            i < self.height,
+            "row {} out of bounds for Vec2D of height {}",
+            i,
+            self.height
+        );
         let begin_index = i * self.width;
         let end_index = (i + 1) * self.width;
         &mut self.data[begin_index..end_index]
@@ -186,6 +437,17 @@ impl<'a, T> IntoIterator for &'a mut Vec2D<T> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_from_rows() {
+        let vec = Vec2D::from_rows(vec![vec![0, 1, 2], vec![3, 4, 5]]).unwrap();
+        assert_eq!(vec, Vec2D::from_vec(vec![0, 1, 2, 3, 4, 5], 2, 3));
+        assert_eq!(Vec2D::<u8>::from_rows(vec![]).unwrap().height(), 0);
+        assert_eq!(
+            Vec2D::from_rows(vec![vec![0, 1], vec![2, 3], vec![4]]),
+            Err(2)
+        );
+    }
+
     #[test]
     fn test_get_mut() {
         let mut vec = Vec2D::from_vec(vec![0, 1, 2, 3, 4, 5], 2, 3);
@@ -194,11 +456,20 @@ mod tests {
 
     #[test]
     #[should_panic]
+    #[allow(clippy::unnecessary_operation)]
     fn test_get_mut_panic() {
         let mut vec = Vec2D::from_vec(vec![0, 1, 2, 3, 4, 5], 2, 3);
         vec.index_mut(2)[2];
     }
 
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "row 5 out of bounds for Vec2D of height 2")]
+    fn test_index_panic_message() {
+        let vec = Vec2D::from_vec(vec![0, 1, 2, 3, 4, 5], 2, 3);
+        let _ = vec[5][0];
+    }
+
     #[test]
     fn test_get() {
         let vec = Vec2D::from_vec(vec![0, 1, 2, 3, 4, 5], 2, 3);
@@ -207,6 +478,7 @@ mod tests {
 
     #[test]
     #[should_panic]
+    #[allow(clippy::unnecessary_operation)]
     fn test_get_panic() {
         let vec = Vec2D::from_vec(vec![0, 1, 2, 3, 4, 5], 2, 3);
         vec.index(2)[2];
@@ -251,4 +523,233 @@ mod tests {
         let vec_result = Vec2D::<usize>::from_vec(vec![], 0, 0);
         assert_eq!(vec.rotated(), vec_result);
     }
+
+    #[test]
+    fn test_rotated_by() {
+        let vec = Vec2D::from_vec(vec![0, 1, 2, 3, 4, 5], 2, 3);
+        assert_eq!(vec.rotated_by(0), vec);
+        assert_eq!(vec.rotated_by(1), vec.rotated());
+        assert_eq!(vec.rotated_by(2), vec.rotated().rotated());
+        assert_eq!(vec.rotated_by(3), vec.rotated().rotated().rotated());
+        assert_eq!(vec.rotated_by(4), vec);
+        assert_eq!(vec.rotated_by(7), vec.rotated_by(3));
+    }
+
+    #[test]
+    fn test_rotated_by_empty() {
+        let vec = Vec2D::<usize>::from_vec(vec![], 0, 1);
+        assert_eq!(vec.rotated_by(2), vec);
+        assert_eq!(vec.rotated_by(3), Vec2D::from_vec(vec![], 1, 0));
+    }
+
+    #[test]
+    fn test_map() {
+        let vec = Vec2D::from_vec(vec![0u8, 1, 2, 3, 4, 5], 2, 3);
+        let vec_result = Vec2D::from_vec(
+            vec!["0", "1", "2", "3", "4", "5"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            2,
+            3,
+        );
+        assert_eq!(vec.map(|v| v.to_string()), vec_result);
+    }
+
+    #[test]
+    fn test_zip_map() {
+        let vec1 = Vec2D::from_vec(vec![0, 1, 2, 3, 4, 5], 2, 3);
+        let vec2 = Vec2D::from_vec(vec![5, 4, 3, 2, 1, 0], 2, 3);
+        let vec_result = Vec2D::new(2, 3, &5);
+        assert_eq!(vec1.zip_map(&vec2, |a, b| a + b), vec_result);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_zip_map_panic() {
+        let vec1 = Vec2D::from_vec(vec![0, 1, 2, 3, 4, 5], 2, 3);
+        let vec2 = Vec2D::from_vec(vec![0, 1, 2, 3, 4, 5], 3, 2);
+        vec1.zip_map(&vec2, |a, b| a + b);
+    }
+
+    #[test]
+    fn test_iter_enumerate() {
+        let vec = Vec2D::from_vec(vec![0, 1, 2, 3, 4, 5], 2, 3);
+        let indices: Vec<_> = vec.iter_enumerate().map(|(index, _)| index).collect();
+        assert_eq!(
+            indices,
+            vec![(0, 0), (0, 1), (0, 2), (1, 0), (1, 1), (1, 2)]
+        );
+        for ((i, j), v) in vec.iter_enumerate() {
+            assert_eq!(*v, i * 3 + j);
+        }
+        let vec = Vec2D::<usize>::from_vec(vec![], 2, 0);
+        assert_eq!(vec.iter_enumerate().count(), 0);
+    }
+
+    #[test]
+    fn test_iter_enumerate_mut() {
+        let mut vec = Vec2D::new(2, 3, &0);
+        for ((i, j), v) in vec.iter_enumerate_mut() {
+            *v = i * 3 + j;
+        }
+        assert_eq!(vec, Vec2D::from_vec(vec![0, 1, 2, 3, 4, 5], 2, 3));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let vec = Vec2D::from_vec(vec![0, 1, 2, 3, 4, 5], 2, 3);
+        let json = serde_json::to_string(&vec).unwrap();
+        assert_eq!(serde_json::from_str::<Vec2D<i32>>(&json).unwrap(), vec);
+
+        let empty: Vec2D<i32> = Vec2D::from_vec(vec![], 0, 4);
+        let json = serde_json::to_string(&empty).unwrap();
+        let back: Vec2D<i32> = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, empty);
+        assert_eq!(back.width(), 4);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_wrong_length() {
+        let json = r#"{"height":2,"width":3,"data":[0,1,2,3,4]}"#;
+        assert!(serde_json::from_str::<Vec2D<i32>>(json).is_err());
+    }
+
+    #[test]
+    fn test_hconcat() {
+        let left = Vec2D::from_vec(vec![0, 1, 2, 3], 2, 2);
+        let right = Vec2D::from_vec(vec![4, 5], 2, 1);
+        let concat = left.hconcat(&right).unwrap();
+        assert_eq!(concat, Vec2D::from_vec(vec![0, 1, 4, 2, 3, 5], 2, 3));
+
+        let empty = Vec2D::from_vec(vec![], 2, 0);
+        assert_eq!(left.hconcat(&empty).unwrap(), left);
+        assert_eq!(empty.hconcat(&left).unwrap(), left);
+        assert!(left
+            .hconcat(&Vec2D::from_vec(vec![4, 5, 6], 3, 1))
+            .is_none());
+    }
+
+    #[test]
+    fn test_vconcat() {
+        let top = Vec2D::from_vec(vec![0, 1, 2, 3], 2, 2);
+        let bottom = Vec2D::from_vec(vec![4, 5], 1, 2);
+        let concat = top.vconcat(&bottom).unwrap();
+        assert_eq!(concat, Vec2D::from_vec(vec![0, 1, 2, 3, 4, 5], 3, 2));
+
+        let empty = Vec2D::from_vec(vec![], 0, 2);
+        assert_eq!(top.vconcat(&empty).unwrap(), top);
+        assert_eq!(empty.vconcat(&top).unwrap(), top);
+        assert!(top.vconcat(&Vec2D::from_vec(vec![4, 5, 6], 1, 3)).is_none());
+    }
+
+    #[test]
+    fn test_get_sub_vec_bounded() {
+        // 0 1 2
+        // 3 4 5
+        // 6 7 8
+        let vec = Vec2D::from_vec((0..9).collect(), 3, 3);
+        assert_eq!(
+            vec.get_sub_vec(2, 2, 2, 2),
+            Vec2D::from_vec(vec![8, 6, 2, 0], 2, 2)
+        );
+        assert_eq!(vec.get_sub_vec_bounded(2, 2, 2, 2), None);
+        assert_eq!(
+            vec.get_sub_vec_bounded(1, 1, 2, 2),
+            Some(vec.get_sub_vec(1, 1, 2, 2))
+        );
+        assert_eq!(
+            vec.get_sub_vec_bounded(2, 2, 1, 1),
+            Some(Vec2D::from_vec(vec![8], 1, 1))
+        );
+        assert_eq!(vec.get_sub_vec_bounded(0, 2, 1, 2), None);
+    }
+
+    #[test]
+    fn test_tiled() {
+        let vec = Vec2D::from_vec(vec![0, 1, 2, 3, 4, 5], 2, 3);
+        let tiled = vec.tiled(2, 1);
+        assert_eq!(tiled, vec.vconcat(&vec).unwrap());
+        let tiled = vec.tiled(1, 2);
+        assert_eq!(tiled, vec.hconcat(&vec).unwrap());
+        assert_eq!(vec.tiled(0, 2), Vec2D::from_vec(vec![], 0, 6));
+    }
+
+    #[test]
+    fn test_rotated_cw() {
+        // 0 1 2
+        // 3 4 5
+        let vec = Vec2D::from_vec(vec![0, 1, 2, 3, 4, 5], 2, 3);
+        // 3 0
+        // 4 1
+        // 5 2
+        let expected = Vec2D::from_vec(vec![3, 0, 4, 1, 5, 2], 3, 2);
+        assert_eq!(vec.rotated_cw(), expected);
+        assert_eq!(vec.rotated_cw().rotated_cw().rotated_cw(), vec.rotated());
+        assert_eq!(vec.rotated_cw().rotated(), vec);
+    }
+
+    #[test]
+    fn test_transposed() {
+        // 0 1 2
+        // 3 4 5
+        let vec = Vec2D::from_vec(vec![0, 1, 2, 3, 4, 5], 2, 3);
+        // 0 3
+        // 1 4
+        // 2 5
+        let expected = Vec2D::from_vec(vec![0, 3, 1, 4, 2, 5], 3, 2);
+        let transposed = vec.transposed();
+        assert_eq!(transposed, expected);
+        assert_eq!((transposed.height(), transposed.width()), (3, 2));
+        assert_eq!(transposed.transposed(), vec);
+    }
+
+    #[test]
+    fn test_try_from_generator() {
+        let vec: Result<_, ()> = Vec2D::try_from_generator(2, 3, |i, j| Ok(i * 3 + j));
+        assert_eq!(vec, Ok(Vec2D::from_vec(vec![0, 1, 2, 3, 4, 5], 2, 3)));
+
+        let calls = std::cell::Cell::new(0);
+        let vec = Vec2D::try_from_generator(2, 3, |i, j| {
+            calls.set(calls.get() + 1);
+            if (i, j) == (1, 0) {
+                Err((i, j))
+            } else {
+                Ok(i * 3 + j)
+            }
+        });
+        assert_eq!(vec, Err((1, 0)));
+        assert_eq!(calls.get(), 4);
+    }
+
+    #[test]
+    fn test_row_column() {
+        // 0 1 2
+        // 3 4 5
+        let vec = Vec2D::from_vec(vec![0, 1, 2, 3, 4, 5], 2, 3);
+        assert_eq!(vec.row(0), &[0, 1, 2]);
+        assert_eq!(vec.row(1), &[3, 4, 5]);
+        assert_eq!(vec.column(0), vec![0, 3]);
+        assert_eq!(vec.column(2), vec![2, 5]);
+        let columns: Vec<_> = vec.columns().collect();
+        assert_eq!(columns, vec![vec![0, 3], vec![1, 4], vec![2, 5]]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_column_panic() {
+        let vec = Vec2D::from_vec(vec![0, 1, 2, 3, 4, 5], 2, 3);
+        vec.column(3);
+    }
+
+    #[test]
+    fn test_content_hash() {
+        let vec = Vec2D::from_vec(vec![0, 1, 2, 3, 4, 5], 2, 3);
+        assert_eq!(vec.content_hash(), 0x1b95_31af_b1a7_e7a5);
+        assert_eq!(vec.content_hash(), vec.clone().content_hash());
+        assert_ne!(vec.content_hash(), vec.transposed().content_hash());
+        assert_ne!(vec.content_hash(), vec.map(|v| v + 1).content_hash());
+    }
 }
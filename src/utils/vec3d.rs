@@ -5,6 +5,8 @@ use std::slice::{Iter, IterMut};
 
 /// A 3D matrix represented by a Vec.
 #[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "RawVec3D<T>"))]
 pub struct Vec3D<T> {
     depth: usize,
     height: usize,
@@ -12,6 +14,43 @@ pub struct Vec3D<T> {
     data: Vec<T>,
 }
 
+/// The serialized form of a Vec3D, checked before being turned into one.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct RawVec3D<T> {
+    depth: usize,
+    height: usize,
+    width: usize,
+    data: Vec<T>,
+}
+
+#[cfg(feature = "serde")]
+impl<T> std::convert::TryFrom<RawVec3D<T>> for Vec3D<T> {
+    type Error = String;
+
+    fn try_from(raw: RawVec3D<T>) -> Result<Self, Self::Error> {
+        let size = raw
+            .depth
+            .checked_mul(raw.height)
+            .and_then(|size| size.checked_mul(raw.width));
+        if size != Some(raw.data.len()) {
+            return Err(format!(
+                "a {}x{}x{} Vec3D cannot hold {} elements",
+                raw.depth,
+                raw.height,
+                raw.width,
+                raw.data.len()
+            ));
+        }
+        Ok(Vec3D {
+            depth: raw.depth,
+            height: raw.height,
+            width: raw.width,
+            data: raw.data,
+        })
+    }
+}
+
 impl<T> Vec3D<T> {
     /// Create a matrix given its height and width, that is filled with a value
     pub fn new(depth: usize, height: usize, width: usize, value: &T) -> Vec3D<T>
@@ -48,8 +87,7 @@ impl<T> Vec3D<T> {
     ) -> Vec3D<T> {
         let generator = &generator;
         let vec = (0..depth)
-            .map(|i| (0..height).map(move |j| (0..width).map(move |k| generator(i, j, k))))
-            .flatten()
+            .flat_map(|i| (0..height).map(move |j| (0..width).map(move |k| generator(i, j, k))))
             .flatten()
             .collect();
         Vec3D::from_vec(vec, depth, height, width)
@@ -65,6 +103,15 @@ impl<T> Vec3D<T> {
         self.data.iter_mut()
     }
 
+    /// Return an iterator which gives the cell index (i,j,k) as well as the value.
+    pub fn iter_enumerate(&self) -> impl Iterator<Item = ((usize, usize, usize), &T)> {
+        let (height, width) = (self.height, self.width);
+        self.data.iter().enumerate().map(move |(index, v)| {
+            let (ij, k) = (index / width, index % width);
+            ((ij / height, ij % height, k), v)
+        })
+    }
+
     /// Get the Vec3D data as a Vec.
     pub fn into_vec(self) -> Vec<T> {
         self.data
@@ -85,13 +132,42 @@ impl<T> Vec3D<T> {
         self.width
     }
 
+    /// Get the element (i, j, k).
+    /// The element is stored at index k + width * (j + i * height), and only
+    /// the index is checked, so an out of bounds coordinate may return another element.
     pub fn get(&self, i: usize, j: usize, k: usize) -> &T {
         &self.data[k + self.width * (j + i * self.height)]
     }
 
+    /// Get a mutable reference to the element (i, j, k).
+    /// See [Vec3D::get] for the bounds checks.
     pub fn get_mut(&mut self, i: usize, j: usize, k: usize) -> &mut T {
         &mut self.data[k + self.width * (j + i * self.height)]
     }
+
+    /// Return true if (i, j, k) is inside the matrix.
+    fn contains(&self, i: usize, j: usize, k: usize) -> bool {
+        i < self.depth && j < self.height && k < self.width
+    }
+
+    /// Get the element (i, j, k), or None if the coordinates are out of bounds.
+    pub fn try_get(&self, i: usize, j: usize, k: usize) -> Option<&T> {
+        if self.contains(i, j, k) {
+            Some(self.get(i, j, k))
+        } else {
+            None
+        }
+    }
+
+    /// Get a mutable reference to the element (i, j, k), or None if the
+    /// coordinates are out of bounds.
+    pub fn try_get_mut(&mut self, i: usize, j: usize, k: usize) -> Option<&mut T> {
+        if self.contains(i, j, k) {
+            Some(self.get_mut(i, j, k))
+        } else {
+            None
+        }
+    }
 }
 
 impl<'a, T> IntoIterator for &'a Vec3D<T> {
@@ -130,6 +206,20 @@ impl<T> IndexMut<(usize, usize)> for Vec3D<T> {
     }
 }
 
+impl<T> Index<(usize, usize, usize)> for Vec3D<T> {
+    type Output = T;
+
+    fn index(&self, (i, j, k): (usize, usize, usize)) -> &Self::Output {
+        self.get(i, j, k)
+    }
+}
+
+impl<T> IndexMut<(usize, usize, usize)> for Vec3D<T> {
+    fn index_mut(&mut self, (i, j, k): (usize, usize, usize)) -> &mut Self::Output {
+        self.get_mut(i, j, k)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -147,4 +237,71 @@ mod tests {
         assert_eq!(vec[(0, 2)][1], 5);
         assert_eq!(vec[(1, 0)][1], 7);
     }
+
+    #[test]
+    fn test_index_element() {
+        let mut vec = Vec3D::from_vec(vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11], 2, 3, 2);
+        assert_eq!(vec[(0, 2, 1)], 5);
+        assert_eq!(vec[(1, 0, 1)], 7);
+        vec[(1, 0, 1)] = 42;
+        assert_eq!(*vec.get(1, 0, 1), 42);
+    }
+
+    #[test]
+    fn test_iter_enumerate() {
+        let vec = Vec3D::from_vec(vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11], 2, 3, 2);
+        assert_eq!(vec.iter_enumerate().count(), 12);
+        for ((i, j, k), value) in vec.iter_enumerate() {
+            assert_eq!(vec.get(i, j, k), value);
+        }
+        assert_eq!(vec.iter_enumerate().nth(7), Some(((1, 0, 1), &7)));
+    }
+
+    #[test]
+    fn test_get_out_of_bounds_aliasing() {
+        // get only checks the flattened index k + width * (j + i * height),
+        // so (0, 0, 2) reads the element stored at (0, 1, 0).
+        let vec = Vec3D::from_vec(vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11], 2, 3, 2);
+        assert_eq!(*vec.get(0, 0, 2), *vec.get(0, 1, 0));
+        assert_eq!(vec.try_get(0, 0, 2), None);
+    }
+
+    #[test]
+    fn test_try_get() {
+        let vec = Vec3D::from_vec(vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11], 2, 3, 2);
+        assert_eq!(vec.try_get(0, 2, 1), Some(&5));
+        assert_eq!(vec.try_get(1, 2, 1), Some(&11));
+        assert_eq!(vec.try_get(2, 0, 0), None);
+        assert_eq!(vec.try_get(0, 3, 0), None);
+        assert_eq!(vec.try_get(0, 0, 2), None);
+    }
+
+    #[test]
+    fn test_try_get_mut() {
+        let mut vec = Vec3D::from_vec(vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11], 2, 3, 2);
+        *vec.try_get_mut(1, 0, 1).unwrap() = 42;
+        assert_eq!(*vec.get(1, 0, 1), 42);
+        assert!(vec.try_get_mut(2, 0, 0).is_none());
+        assert!(vec.try_get_mut(0, 3, 0).is_none());
+        assert!(vec.try_get_mut(0, 0, 2).is_none());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let vec = Vec3D::from_vec((0..12).collect(), 2, 3, 2);
+        let json = serde_json::to_string(&vec).unwrap();
+        assert_eq!(serde_json::from_str::<Vec3D<i32>>(&json).unwrap(), vec);
+
+        let empty: Vec3D<i32> = Vec3D::from_vec(vec![], 0, 3, 2);
+        let json = serde_json::to_string(&empty).unwrap();
+        assert_eq!(serde_json::from_str::<Vec3D<i32>>(&json).unwrap(), empty);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_wrong_length() {
+        let json = r#"{"depth":2,"height":3,"width":2,"data":[0,1,2]}"#;
+        assert!(serde_json::from_str::<Vec3D<i32>>(json).is_err());
+    }
 }
@@ -1,17 +1,107 @@
 //! Contain the Wave struct, that keep track of main wave structure,
 //! which is the possible patterns for each cell, and the entropy of the cell
 
+use crate::neighborhood::Neighborhood;
+#[cfg(feature = "bitvec")]
+use crate::utils::bitvec3d::BitVec3D;
 use crate::utils::vec2d::Vec2D;
 use crate::utils::vec3d::Vec3D;
 use crate::Real;
 use rand::Rng;
-use rand_xorshift::XorShiftRng;
 use std::cmp::Ordering;
+#[cfg(not(feature = "bitvec"))]
 use std::ops::Index;
 
+/// The storage of the wave data, indexed by (row, column, pattern).
+#[cfg(not(feature = "bitvec"))]
+type WaveData = Vec3D<bool>;
+
+/// The storage of the wave data, indexed by (row, column, pattern).
+/// It uses one bit per pattern instead of one byte, but is slower to access.
+#[cfg(feature = "bitvec")]
+type WaveData = BitVec3D;
+
+/// The operations the wave needs on its storage, so that the storage can be
+/// chosen with the bitvec feature.
+trait WaveStorage {
+    /// Create a storage where every element is value.
+    fn filled(depth: usize, height: usize, width: usize, value: bool) -> Self;
+    /// Set every element to value.
+    fn fill_bits(&mut self, value: bool);
+    /// Return true if the element (i, j, k) is set.
+    fn is_set(&self, i: usize, j: usize, k: usize) -> bool;
+    /// Set the element (i, j, k) to true.
+    fn set_bit(&mut self, i: usize, j: usize, k: usize);
+    /// Set the element (i, j, k) to false.
+    fn clear_bit(&mut self, i: usize, j: usize, k: usize);
+}
+
+impl WaveStorage for Vec3D<bool> {
+    fn filled(depth: usize, height: usize, width: usize, value: bool) -> Self {
+        Vec3D::new(depth, height, width, &value)
+    }
+
+    fn fill_bits(&mut self, value: bool) {
+        for b in self {
+            *b = value;
+        }
+    }
+
+    #[inline]
+    fn is_set(&self, i: usize, j: usize, k: usize) -> bool {
+        *self.get(i, j, k)
+    }
+
+    #[inline]
+    fn set_bit(&mut self, i: usize, j: usize, k: usize) {
+        *self.get_mut(i, j, k) = true;
+    }
+
+    #[inline]
+    fn clear_bit(&mut self, i: usize, j: usize, k: usize) {
+        *self.get_mut(i, j, k) = false;
+    }
+}
+
+#[cfg(feature = "bitvec")]
+impl WaveStorage for BitVec3D {
+    fn filled(depth: usize, height: usize, width: usize, value: bool) -> Self {
+        BitVec3D::new(depth, height, width, value)
+    }
+
+    fn fill_bits(&mut self, value: bool) {
+        self.fill(value);
+    }
+
+    #[inline]
+    fn is_set(&self, i: usize, j: usize, k: usize) -> bool {
+        self.get(i, j, k)
+    }
+
+    #[inline]
+    fn set_bit(&mut self, i: usize, j: usize, k: usize) {
+        self.set(i, j, k);
+    }
+
+    #[inline]
+    fn clear_bit(&mut self, i: usize, j: usize, k: usize) {
+        self.clear(i, j, k);
+    }
+}
+
+/// Compute weight * ln(weight), with 0 * ln(0) = 0.
+fn plogp(weight: Real) -> Real {
+    if weight == 0.0 {
+        0.0
+    } else {
+        weight * weight.ln()
+    }
+}
+
 /// Values memoized to compute the entropy. Keeping these allow us to update quickly
 /// the entropy when modifying the wave.
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 struct EntropyMemoizationCell {
     /// The sum of p(pattern) * (log(p(pattern)))
     plogp_sum: Real,
@@ -22,44 +112,64 @@ struct EntropyMemoizationCell {
 }
 
 impl EntropyMemoizationCell {
-    /// Update the values when removing a pattern of weight weight.
-    fn update(&mut self, weight: Real) {
-        self.plogp_sum -= weight * weight.ln();
+    /// Create the memoized values of a cell where every pattern is possible.
+    fn new(weights: &[Real]) -> EntropyMemoizationCell {
+        EntropyMemoizationCell {
+            plogp_sum: weights.iter().map(|x| plogp(*x)).sum(),
+            sum: weights.iter().sum(),
+            n_patterns: weights.len(),
+        }
+    }
+
+    /// Update the values when removing a pattern of weight weight, where
+    /// plogp_weight is plogp(weight).
+    fn update(&mut self, weight: Real, plogp_weight: Real) {
+        self.plogp_sum -= plogp_weight;
         self.sum -= weight;
         self.n_patterns -= 1;
     }
 
-    /// Get the entropy
+    /// Get the entropy.
+    /// If every possible pattern has a zero weight, the patterns are
+    /// considered equiprobable.
     fn entropy(&self) -> Real {
+        if self.sum <= 0.0 {
+            return (self.n_patterns as Real).ln();
+        }
         self.sum.ln() - (self.plogp_sum / self.sum)
     }
 }
 
 /// Values memoized to compute the entropy for each cell.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 struct EntropyMemoization {
     /// The memoization for each cell
     data: Vec2D<EntropyMemoizationCell>,
 }
 
 impl EntropyMemoization {
-    /// Create a new object given the weights of the patterns used in the wave.
-    fn new(weights: &[Real], height: usize, width: usize) -> EntropyMemoization {
-        let sum = weights.iter().sum();
-        let plogp_sum = weights.iter().map(|x| x * x.ln()).sum();
-        let n_patterns = weights.len();
-        let memoization_cell = EntropyMemoizationCell {
-            plogp_sum,
-            sum,
-            n_patterns,
-        };
+    /// Create a new object where every cell has the given memoized values.
+    fn new(
+        memoization_cell: &EntropyMemoizationCell,
+        height: usize,
+        width: usize,
+    ) -> EntropyMemoization {
         EntropyMemoization {
-            data: Vec2D::new(height, width, &memoization_cell),
+            data: Vec2D::new(height, width, memoization_cell),
+        }
+    }
+
+    /// Set every cell to the given memoized values.
+    fn reset(&mut self, memoization_cell: &EntropyMemoizationCell) {
+        for cell in self.data.iter_mut() {
+            *cell = *memoization_cell;
         }
     }
 
     /// Update the memoized values for a cell.
-    fn update(&mut self, y: usize, x: usize, weight: Real) {
-        self.data[y][x].update(weight)
+    fn update(&mut self, y: usize, x: usize, weight: Real, plogp_weight: Real) {
+        self.data[y][x].update(weight, plogp_weight)
     }
 
     /// Get the entropy of a cell.
@@ -70,65 +180,566 @@ impl EntropyMemoization {
 
 /// Contains the list of valid patterns for each cell.
 /// Also, contains information about cell entropy.
+#[derive(Clone)]
 pub struct Wave {
-    /// The wave data. data[index][pattern] is equal to 0 if the pattern can be placed in the cell index
-    data: Vec3D<bool>,
+    /// The wave data. data[index][pattern] is true if the pattern can be placed in the cell index
+    data: WaveData,
     /// The weigths of each pattern
     weights: Vec<Real>,
+    /// The value of plogp for the weight of each pattern, so that no
+    /// logarithm is computed when removing a pattern
+    plogp_weights: Vec<Real>,
     /// The values memoized to compute the entropy of each cell
     entropy_memoization: EntropyMemoization,
+    /// The values memoized for a cell where every pattern is possible, which
+    /// are computed once and copied in every cell on reset
+    initial_memoization: EntropyMemoizationCell,
+    /// The first cell (y, x) that had no possible patterns left,
+    /// and the last pattern that was removed from it.
+    contradiction: Option<(usize, usize, usize)>,
+    /// The number of cells that have exactly one possible pattern.
+    collapsed_cells: usize,
+    /// decided_counts[pattern] is the number of cells where pattern is the
+    /// only possible pattern.
+    decided_counts: Vec<usize>,
+    /// frontier[i][j] is the number of neighbors of cell (i, j) having exactly
+    /// one possible pattern. The neighbors are the ones of a non toric grid.
+    frontier: Vec2D<u8>,
+    /// The neighbors of each cell, used to compute the frontier.
+    neighborhood: Neighborhood,
+    /// The removals done since trailing was enabled, so that they can be
+    /// undone, see [Wave::undo]. None if the removals are not recorded.
+    trail: Option<Vec<TrailEntry>>,
+}
+
+/// A pattern removed from a cell, with the memoized values of the cell before
+/// the removal.
+#[derive(Clone, Debug)]
+struct TrailEntry {
+    y: usize,
+    x: usize,
+    pattern: usize,
+    memoization_cell: EntropyMemoizationCell,
+    /// True if the removal recorded the contradiction of the wave.
+    contradiction: bool,
+}
+
+/// The state of a wave, without the weights of the patterns.
+/// It can be used to save a wave, and to recreate it later.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WaveState {
+    /// The saved wave data.
+    data: WaveData,
+    /// The saved values memoized to compute the entropy.
+    entropy_memoization: EntropyMemoization,
+    /// The saved contradiction.
+    contradiction: Option<(usize, usize, usize)>,
 }
 
 /// Error for some operations dealing with the wave.
 /// Impossible mean that there is a contradiction in the wave, and no solution exists.
+/// It contains the cell (y, x) that had no possible patterns left, and the last
+/// pattern that was removed from it.
 /// Finished mean that every cell is determined
+/// Undetermined mean that the cell (y, x) was expected to be determined, but
+/// still has several possible patterns.
+/// NoPatterns mean that the cells have no possible patterns, without any
+/// pattern being removed from them, for instance when there are no patterns.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum WaveError {
-    Impossible,
+    Impossible { y: usize, x: usize, pattern: usize },
     Finished,
+    Undetermined { y: usize, x: usize },
+    NoPatterns,
+}
+
+/// The heuristic used to choose the next cell to collapse.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Heuristic {
+    /// Choose the cell with the lowest entropy.
+    /// Ties are broken randomly, see [Wave::get_min_entropy].
+    MinEntropy,
+    /// Choose the cell with the lowest entropy.
+    /// Ties are broken by choosing the first cell in row-major order,
+    /// see [Wave::get_min_entropy_first].
+    MinEntropyFirst,
+    /// Choose the first undetermined cell, in row-major order.
+    Scanline,
+    /// Choose the cell with the fewest possible patterns.
+    /// Ties are broken randomly.
+    MostConstrained,
+    /// Choose the cell with the fewest possible patterns, and prefer the
+    /// cells next to a collapsed cell among them. Ties are broken randomly.
+    /// This grows the collapsed region from the first collapsed cell.
+    Frontier,
+}
+
+impl WaveState {
+    /// Get the size of the saved wave, as (rows, cols, patterns).
+    pub fn size(&self) -> (usize, usize, usize) {
+        (self.data.depth(), self.data.height(), self.data.width())
+    }
 }
 
 impl Wave {
     /// Create a new wave where every pattern can be in every cell.
     pub fn new(height: usize, width: usize, weights: Vec<Real>) -> Self {
-        let entropy_memoization = EntropyMemoization::new(&weights, height, width);
-        Wave {
-            data: Vec3D::new(height, width, weights.len(), &true),
+        Wave::new_with_neighborhood(Neighborhood::new_2d(height, width), weights)
+    }
+
+    /// Create a new wave where every pattern can be in every cell, for a grid
+    /// with the given neighborhood, see [Neighborhood].
+    pub fn new_with_neighborhood(neighborhood: Neighborhood, weights: Vec<Real>) -> Self {
+        let height = neighborhood.rows();
+        let width = neighborhood.cols();
+        let initial_memoization = EntropyMemoizationCell::new(&weights);
+        let entropy_memoization = EntropyMemoization::new(&initial_memoization, height, width);
+        let n_patterns = weights.len();
+        let mut wave = Wave {
+            data: WaveData::filled(height, width, weights.len(), true),
+            plogp_weights: weights.iter().map(|&weight| plogp(weight)).collect(),
             weights,
             entropy_memoization,
+            initial_memoization,
+            contradiction: None,
+            collapsed_cells: 0,
+            decided_counts: vec![0; n_patterns],
+            frontier: Vec2D::new(height, width, &0),
+            neighborhood,
+            trail: None,
+        };
+        wave.count_collapsed_cells();
+        wave
+    }
+
+    /// Recreate a wave from a saved state and the weights of the patterns.
+    /// Panic if the state does not contain as many patterns as there are weights.
+    pub fn from_state(state: WaveState, weights: Vec<Real>) -> Self {
+        let neighborhood = Neighborhood::new_2d(state.data.depth(), state.data.height());
+        Wave::from_state_with_neighborhood(state, neighborhood, weights)
+    }
+
+    /// Recreate a wave from a saved state and the weights of the patterns, for
+    /// a grid with the given neighborhood.
+    /// Panic if the state does not contain as many patterns as there are
+    /// weights, or if its size is not the one of the neighborhood.
+    pub fn from_state_with_neighborhood(
+        state: WaveState,
+        neighborhood: Neighborhood,
+        weights: Vec<Real>,
+    ) -> Self {
+        assert_eq!(state.data.width(), weights.len());
+        assert_eq!(
+            (state.data.depth(), state.data.height()),
+            (neighborhood.rows(), neighborhood.cols())
+        );
+        let mut wave = Wave {
+            neighborhood,
+            frontier: Vec2D::new(state.data.depth(), state.data.height(), &0),
+            decided_counts: vec![0; weights.len()],
+            data: state.data,
+            plogp_weights: weights.iter().map(|&weight| plogp(weight)).collect(),
+            initial_memoization: EntropyMemoizationCell::new(&weights),
+            weights,
+            entropy_memoization: state.entropy_memoization,
+            contradiction: state.contradiction,
+            collapsed_cells: 0,
+            trail: None,
+        };
+        wave.count_collapsed_cells();
+        wave
+    }
+
+    /// Save the state of the wave, without the weights of the patterns.
+    pub fn state(&self) -> WaveState {
+        WaveState {
+            data: self.data.clone(),
+            entropy_memoization: self.entropy_memoization.clone(),
+            contradiction: self.contradiction,
         }
     }
 
-    /// Set every element in the wave to true
+    /// Get the weights of the patterns.
+    pub fn weights(&self) -> &[Real] {
+        &self.weights
+    }
+
+    /// Set every element in the wave to true.
+    /// The recorded removals are dropped, see [Wave::set_trailing].
     pub fn reset(&mut self) {
-        for i in &mut self.data {
-            *i = true;
+        self.data.fill_bits(true);
+        self.entropy_memoization.reset(&self.initial_memoization);
+        self.contradiction = None;
+        if let Some(trail) = &mut self.trail {
+            trail.clear();
+        }
+        self.count_collapsed_cells();
+    }
+
+    /// Enable or disable the recording of the removed patterns, so that they
+    /// can be undone with [Wave::undo]. Disabling it drops the recorded removals.
+    pub fn set_trailing(&mut self, trailing: bool) {
+        match (trailing, &self.trail) {
+            (true, None) => self.trail = Some(vec![]),
+            (false, _) => self.trail = None,
+            _ => (),
+        }
+    }
+
+    /// Get the number of recorded removals, which can later be given to
+    /// [Wave::undo]. Return 0 if the removals are not recorded.
+    pub fn trail_len(&self) -> usize {
+        self.trail.as_ref().map_or(0, Vec::len)
+    }
+
+    /// Undo the recorded removals, until only trail_len of them are left.
+    /// The wave is then in the same state as when [Wave::trail_len] returned
+    /// trail_len.
+    pub fn undo(&mut self, trail_len: usize) {
+        let mut trail = match self.trail.take() {
+            Some(trail) => trail,
+            None => return,
+        };
+        while trail.len() > trail_len {
+            let entry = trail.pop().unwrap();
+            let (i, j) = (entry.y, entry.x);
+            match self.entropy_memoization.data[i][j].n_patterns {
+                1 => {
+                    self.collapsed_cells -= 1;
+                    let decided = self.decided_pattern(i, j);
+                    self.decided_counts[decided] -= 1;
+                    self.update_frontier(i, j, false);
+                }
+                0 => {
+                    self.collapsed_cells += 1;
+                    self.decided_counts[entry.pattern] += 1;
+                    self.update_frontier(i, j, true);
+                }
+                _ => (),
+            }
+            if entry.contradiction {
+                self.contradiction = None;
+            }
+            self.data.set_bit(i, j, entry.pattern);
+            self.entropy_memoization.data[i][j] = entry.memoization_cell;
+        }
+        self.trail = Some(trail);
+    }
+
+    /// Compute the number of cells having exactly one possible pattern,
+    /// and the cells next to them.
+    fn count_collapsed_cells(&mut self) {
+        self.collapsed_cells = 0;
+        for value in self.frontier.iter_mut() {
+            *value = 0;
+        }
+        for count in &mut self.decided_counts {
+            *count = 0;
+        }
+        for i in 0..self.rows() {
+            for j in 0..self.cols() {
+                if self.is_collapsed(i, j) {
+                    self.collapsed_cells += 1;
+                    let decided = self.decided_pattern(i, j);
+                    self.decided_counts[decided] += 1;
+                    self.update_frontier(i, j, true);
+                }
+            }
+        }
+    }
+
+    /// Get the first possible pattern of the cell (i, j), which is its only
+    /// possible pattern if the cell is collapsed.
+    fn decided_pattern(&self, i: usize, j: usize) -> usize {
+        self.cell(i, j)
+            .position(|b| b)
+            .expect("the cell has a possible pattern")
+    }
+
+    /// Update the frontier when the cell (i, j) becomes collapsed, or when it
+    /// stops being collapsed if collapsed is false.
+    fn update_frontier(&mut self, i: usize, j: usize, collapsed: bool) {
+        for direction in 0..self.neighborhood.n_directions() {
+            if let Some((y, x)) = self.neighborhood.neighbor((i, j), direction, false) {
+                let count = &mut self.frontier[y][x];
+                if collapsed {
+                    *count += 1;
+                } else {
+                    *count -= 1;
+                }
+            }
+        }
+    }
+
+    /// Return true if a neighbor of cell (i, j) has exactly one possible
+    /// pattern, where the grid is considered non toric.
+    pub fn is_on_frontier(&self, i: usize, j: usize) -> bool {
+        self.frontier[i][j] > 0
+    }
+
+    /// Get the number of cells having exactly one possible pattern.
+    pub fn collapsed_cells(&self) -> usize {
+        self.collapsed_cells
+    }
+
+    /// Get, for each pattern, the number of cells where it is the only
+    /// possible pattern, whether the cell was collapsed or decided by the
+    /// propagation.
+    pub fn decided_counts(&self) -> &[usize] {
+        &self.decided_counts
+    }
+
+    /// Change the weight of a pattern, and recompute the entropy of every cell.
+    /// The recorded removals are dropped, since they contain the entropies
+    /// computed with the previous weight.
+    pub fn set_weight(&mut self, pattern: usize, weight: Real) {
+        assert!(weight >= 0.0);
+        if let Some(trail) = &mut self.trail {
+            trail.clear();
+        }
+        self.weights[pattern] = weight;
+        self.plogp_weights[pattern] = plogp(weight);
+        self.initial_memoization = EntropyMemoizationCell::new(&self.weights);
+        for i in 0..self.rows() {
+            for j in 0..self.cols() {
+                let mut memoization_cell = EntropyMemoizationCell {
+                    plogp_sum: 0.0,
+                    sum: 0.0,
+                    n_patterns: 0,
+                };
+                let weights = self.weights.iter().zip(&self.plogp_weights);
+                for (b, (weight, plogp_weight)) in self.cell(i, j).zip(weights) {
+                    if b {
+                        memoization_cell.plogp_sum += plogp_weight;
+                        memoization_cell.sum += weight;
+                        memoization_cell.n_patterns += 1;
+                    }
+                }
+                self.entropy_memoization.data[i][j] = memoization_cell;
+            }
         }
-        self.entropy_memoization =
-            EntropyMemoization::new(&self.weights, self.height(), self.width());
     }
 
     /// Return true if pattern can be placed in cell (i, j).
     pub fn get(&self, i: usize, j: usize, pattern: usize) -> bool {
-        *self.data.get(i, j, pattern)
+        self.data.is_set(i, j, pattern)
     }
 
     /// Remove pattern from the wave on cell (i, j).
     /// This means that pattern cannot be placed in cell (i, j).
     pub fn unset(&mut self, i: usize, j: usize, pattern: usize) {
-        if *self.data.get(i, j, pattern) {
-            *self.data.get_mut(i, j, pattern) = false;
-            self.entropy_memoization.update(i, j, self.weights[pattern]);
+        if self.data.is_set(i, j, pattern) {
+            self.data.clear_bit(i, j, pattern);
+            let memoization_cell = self.entropy_memoization.data[i][j];
+            self.entropy_memoization.update(
+                i,
+                j,
+                self.weights[pattern],
+                self.plogp_weights[pattern],
+            );
+            let mut contradiction = false;
+            match self.entropy_memoization.data[i][j].n_patterns {
+                1 => {
+                    self.collapsed_cells += 1;
+                    let decided = self.decided_pattern(i, j);
+                    self.decided_counts[decided] += 1;
+                    self.update_frontier(i, j, true);
+                }
+                0 => {
+                    self.collapsed_cells -= 1;
+                    self.decided_counts[pattern] -= 1;
+                    self.update_frontier(i, j, false);
+                    if self.contradiction.is_none() {
+                        self.contradiction = Some((i, j, pattern));
+                        contradiction = true;
+                    }
+                }
+                _ => (),
+            }
+            if let Some(trail) = &mut self.trail {
+                trail.push(TrailEntry {
+                    y: i,
+                    x: j,
+                    pattern,
+                    memoization_cell,
+                    contradiction,
+                });
+            }
         }
     }
 
+    /// Return an iterator giving, for each pattern, true if the pattern can be
+    /// placed in cell (i, j).
+    pub fn cell(&self, i: usize, j: usize) -> impl Iterator<Item = bool> + '_ {
+        (0..self.n_patterns()).map(move |pattern| self.data.is_set(i, j, pattern))
+    }
+
+    /// Return an iterator on the patterns that can still be placed in cell (i, j).
+    pub fn valid_patterns(&self, i: usize, j: usize) -> impl Iterator<Item = usize> + '_ {
+        self.cell(i, j)
+            .enumerate()
+            .filter(|(_, b)| *b)
+            .map(|(pattern, _)| pattern)
+    }
+
+    /// Get the number of patterns that can still be placed in cell (i, j).
+    pub fn possibilities(&self, i: usize, j: usize) -> usize {
+        self.entropy_memoization.data[i][j].n_patterns
+    }
+
+    /// Return true if exactly one pattern can be placed in cell (i, j).
+    pub fn is_collapsed(&self, i: usize, j: usize) -> bool {
+        self.possibilities(i, j) == 1
+    }
+
     /// Get the entropy of cell (i, j).
     pub fn get_entropy(&self, i: usize, j: usize) -> Real {
         self.entropy_memoization.entropy(i, j)
     }
 
-    pub fn get_min_entropy(&self, rng_gen: &mut XorShiftRng) -> Result<(usize, usize), WaveError> {
-        let mut min = std::f64::INFINITY as Real;
-        let mut min_random = std::i32::MAX;
+    /// Get the entropy of every cell.
+    /// The cells with one pattern or none left have an entropy of 0.
+    pub fn entropy_grid(&self) -> Vec2D<Real> {
+        self.entropy_memoization.data.map(|memoization| {
+            if memoization.n_patterns <= 1 {
+                0.0
+            } else {
+                memoization.entropy()
+            }
+        })
+    }
+
+    /// Get a copy of the wave, where cube.get(i, j, pattern) is true if pattern
+    /// can be placed in cell (i, j).
+    pub fn possibility_cube(&self) -> Vec3D<bool> {
+        Vec3D::new_generator(self.rows(), self.cols(), self.n_patterns(), |i, j, k| {
+            self.data.is_set(i, j, k)
+        })
+    }
+
+    /// Get a mask of the cells where exactly one pattern can be placed.
+    pub fn collapsed_mask(&self) -> Vec2D<bool> {
+        self.entropy_memoization
+            .data
+            .map(|memoization| memoization.n_patterns == 1)
+    }
+
+    /// Get the next cell to collapse, given the heuristic to use.
+    pub fn get_next_cell<R: Rng>(
+        &self,
+        heuristic: Heuristic,
+        rng_gen: &mut R,
+    ) -> Result<(usize, usize), WaveError> {
+        match heuristic {
+            Heuristic::MinEntropy => self.get_min_entropy(rng_gen),
+            Heuristic::MinEntropyFirst => self.get_min_entropy_first(),
+            Heuristic::Scanline => self.get_first_undetermined(),
+            Heuristic::MostConstrained => self.get_min_patterns(rng_gen),
+            Heuristic::Frontier => self.get_min_patterns_frontier(rng_gen),
+        }
+    }
+
+    /// Get the first cell in row-major order that has more than one possible pattern.
+    pub fn get_first_undetermined(&self) -> Result<(usize, usize), WaveError> {
+        for ((i, j), memoization) in self.entropy_memoization.data.iter_enumerate() {
+            match memoization.n_patterns {
+                0 => return Err(self.impossible()),
+                1 => (),
+                _ => return Ok((i, j)),
+            }
+        }
+        Err(WaveError::Finished)
+    }
+
+    /// Get the cell with the fewest possible patterns, that has more than one
+    /// possible pattern. Ties are broken randomly.
+    pub fn get_min_patterns<R: Rng>(&self, rng_gen: &mut R) -> Result<(usize, usize), WaveError> {
+        self.get_min_key(rng_gen, |_, n_patterns| n_patterns)
+    }
+
+    /// Get the cell with the fewest possible patterns, that has more than one
+    /// possible pattern. Among them, the cells on the frontier are preferred,
+    /// see [Wave::is_on_frontier]. Ties are broken randomly.
+    pub fn get_min_patterns_frontier<R: Rng>(
+        &self,
+        rng_gen: &mut R,
+    ) -> Result<(usize, usize), WaveError> {
+        self.get_min_key(rng_gen, |(i, j), n_patterns| {
+            (n_patterns, self.frontier[i][j] == 0)
+        })
+    }
+
+    /// Get the cell with the lowest key, that has more than one possible
+    /// pattern. The key of a cell is computed from its position and its number
+    /// of possible patterns. Ties are broken randomly.
+    fn get_min_key<R, K, F>(&self, rng_gen: &mut R, key: F) -> Result<(usize, usize), WaveError>
+    where
+        R: Rng,
+        K: Ord,
+        F: Fn((usize, usize), usize) -> K,
+    {
+        let mut min = None;
+        let mut min_random = i32::MAX;
+        let mut argmin = None;
+
+        for ((i, j), memoization) in self.entropy_memoization.data.iter_enumerate() {
+            let n_patterns = memoization.n_patterns;
+            if n_patterns == 1 {
+                continue;
+            }
+            if n_patterns == 0 {
+                return Err(self.impossible());
+            }
+
+            let cell_key = key((i, j), n_patterns);
+            let ordering = match &min {
+                Some(min) => cell_key.cmp(min),
+                None => Ordering::Less,
+            };
+            match ordering {
+                Ordering::Less => {
+                    min = Some(cell_key);
+                    argmin = Some((i, j));
+                    min_random = rng_gen.gen();
+                }
+                Ordering::Equal => {
+                    let random = rng_gen.gen();
+                    if random < min_random {
+                        min_random = random;
+                        argmin = Some((i, j));
+                    }
+                }
+                Ordering::Greater => (),
+            }
+        }
+
+        argmin.ok_or(WaveError::Finished)
+    }
+
+    /// Get the cell with the lowest entropy, that has more than one
+    /// possible pattern. Ties are broken randomly: the cells are visited in
+    /// row-major order, and each cell lowering the minimum entropy draws a
+    /// random i32. Each cell with an entropy equal to the minimum also draws
+    /// one, and replaces the chosen cell if its number is strictly lower.
+    pub fn get_min_entropy<R: Rng>(&self, rng_gen: &mut R) -> Result<(usize, usize), WaveError> {
+        self.get_min_entropy_with_noise(rng_gen, 0.0)
+    }
+
+    /// Get the cell with the lowest entropy, as get_min_entropy, where a
+    /// random number between 0 and noise is added to the entropy of each cell,
+    /// so that the cells with almost the same entropy are chosen randomly.
+    /// A noise of 0 draws no random number, and gives the same cell as
+    /// get_min_entropy.
+    pub fn get_min_entropy_with_noise<R: Rng>(
+        &self,
+        rng_gen: &mut R,
+        noise: Real,
+    ) -> Result<(usize, usize), WaveError> {
+        let mut min = Real::INFINITY;
+        let mut min_random = i32::MAX;
         let mut argmin = (-1, -1);
 
         for ((i, j), memoization) in self.entropy_memoization.data.iter_enumerate() {
@@ -137,10 +748,13 @@ impl Wave {
                 continue;
             }
             if n_patterns == 0 {
-                return Err(WaveError::Impossible);
+                return Err(self.impossible());
             }
 
-            let entropy = memoization.entropy();
+            let mut entropy = memoization.entropy();
+            if noise > 0.0 {
+                entropy += rng_gen.gen::<Real>() * noise;
+            }
             match entropy.partial_cmp(&min) {
                 Some(Ordering::Less) => {
                     min = entropy;
@@ -166,17 +780,91 @@ impl Wave {
         }
     }
 
+    /// Get the cell with the lowest entropy, that has more than one
+    /// possible pattern. Ties are broken by choosing the lowest (i, j) index,
+    /// that is the first cell in row-major order, without using randomness.
+    pub fn get_min_entropy_first(&self) -> Result<(usize, usize), WaveError> {
+        let mut min = Real::INFINITY;
+        let mut argmin = None;
+
+        for ((i, j), memoization) in self.entropy_memoization.data.iter_enumerate() {
+            let n_patterns = memoization.n_patterns;
+            if n_patterns == 1 {
+                continue;
+            }
+            if n_patterns == 0 {
+                return Err(self.impossible());
+            }
+
+            let entropy = memoization.entropy();
+            if argmin.is_none() || entropy < min {
+                min = entropy;
+                argmin = Some((i, j));
+            }
+        }
+
+        argmin.ok_or(WaveError::Finished)
+    }
+
+    /// Return true if a cell has no possible patterns left.
+    pub fn has_contradiction(&self) -> bool {
+        self.contradiction.is_some()
+    }
+
+    /// Return WaveError::Impossible if a cell has no possible patterns left.
+    pub fn check_contradiction(&self) -> Result<(), WaveError> {
+        match self.contradiction {
+            Some((y, x, pattern)) => Err(WaveError::Impossible { y, x, pattern }),
+            None => Ok(()),
+        }
+    }
+
+    /// Get the error describing the contradiction in the wave.
+    /// Should only be called when a cell has no possible patterns left.
+    pub(crate) fn impossible(&self) -> WaveError {
+        match self.contradiction {
+            Some((y, x, pattern)) => WaveError::Impossible { y, x, pattern },
+            None => WaveError::NoPatterns,
+        }
+    }
+
+    /// Get the number of rows of the wave.
+    /// The wave data is stored in a 3D matrix indexed by (row, column, pattern).
+    pub fn rows(&self) -> usize {
+        self.data.depth()
+    }
+
+    /// Get the number of columns of the wave.
+    pub fn cols(&self) -> usize {
+        self.data.height()
+    }
+
+    /// Get the number of patterns.
+    pub fn n_patterns(&self) -> usize {
+        self.data.width()
+    }
+
+    /// Get the neighborhood of the grid stored in the wave.
+    pub fn neighborhood(&self) -> Neighborhood {
+        self.neighborhood
+    }
+
     /// Get the wave height
+    #[deprecated(note = "use Wave::rows instead")]
     pub fn height(&self) -> usize {
-        self.data.depth()
+        self.rows()
     }
 
     /// Get the wave width
+    #[deprecated(note = "use Wave::cols instead")]
     pub fn width(&self) -> usize {
-        self.data.height()
+        self.cols()
     }
 }
 
+/// Get, for each pattern, true if the pattern can be placed in the cell (i, j).
+/// This is not available with the bitvec feature, where the booleans are packed.
+#[cfg(not(feature = "bitvec"))]
 impl Index<(usize, usize)> for Wave {
     type Output = [bool];
 
@@ -184,3 +872,256 @@ impl Index<(usize, usize)> for Wave {
         &self.data[i]
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_dimensions() {
+        let wave = Wave::new(5, 3, vec![1.0; 7]);
+        assert_eq!(wave.rows(), 5);
+        assert_eq!(wave.cols(), 3);
+        assert_eq!(wave.n_patterns(), 7);
+        assert_eq!(wave.height(), 5);
+        assert_eq!(wave.width(), 3);
+    }
+
+    #[test]
+    fn test_reset_memoization() {
+        let weights = vec![1.0, 2.0, 0.5];
+        let mut wave = Wave::new(3, 2, weights.clone());
+        wave.unset(0, 1, 1);
+        wave.unset(2, 0, 0);
+        wave.unset(2, 0, 2);
+        wave.reset();
+        let fresh = Wave::new(3, 2, weights);
+        assert_eq!(wave.entropy_memoization, fresh.entropy_memoization);
+
+        wave.set_weight(1, 4.0);
+        wave.unset(1, 1, 2);
+        wave.reset();
+        let fresh = Wave::new(3, 2, vec![1.0, 4.0, 0.5]);
+        assert_eq!(wave.entropy_memoization, fresh.entropy_memoization);
+    }
+
+    #[test]
+    fn test_possibilities() {
+        let mut wave = Wave::new(2, 2, vec![1.0; 3]);
+        assert_eq!(wave.possibilities(1, 0), 3);
+        wave.unset(1, 0, 2);
+        wave.unset(1, 0, 2);
+        assert_eq!(wave.possibilities(1, 0), 2);
+        assert!(!wave.is_collapsed(1, 0));
+        wave.unset(1, 0, 0);
+        assert_eq!(wave.possibilities(1, 0), 1);
+        assert!(wave.is_collapsed(1, 0));
+        assert_eq!(wave.possibilities(0, 0), 3);
+    }
+
+    #[test]
+    fn test_no_patterns() {
+        let wave = Wave::new(2, 3, vec![]);
+        assert_eq!(wave.get_first_undetermined(), Err(WaveError::NoPatterns));
+        assert_eq!(wave.get_min_entropy_first(), Err(WaveError::NoPatterns));
+    }
+
+    #[test]
+    fn test_min_entropy_near_equal_weights() {
+        use rand::SeedableRng;
+        use rand_xorshift::XorShiftRng;
+
+        // Many patterns with almost the same weight. Removing the heaviest pattern
+        // from the cell (0, 1) leaves it with a slightly lower entropy than removing
+        // the lightest pattern from the cell (0, 0).
+        let weights: Vec<f64> = (0..200).map(|i| 1.0 + (i as f64) * 1e-4).collect();
+        let exact_entropy = |removed: usize| {
+            let remaining = weights.iter().enumerate().filter(|&(i, _)| i != removed);
+            let sum: f64 = remaining.clone().map(|(_, w)| w).sum();
+            let plogp_sum: f64 = remaining.map(|(_, w)| w * w.ln()).sum();
+            sum.ln() - plogp_sum / sum
+        };
+        assert!(exact_entropy(199) < exact_entropy(0));
+
+        let weights: Vec<Real> = weights.iter().map(|&w| w as Real).collect();
+        let mut chosen = HashSet::new();
+        for seed in 0..16 {
+            let mut wave = Wave::new(1, 2, weights.clone());
+            wave.unset(0, 0, 0);
+            wave.unset(0, 1, 199);
+            if cfg!(feature = "f64") {
+                assert!(wave.get_entropy(0, 1) < wave.get_entropy(0, 0));
+            } else {
+                // With f32, both entropies are rounded to the same value.
+                assert_eq!(wave.get_entropy(0, 1), wave.get_entropy(0, 0));
+            }
+            let mut rng = XorShiftRng::from_seed([seed + 1; 16]);
+            chosen.insert(wave.get_min_entropy(&mut rng).unwrap());
+        }
+        if cfg!(feature = "f64") {
+            assert_eq!(chosen, [(0, 1)].iter().cloned().collect());
+        } else {
+            assert_eq!(chosen.len(), 2);
+        }
+    }
+
+    #[test]
+    fn test_set_weight() {
+        let mut wave = Wave::new(2, 2, vec![1.0, 1.0, 2.0]);
+        wave.unset(0, 1, 0);
+        wave.set_weight(2, 1.0);
+        let expected = Wave::new(2, 2, vec![1.0, 1.0]);
+        assert!((wave.get_entropy(0, 1) - expected.get_entropy(0, 0)).abs() < 1e-6);
+        assert_eq!(wave.possibilities(0, 1), 2);
+
+        wave.set_weight(2, 0.0);
+        assert!(wave.get_entropy(0, 1).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_min_entropy_ties() {
+        use rand::SeedableRng;
+        use rand_xorshift::XorShiftRng;
+
+        let mut wave = Wave::new(2, 2, vec![1.0, 1.0, 1.0]);
+        wave.unset(0, 0, 0);
+        wave.unset(1, 1, 0);
+        wave.unset(1, 0, 1);
+        wave.unset(1, 0, 2);
+        assert_eq!(wave.get_entropy(0, 0), wave.get_entropy(1, 1));
+
+        assert_eq!(wave.get_min_entropy_first(), Ok((0, 0)));
+        let mut rng_gen = XorShiftRng::from_seed([3; 16]);
+        assert_eq!(wave.get_min_entropy(&mut rng_gen), Ok((1, 1)));
+    }
+
+    #[test]
+    fn test_min_entropy_noise() {
+        use rand::SeedableRng;
+        use rand_xorshift::XorShiftRng;
+
+        // The cell (1, 1) has a slightly lower entropy than the cell (0, 0).
+        let mut wave = Wave::new(2, 2, vec![1.0, 1.0, 1.1]);
+        wave.unset(0, 0, 2);
+        wave.unset(1, 1, 0);
+        wave.unset(1, 0, 1);
+        wave.unset(1, 0, 2);
+        assert!(wave.get_entropy(1, 1) < wave.get_entropy(0, 0));
+
+        let mut picked = vec![];
+        for seed in 0..16 {
+            let mut rng = XorShiftRng::seed_from_u64(seed);
+            let mut noisy_rng = rng.clone();
+            let cell = wave.get_min_entropy(&mut rng);
+            assert_eq!(wave.get_min_entropy_with_noise(&mut noisy_rng, 0.0), cell);
+            assert_eq!(cell, Ok((1, 1)));
+
+            let mut rng = XorShiftRng::seed_from_u64(seed);
+            picked.push(wave.get_min_entropy_with_noise(&mut rng, 1.0).unwrap());
+        }
+        assert!(picked.contains(&(0, 0)));
+        assert!(picked.contains(&(1, 1)));
+    }
+
+    #[test]
+    fn test_collapsed_cells() {
+        let mut wave = Wave::new(2, 2, vec![1.0, 1.0]);
+        assert_eq!(wave.collapsed_cells(), 0);
+        wave.unset(0, 1, 0);
+        assert_eq!(wave.collapsed_cells(), 1);
+        wave.unset(0, 1, 1);
+        assert_eq!(wave.collapsed_cells(), 0);
+        wave.unset(1, 1, 1);
+        assert_eq!(wave.collapsed_cells(), 1);
+        assert_eq!(
+            Wave::from_state(wave.state(), vec![1.0, 1.0]).collapsed_cells(),
+            1
+        );
+        wave.reset();
+        assert_eq!(wave.collapsed_cells(), 0);
+        assert_eq!(Wave::new(2, 3, vec![1.0]).collapsed_cells(), 6);
+    }
+
+    #[test]
+    fn test_valid_patterns() {
+        let mut wave = Wave::new(2, 2, vec![1.0, 1.0, 1.0]);
+        assert_eq!(wave.valid_patterns(1, 0).collect::<Vec<_>>(), vec![0, 1, 2]);
+        wave.unset(1, 0, 1);
+        assert_eq!(wave.valid_patterns(1, 0).collect::<Vec<_>>(), vec![0, 2]);
+        assert_eq!(wave.valid_patterns(0, 0).count(), 3);
+    }
+
+    #[test]
+    fn test_entropy_grid() {
+        let mut wave = Wave::new(2, 3, vec![1.0, 1.0, 2.0]);
+        let entropy = wave.get_entropy(0, 0);
+        assert!(entropy > 0.0);
+        assert!(wave.entropy_grid().iter().all(|&e| e == entropy));
+
+        wave.unset(1, 2, 0);
+        wave.unset(1, 2, 1);
+        let grid = wave.entropy_grid();
+        assert_eq!(grid[1][2], 0.0);
+        assert_eq!(grid[0][2], entropy);
+    }
+
+    #[test]
+    fn test_possibility_cube() {
+        let mut wave = Wave::new(2, 3, vec![1.0, 1.0]);
+        wave.unset(1, 2, 0);
+        let cube = wave.possibility_cube();
+        assert_eq!((cube.depth(), cube.height(), cube.width()), (2, 3, 2));
+        for ((i, j, pattern), &possible) in cube.iter_enumerate() {
+            assert_eq!(possible, (i, j, pattern) != (1, 2, 0));
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "bitvec"))]
+    fn test_index() {
+        let mut wave = Wave::new(2, 3, vec![1.0, 1.0]);
+        wave.unset(1, 2, 0);
+        assert_eq!(&wave[(1, 2)], &[false, true]);
+        assert_eq!(&wave[(0, 0)], &[true, true]);
+    }
+
+    #[test]
+    fn test_frontier() {
+        use rand::SeedableRng;
+        use rand_xorshift::XorShiftRng;
+
+        let mut wave = Wave::new(3, 3, vec![1.0, 1.0]);
+        assert!(wave.frontier.iter().all(|&count| count == 0));
+        wave.unset(1, 0, 0);
+        for ((i, j), &count) in wave.frontier.iter_enumerate() {
+            assert_eq!(count > 0, [(0, 0), (1, 1), (2, 0)].contains(&(i, j)));
+        }
+        let mut rng = XorShiftRng::from_seed([0; 16]);
+        for _ in 0..10 {
+            let (i, j) = wave.get_min_patterns_frontier(&mut rng).unwrap();
+            assert!(wave.is_on_frontier(i, j));
+        }
+
+        let restored = Wave::from_state(wave.state(), vec![1.0, 1.0]);
+        assert_eq!(restored.frontier, wave.frontier);
+        wave.reset();
+        assert!(wave.frontier.iter().all(|&count| count == 0));
+    }
+
+    #[test]
+    fn test_frontier_3d() {
+        // The cell (z, y, x) = (0, 1, 0) is stored in the cell (1, 0). The
+        // cell (2, 0) is (1, 0, 0), which is not one of its neighbors.
+        let neighborhood = Neighborhood::new_3d(2, 2, 2);
+        let mut wave = Wave::new_with_neighborhood(neighborhood, vec![1.0, 1.0]);
+        wave.unset(1, 0, 0);
+        for ((i, j), &count) in wave.frontier.iter_enumerate() {
+            assert_eq!(count > 0, [(0, 0), (1, 1), (3, 0)].contains(&(i, j)));
+        }
+
+        let restored = Wave::from_state_with_neighborhood(wave.state(), neighborhood, vec![1.0; 2]);
+        assert_eq!(restored.frontier, wave.frontier);
+    }
+}
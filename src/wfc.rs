@@ -1,115 +1,1375 @@
 use crate::direction::*;
+use crate::neighborhood::Neighborhood;
 use crate::propagator::*;
 use crate::utils::vec2d::*;
-use crate::wave::WaveError;
+use crate::utils::vec3d::Vec3D;
+use crate::wave::{Heuristic, Wave, WaveError};
 use crate::Real;
 use rand::distributions::*;
-use rand::SeedableRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
 use rand_xorshift::XorShiftRng;
 
-pub struct WFC {
+/// Expand a u64 seed into the 16 bytes seed used by the random number generator.
+/// The first 8 bytes are the little-endian representation of seed, and the
+/// last 8 bytes are the little-endian representation of its bitwise negation.
+/// The resulting seed is thus never made only of zeros.
+pub fn seed_from_u64(seed: u64) -> [u8; 16] {
+    let mut bytes = [0; 16];
+    bytes[..8].copy_from_slice(&seed.to_le_bytes());
+    bytes[8..].copy_from_slice(&(!seed).to_le_bytes());
+    bytes
+}
+
+/// Draw a fresh seed from the thread-local random number generator.
+/// The results obtained with this seed are not reproducible.
+pub fn random_seed() -> [u8; 16] {
+    rand::thread_rng().gen()
+}
+
+/// Derive a new seed from a seed and an index, so that each index gives a
+/// different seed. Each half of the seed is read as a little-endian u64,
+/// offset by index, and mixed with the splitmix64 finalizer.
+pub fn derive_seed(seed: [u8; 16], index: u64) -> [u8; 16] {
+    let mix = |mut z: u64| {
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    };
+    let mut low = [0; 8];
+    let mut high = [0; 8];
+    low.copy_from_slice(&seed[..8]);
+    high.copy_from_slice(&seed[8..]);
+    let offset = index.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    let low = mix(u64::from_le_bytes(low).wrapping_add(offset));
+    let high = mix(u64::from_le_bytes(high) ^ offset);
+
+    let mut bytes = [0; 16];
+    bytes[..8].copy_from_slice(&low.to_le_bytes());
+    bytes[8..].copy_from_slice(&high.to_le_bytes());
+    bytes
+}
+
+/// Information about the progress of a run, given after each step.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProgressInfo {
+    /// The number of cells having exactly one possible pattern.
+    pub collapsed: usize,
+    /// The number of cells.
+    pub total: usize,
+    /// The number of steps done since the start of the run.
+    pub steps: usize,
+}
+
+/// The WFC algorithm, using the random number generator R, on a grid whose
+/// cells have N neighbors, see [Propagator].
+pub struct WFC<R = XorShiftRng, const N: usize = 4> {
     /// The random number generator
-    rng_gen: XorShiftRng,
+    rng_gen: R,
     /// The distribution of patterns
     patterns_weights: Vec<Real>,
     /// The propagator, that is used to propagate the information
-    propagator: Propagator,
+    propagator: Propagator<N>,
+    /// The heuristic used to choose the next cell to collapse
+    heuristic: Heuristic,
+    /// The patterns that are removed from every cell, even after a restart
+    banned_patterns: Vec<usize>,
+    /// Is the choice of the patterns biased toward the target frequencies
+    frequency_matching: bool,
+    /// The cells (y, x) forced to a pattern with observe since the last restart
+    observations: Vec<(usize, usize, usize)>,
+    /// The maximal random noise added to the entropies with Heuristic::MinEntropy
+    entropy_noise: Real,
+    /// The temperature applied to the weights when choosing a pattern
+    temperature: Real,
 }
 
-impl WFC {
+/// A saved state of a WFC run, that can be used to resume the run later.
+/// It contains the state of the wave, the compatible counts and the propagating
+/// queue of the propagator, the state of the random number generator, and the
+/// settings of the run, such as the heuristic, the banned patterns and the
+/// observations.
+/// The compatibilities of the patterns are not saved, since they are
+/// deterministic given the input: they should be computed again and given
+/// to WFC::new, before calling WFC::resume on the new WFC. The weights are
+/// saved, so that resuming with different weights fails.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WfcCheckpoint<R = XorShiftRng, const N: usize = 4> {
+    /// The saved propagator state
+    propagator: PropagatorState<N>,
+    /// The saved random number generator
+    rng_gen: R,
+    /// The saved weights of the patterns
+    patterns_weights: Vec<Real>,
+    /// The saved heuristic
+    heuristic: Heuristic,
+    /// The saved banned patterns
+    banned_patterns: Vec<usize>,
+    /// The saved frequency matching setting
+    frequency_matching: bool,
+    /// The saved observations
+    observations: Vec<(usize, usize, usize)>,
+    /// The saved entropy noise
+    entropy_noise: Real,
+    /// The saved temperature
+    temperature: Real,
+}
+
+/// The reasons a checkpoint cannot be resumed by a WFC.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CheckpointError {
+    /// The checkpoint does not have the same number of patterns as the WFC.
+    PatternCount,
+    /// The checkpoint does not have the same pattern weights as the WFC.
+    Weights,
+    /// The checkpoint does not have the same dimensions as the WFC.
+    Dimensions,
+}
+
+impl WFC<XorShiftRng> {
     /// Create the object containing all the information to perform the WFC
-    /// algorithm.
-    pub fn new(
+    /// algorithm, given a u64 seed.
+    /// See [seed_from_u64] for the way the seed is expanded.
+    pub fn from_u64_seed(
         is_toric: bool,
-        seed: [u8; 16],
+        seed: u64,
         patterns_weights: Vec<Real>,
         patterns_compatibility: Vec<DirArray<Vec<usize>>>,
         height: usize,
         width: usize,
     ) -> Self {
-        let propagator = Propagator::new(
+        WFC::new(
+            is_toric,
+            seed_from_u64(seed),
+            patterns_weights,
+            patterns_compatibility,
             height,
             width,
+        )
+    }
+
+    /// Restart WFC, given a u64 seed.
+    pub fn restart_u64(&mut self, seed: u64) {
+        self.restart(seed_from_u64(seed));
+    }
+}
+
+impl<R: Rng + SeedableRng> WFC<R> {
+    /// Create the object containing all the information to perform the WFC
+    /// algorithm.
+    pub fn new(
+        is_toric: bool,
+        seed: R::Seed,
+        patterns_weights: Vec<Real>,
+        patterns_compatibility: Vec<DirArray<Vec<usize>>>,
+        height: usize,
+        width: usize,
+    ) -> Self {
+        let neighborhood = Neighborhood::new_2d(height, width);
+        WFC::new_with_neighborhood(
+            is_toric,
+            seed,
+            patterns_weights,
+            patterns_compatibility,
+            neighborhood,
+        )
+    }
+}
+
+impl<R: Rng + SeedableRng, const N: usize> WFC<R, N> {
+    /// Create the object containing all the information to perform the WFC
+    /// algorithm on a grid with the given neighborhood, where
+    /// patterns_compatibility[pattern1][dir] contains the patterns that can be
+    /// placed in the direction of index dir of pattern1. The output is then
+    /// stored in the rows and columns of the wave, see [Neighborhood].
+    pub fn new_with_neighborhood(
+        is_toric: bool,
+        seed: R::Seed,
+        patterns_weights: Vec<Real>,
+        patterns_compatibility: Vec<DirArray<Vec<usize>, N>>,
+        neighborhood: Neighborhood,
+    ) -> Self {
+        let propagator = Propagator::new_with_neighborhood(
+            neighborhood,
             patterns_weights.clone(),
             patterns_compatibility,
             is_toric,
         );
         WFC {
-            rng_gen: XorShiftRng::from_seed(seed),
+            rng_gen: R::from_seed(seed),
             patterns_weights,
             propagator,
+            heuristic: Heuristic::MinEntropy,
+            banned_patterns: vec![],
+            frequency_matching: false,
+            observations: vec![],
+            entropy_noise: 0.0,
+            temperature: 1.0,
         }
     }
 
     /// Restart WFC.
-    pub fn restart(&mut self, seed: [u8; 16]) {
+    /// The banned patterns are removed again from every cell, but the
+    /// observations made with observe are forgotten.
+    pub fn restart(&mut self, seed: R::Seed) {
+        self.observations.clear();
         self.propagator.reset();
-        self.rng_gen = XorShiftRng::from_seed(seed);
+        self.rng_gen = R::from_seed(seed);
+        for k in 0..self.banned_patterns.len() {
+            self.unset_everywhere(self.banned_patterns[k]);
+        }
+    }
+
+    /// Restart WFC, and force again the patterns given to observe since the
+    /// last restart, so that only the other cells are generated again.
+    /// The banned patterns are removed again from every cell.
+    /// Every observation is applied and kept, even if another one fails, so
+    /// that a later call forces them all again.
+    /// Return the first WaveError::Impossible if the observations are in
+    /// contradiction.
+    pub fn restart_keep_constraints(&mut self, seed: R::Seed) -> Result<(), WaveError> {
+        let observations = std::mem::take(&mut self.observations);
+        self.restart(seed);
+        let mut result = Ok(());
+        for &(y, x, pattern) in &observations {
+            let observed = self.observe(y, x, pattern);
+            if result.is_ok() {
+                result = observed;
+            }
+        }
+        self.observations = observations;
+        result
+    }
+
+    /// Get the cells (y, x) forced to a pattern with observe since the last
+    /// restart, as (y, x, pattern).
+    pub fn observations(&self) -> &[(usize, usize, usize)] {
+        &self.observations
+    }
+
+    /// Forbid a pattern to appear in the output. The pattern is removed from
+    /// every cell, and the information is propagated. The ban is kept after
+    /// a restart.
+    pub fn ban_pattern(&mut self, pattern: usize) {
+        if !self.banned_patterns.contains(&pattern) {
+            self.banned_patterns.push(pattern);
+        }
+        self.unset_everywhere(pattern);
+    }
+
+    /// Get the patterns that were banned with ban_pattern.
+    pub fn banned_patterns(&self) -> &[usize] {
+        &self.banned_patterns
+    }
+
+    /// Remove a pattern from every cell, and propagate the information.
+    fn unset_everywhere(&mut self, pattern: usize) {
+        let height = self.propagator.wave().rows();
+        let width = self.propagator.wave().cols();
+        for y in 0..height {
+            for x in 0..width {
+                self.propagator.unset(y, x, pattern);
+            }
+        }
+    }
+
+    /// Save the current state of the algorithm, to resume it later.
+    pub fn checkpoint(&self) -> WfcCheckpoint<R, N>
+    where
+        R: Clone,
+    {
+        WfcCheckpoint {
+            propagator: self.propagator.state(),
+            rng_gen: self.rng_gen.clone(),
+            patterns_weights: self.patterns_weights.clone(),
+            heuristic: self.heuristic,
+            banned_patterns: self.banned_patterns.clone(),
+            frequency_matching: self.frequency_matching,
+            observations: self.observations.clone(),
+            entropy_noise: self.entropy_noise,
+            temperature: self.temperature,
+        }
+    }
+
+    /// Resume the algorithm from a saved state.
+    /// The WFC should have been created with the same compatibilities as the
+    /// one that created the checkpoint.
+    /// Return an error, without changing the WFC, if the checkpoint does not
+    /// have the same number of patterns, pattern weights, or dimensions.
+    pub fn resume(&mut self, checkpoint: WfcCheckpoint<R, N>) -> Result<(), CheckpointError> {
+        let wave = self.propagator.wave();
+        let (rows, cols, n_patterns) = checkpoint.propagator.size();
+        if n_patterns != wave.n_patterns() || checkpoint.patterns_weights.len() != n_patterns {
+            return Err(CheckpointError::PatternCount);
+        }
+        if checkpoint.patterns_weights != self.patterns_weights {
+            return Err(CheckpointError::Weights);
+        }
+        if (rows, cols) != (wave.rows(), wave.cols()) {
+            return Err(CheckpointError::Dimensions);
+        }
+        self.propagator.set_state(checkpoint.propagator);
+        self.rng_gen = checkpoint.rng_gen;
+        self.heuristic = checkpoint.heuristic;
+        self.banned_patterns = checkpoint.banned_patterns;
+        self.frequency_matching = checkpoint.frequency_matching;
+        self.observations = checkpoint.observations;
+        self.entropy_noise = checkpoint.entropy_noise;
+        self.temperature = checkpoint.temperature;
+        Ok(())
+    }
+
+    /// Enable or disable frequency matching. When enabled, the choice of a
+    /// pattern is biased toward the patterns that are decided in fewer cells
+    /// than their target frequency, given by their weight. The cells decided
+    /// by the propagation are counted, as well as the collapsed ones.
+    /// This makes the pattern usage in the output closer to the weights than
+    /// independent weighted sampling. It is disabled by default.
+    pub fn set_frequency_matching(&mut self, frequency_matching: bool) {
+        self.frequency_matching = frequency_matching;
+    }
+
+    /// Return true if frequency matching is enabled.
+    pub fn frequency_matching(&self) -> bool {
+        self.frequency_matching
+    }
+
+    /// Change the weight of a pattern, which is used when collapsing a cell.
+    /// The entropy of every cell is recomputed, and the new weight is kept
+    /// after a restart.
+    pub fn set_pattern_weight(&mut self, pattern: usize, weight: Real) {
+        self.patterns_weights[pattern] = weight;
+        self.propagator.set_weight(pattern, weight);
     }
 
     /// Do steps of the wfc algorithm until completion
-    /// Return true if the algorithm finished successfully,
-    /// or false if the algorithm failed.
+    /// Return the output if the algorithm finished successfully,
+    /// or None if the algorithm failed.
     pub fn run(&mut self) -> Option<Vec2D<usize>> {
+        self.run_with_progress(|_| ())
+    }
+
+    /// Do steps of the wfc algorithm until completion, and call progress
+    /// after each step.
+    /// Return the output if the algorithm finished successfully,
+    /// or None if the algorithm failed.
+    pub fn run_with_progress<F: FnMut(ProgressInfo)>(
+        &mut self,
+        progress: F,
+    ) -> Option<Vec2D<usize>> {
+        self.run_result_with_progress(progress).ok()
+    }
+
+    /// Do steps of the wfc algorithm until completion
+    /// Return the output if the algorithm finished successfully, or the error
+    /// that stopped it: WaveError::Impossible describing where the
+    /// contradiction occurred, WaveError::NoPatterns if the wave has no
+    /// patterns, or WaveError::Undetermined if a cell is still undetermined
+    /// once the algorithm finished.
+    pub fn run_result(&mut self) -> Result<Vec2D<usize>, WaveError> {
+        self.run_result_with_progress(|_| ())
+    }
+
+    /// Do steps of the wfc algorithm until completion, see [WFC::run_result].
+    #[deprecated(note = "use WFC::run_result")]
+    pub fn run_diagnostics(&mut self) -> Result<Vec2D<usize>, WaveError> {
+        self.run_result()
+    }
+
+    /// Do steps of the wfc algorithm until completion, and call progress
+    /// after each step.
+    /// Return the output if the algorithm finished successfully, or the error
+    /// that stopped it, see [WFC::run_result].
+    pub fn run_result_with_progress<F: FnMut(ProgressInfo)>(
+        &mut self,
+        mut progress: F,
+    ) -> Result<Vec2D<usize>, WaveError> {
+        let total = self.n_cells();
+        let mut steps = 0;
         loop {
-            let step_status = self.step();
-            match step_status {
+            match self.step() {
+                Ok(()) => {
+                    steps += 1;
+                    progress(ProgressInfo {
+                        collapsed: self.n_collapsed(),
+                        total,
+                        steps,
+                    });
+                }
+                Err(WaveError::Finished) => return self.finished_output(),
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    /// Do steps of the wfc algorithm until completion or contradiction.
+    /// Return the value decided in each cell, or None for the undecided cells.
+    /// On contradiction, the cells decided before the failing step are kept.
+    pub fn run_best_effort(&mut self) -> Vec2D<Option<usize>> {
+        loop {
+            let (y, x) = match self.next_cell() {
+                Ok(cell) => cell,
+                Err(_) => return self.to_partial_output(),
+            };
+            // A contradiction empties the wave, so the changes of the failing
+            // step are undone before building the output.
+            let mark = self.propagator.mark();
+            let collapsed = self
+                .choose_pattern(y, x)
+                .and_then(|pattern| self.propagator.collapse(y, x, pattern));
+            if collapsed.is_err() {
+                self.propagator.undo(mark);
+                self.propagator.clear_trail();
+                return self.to_partial_output();
+            }
+            self.propagator.clear_trail();
+        }
+    }
+
+    /// Do at most max_steps steps of the wfc algorithm.
+    /// Return the output if every cell is decided after these steps, None if
+    /// the algorithm ran out of steps before, or WaveError::Impossible if the
+    /// algorithm failed.
+    pub fn run_bounded(&mut self, max_steps: usize) -> Result<Option<Vec2D<usize>>, WaveError> {
+        for _ in 0..max_steps {
+            match self.step() {
                 Ok(()) => (),
-                Err(WaveError::Impossible) => return None,
-                Err(WaveError::Finished) => return self.to_output(),
+                Err(WaveError::Finished) => return Ok(self.to_output()),
+                Err(error) => return Err(error),
+            }
+        }
+        // The last step may have decided every remaining cell.
+        Ok(self.to_output())
+    }
+
+    /// Set the maximal random noise added to the entropy of each cell with
+    /// Heuristic::MinEntropy, see [Wave::get_min_entropy_with_noise].
+    /// The default noise is 0, where the ties are broken without noise.
+    pub fn set_entropy_noise(&mut self, noise: Real) {
+        assert!(noise >= 0.0);
+        self.entropy_noise = noise;
+    }
+
+    /// Get the maximal random noise added to the entropy of each cell.
+    pub fn entropy_noise(&self) -> Real {
+        self.entropy_noise
+    }
+
+    /// Set the temperature of the choice of the patterns: the weight w of a
+    /// pattern is replaced by w^(1/temperature) when choosing it.
+    /// A high temperature makes the choice more uniform, and a low temperature
+    /// makes it closer to choosing the heaviest pattern.
+    /// The default temperature is 1, where the weights are used as is.
+    pub fn set_temperature(&mut self, temperature: Real) {
+        assert!(temperature > 0.0);
+        self.temperature = temperature;
+    }
+
+    /// Get the temperature of the choice of the patterns.
+    pub fn temperature(&self) -> Real {
+        self.temperature
+    }
+
+    /// Copy the settings of previous, a WFC on the same grid with other
+    /// patterns: the heuristic, the frequency matching, the entropy noise and
+    /// the temperature, as well as the banned patterns and the observations,
+    /// which are applied again. new_ids[pattern] is the id in this WFC of the
+    /// pattern of previous, or None if it was removed, in which case its bans
+    /// and observations are dropped.
+    /// Return WaveError::Impossible if the observations lead to a contradiction.
+    pub(crate) fn copy_settings(
+        &mut self,
+        previous: &WFC<R, N>,
+        new_ids: &[Option<usize>],
+    ) -> Result<(), WaveError> {
+        self.heuristic = previous.heuristic;
+        self.frequency_matching = previous.frequency_matching;
+        self.entropy_noise = previous.entropy_noise;
+        self.temperature = previous.temperature;
+        for &pattern in &previous.banned_patterns {
+            if let Some(pattern) = new_ids[pattern] {
+                self.ban_pattern(pattern);
             }
         }
+        for &(y, x, pattern) in &previous.observations {
+            if let Some(pattern) = new_ids[pattern] {
+                self.observe(y, x, pattern)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the heuristic used to choose the next cell to collapse.
+    pub fn heuristic(&self) -> Heuristic {
+        self.heuristic
+    }
+
+    /// Set the heuristic used to choose the next cell to collapse.
+    /// The default heuristic is Heuristic::MinEntropy.
+    pub fn set_heuristic(&mut self, heuristic: Heuristic) {
+        self.heuristic = heuristic;
     }
 
     /// Get the underlying propagator
-    pub fn propagator(&mut self) -> &mut Propagator {
+    pub fn propagator(&mut self) -> &mut Propagator<N> {
         &mut self.propagator
     }
 
+    /// Is the output toric.
+    pub fn is_toric(&self) -> bool {
+        self.propagator.is_toric()
+    }
+
+    /// Get the current wave.
+    pub fn wave(&self) -> &Wave {
+        self.propagator.wave()
+    }
+
+    /// Get the compatibilities of the patterns: adjacency()[pattern1][dir]
+    /// contains the patterns that can be placed in direction dir of pattern1.
+    pub fn adjacency(&self) -> &[DirArray<Vec<usize>, N>] {
+        self.propagator.patterns_compatibility()
+    }
+
+    /// Get the entropy of every cell, see [Wave::entropy_grid].
+    pub fn entropy_grid(&self) -> Vec2D<Real> {
+        self.propagator.wave().entropy_grid()
+    }
+
+    /// Get a copy of the wave, see [Wave::possibility_cube].
+    pub fn possibility_cube(&self) -> Vec3D<bool> {
+        self.propagator.wave().possibility_cube()
+    }
+
+    /// Get a mask of the collapsed cells, see [Wave::collapsed_mask].
+    pub fn collapsed_mask(&self) -> Vec2D<bool> {
+        self.propagator.wave().collapsed_mask()
+    }
+
+    /// Get the number of cells in the output.
+    pub fn n_cells(&self) -> usize {
+        let wave = self.propagator.wave();
+        wave.rows() * wave.cols()
+    }
+
+    /// Get the number of cells where exactly one pattern remains.
+    pub fn n_collapsed(&self) -> usize {
+        self.propagator.wave().collapsed_cells()
+    }
+
+    /// Do steps of the wfc algorithm until completion, backtracking on
+    /// contradictions instead of failing.
+    /// Before each collapse, a mark is taken in the changes recorded by the
+    /// propagator. When a contradiction is found, the changes done since the
+    /// last mark are undone, and the pattern that was chosen is banned from
+    /// the cell.
+    /// Return None if no solution exists.
+    pub fn run_with_backtracking(&mut self) -> Option<Vec2D<usize>> {
+        self.run_with_backtracking_diagnostics().ok()
+    }
+
+    /// Do steps of the wfc algorithm until completion, backtracking on
+    /// contradictions, see run_with_backtracking.
+    /// Return the output if a solution was found, the WaveError::Impossible
+    /// error of the first collapse if no solution exists,
+    /// WaveError::NoPatterns if the wave has no patterns, or
+    /// WaveError::Undetermined if a cell is still undetermined once the
+    /// algorithm finished.
+    pub fn run_with_backtracking_diagnostics(&mut self) -> Result<Vec2D<usize>, WaveError> {
+        let mut max_steps = usize::MAX;
+        let solution = self
+            .search(&mut vec![], &mut max_steps)
+            .map(|solution| solution.expect("the number of steps is not bounded"));
+        self.propagator.clear_trail();
+        solution
+    }
+
+    /// Iterate over distinct solutions, using the same backtracking as
+    /// run_with_backtracking. Once a solution is found, the last choice is
+    /// banned from its cell, and the search continues from there, so that
+    /// each solution is returned at most once.
+    /// The iteration stops when every solution was found, or after max_steps
+    /// collapses and backtracks in total.
+    pub fn solutions(&mut self, max_steps: usize) -> impl Iterator<Item = Vec2D<usize>> + '_ {
+        let mut history = vec![];
+        let mut remaining_steps = max_steps;
+        let mut started = false;
+        let mut finished = false;
+        std::iter::from_fn(move || {
+            if finished {
+                return None;
+            }
+            if started {
+                let (mark, y, x, pattern) = history.pop()?;
+                self.propagator.undo(mark);
+                self.propagator.unset(y, x, pattern);
+            }
+            started = true;
+            let solution = self.search(&mut history, &mut remaining_steps);
+            finished = !matches!(solution, Ok(Some(_)));
+            if finished {
+                self.propagator.clear_trail();
+            }
+            solution.ok()?
+        })
+    }
+
+    /// The backtracking search used by run_with_backtracking and solutions.
+    /// history contains the mark taken in the propagator before each collapse,
+    /// with the cell and the pattern collapsed. Return None if max_steps collapses and
+    /// backtracks were done, or the contradiction left once every choice was
+    /// backtracked if no solution exists.
+    fn search(
+        &mut self,
+        history: &mut Vec<(TrailMark, usize, usize, usize)>,
+        max_steps: &mut usize,
+    ) -> Result<Option<Vec2D<usize>>, WaveError> {
+        loop {
+            if *max_steps == 0 {
+                return Ok(None);
+            }
+            *max_steps -= 1;
+            let next = self
+                .next_cell()
+                .and_then(|(y, x)| Ok((y, x, self.choose_pattern(y, x)?)));
+            match next {
+                Ok((y, x, chosen_pattern)) => {
+                    history.push((self.propagator.mark(), y, x, chosen_pattern));
+                    let _ = self.propagator.collapse(y, x, chosen_pattern);
+                }
+                Err(error @ WaveError::Impossible { .. }) => {
+                    let (mark, y, x, pattern) = history.pop().ok_or(error)?;
+                    self.propagator.undo(mark);
+                    self.propagator.unset(y, x, pattern);
+                }
+                Err(WaveError::Finished) => return self.finished_output().map(Some),
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
     /// Do a step of the WFC algorithm.
-    /// This mean that we take the cell that has the lowest positive entropy,
-    /// choose a pattern relative to the distribution, and propagate the information
+    /// This mean that we take the cell chosen by the heuristic (by default, the one
+    /// that has the lowest positive entropy), choose a pattern relative to the
+    /// distribution, and propagate the information
     pub fn step(&mut self) -> Result<(), WaveError> {
-        let (y, x) = self.propagator.wave().get_min_entropy(&mut self.rng_gen)?;
-        let weights = self.propagator.wave()[(y, x)]
-            .iter()
-            .zip(self.patterns_weights.iter())
-            .map(|(b, w)| if *b { *w } else { 0.0 });
-        let wc = WeightedIndex::new(weights).unwrap();
+        self.step_observe().map(|_| ())
+    }
+
+    /// Do a step of the WFC algorithm, and return the wave after the step,
+    /// as well as the cell that was collapsed.
+    /// This can be used to display the intermediate states of the algorithm.
+    /// Return WaveError::Finished once every cell is determined, and
+    /// WaveError::Impossible if a contradiction was found.
+    pub fn step_and_observe(&mut self) -> Result<(&Wave, (usize, usize)), WaveError> {
+        let (y, x, _) = self.step_observe()?;
+        Ok((self.propagator.wave(), (y, x)))
+    }
 
-        // Choose a pattern fllowing the weight distribution
-        let chosen_pattern = wc.sample(&mut self.rng_gen);
+    /// Do a step of the WFC algorithm, and return the choice made: the cell
+    /// (y, x) that was collapsed and the pattern chosen for it, as
+    /// (y, x, pattern). Contrary to step_and_observe, the wave is not
+    /// returned, so this can be used to record the choices made by the
+    /// algorithm.
+    /// Return WaveError::Finished once every cell is determined, and
+    /// WaveError::Impossible if a contradiction was found.
+    pub fn step_observe(&mut self) -> Result<(usize, usize, usize), WaveError> {
+        let (y, x) = self.next_cell()?;
+        let chosen_pattern = self.choose_pattern(y, x)?;
+        self.propagator.collapse(y, x, chosen_pattern)?;
+        Ok((y, x, chosen_pattern))
+    }
 
-        for k in 0..self.patterns_weights.len() {
-            if k != chosen_pattern {
-                self.propagator.unset(y, x, k);
+    /// Get the next cell to collapse, using the heuristic.
+    fn next_cell(&mut self) -> Result<(usize, usize), WaveError> {
+        let wave = self.propagator.wave();
+        match self.heuristic {
+            Heuristic::MinEntropy if self.entropy_noise > 0.0 => {
+                wave.get_min_entropy_with_noise(&mut self.rng_gen, self.entropy_noise)
             }
+            heuristic => wave.get_next_cell(heuristic, &mut self.rng_gen),
         }
+    }
 
+    /// Force the cell (y, x) to contain pattern, and propagate the information.
+    /// Return WaveError::Impossible if this leads to a contradiction, or if
+    /// pattern cannot be placed in the cell (y, x).
+    pub fn observe(&mut self, y: usize, x: usize, pattern: usize) -> Result<(), WaveError> {
+        if !self.propagator.wave().get(y, x, pattern) {
+            return Err(WaveError::Impossible { y, x, pattern });
+        }
+        self.propagator.collapse(y, x, pattern)?;
+        self.observations.push((y, x, pattern));
         Ok(())
     }
 
+    /// Choose a pattern for the cell (y, x), following the weight distribution
+    /// of the patterns that can still be placed in the cell.
+    /// If every possible pattern has a zero weight, the pattern is chosen
+    /// uniformly among the possible ones instead.
+    /// With frequency matching, the target frequency of a pattern is
+    /// proportional to its weight, and its weight is multiplied by its target
+    /// frequency divided by its number of decided cells plus one.
+    /// The temperature is then applied to the weights.
+    fn choose_pattern(&mut self, y: usize, x: usize) -> Result<usize, WaveError> {
+        let wave = self.propagator.wave();
+        let frequency_matching = self.frequency_matching;
+        let weights = &self.patterns_weights;
+        let decided_counts = wave.decided_counts();
+        // Only the valid patterns are sampled. Since the removed patterns
+        // would have a zero weight, the same pattern is drawn for a given seed.
+        let (possible_patterns, mut weights): (Vec<_>, Vec<Real>) = wave
+            .valid_patterns(y, x)
+            .map(|pattern| {
+                let weight = weights[pattern];
+                if frequency_matching {
+                    (
+                        pattern,
+                        weight * weight / (decided_counts[pattern] + 1) as Real,
+                    )
+                } else {
+                    (pattern, weight)
+                }
+            })
+            .unzip();
+        if self.temperature != 1.0 {
+            // The weights are divided by the maximal one, so that they do not
+            // overflow with a low temperature.
+            let max = weights.iter().cloned().fold(0.0, Real::max);
+            if max > 0.0 {
+                for weight in &mut weights {
+                    *weight = (*weight / max).powf(1.0 / self.temperature);
+                }
+            }
+        }
+        if let Ok(wc) = WeightedIndex::new(&weights) {
+            return Ok(possible_patterns[wc.sample(&mut self.rng_gen)]);
+        }
+
+        match possible_patterns.choose(&mut self.rng_gen) {
+            Some(&pattern) => Ok(pattern),
+            None => Err(wave.impossible()),
+        }
+    }
+
     /// If every cell in the wave is decided, return the values decided in
     /// each cell.
     fn to_output(&self) -> Option<Vec2D<usize>> {
+        self.finished_output().ok()
+    }
+
+    /// Return the values decided in each cell once the algorithm finished, or
+    /// WaveError::Undetermined with the first cell that is not decided.
+    fn finished_output(&self) -> Result<Vec2D<usize>, WaveError> {
+        let partial = self.to_partial_output();
+        let mut data = Vec2D::new(partial.height(), partial.width(), &0);
+        for (((y, x), value), &pattern) in data.iter_enumerate_mut().zip(partial.iter()) {
+            *value = pattern.ok_or(WaveError::Undetermined { y, x })?;
+        }
+        Ok(data)
+    }
+
+    /// Return the value decided in each cell of the wave, or None for the
+    /// cells that are not decided or in contradiction.
+    fn to_partial_output(&self) -> Vec2D<Option<usize>> {
         let wave = self.propagator.wave();
-        let height = wave.height();
-        let width = wave.width();
-
-        let mut data = Vec2D::new(height, width, &0);
-        for i in 0..height {
-            for j in 0..width {
-                let cell_values: Vec<_> = wave[(i, j)]
-                    .iter()
-                    .enumerate()
-                    .filter_map(|(v, b)| if *b { Some(v) } else { None })
-                    .collect();
-                if cell_values.len() != 1 {
-                    return None;
+        let mut data = Vec2D::new(wave.rows(), wave.cols(), &None);
+        for ((i, j), value) in data.iter_enumerate_mut() {
+            let mut patterns = wave.valid_patterns(i, j);
+            if let (Some(pattern), None) = (patterns.next(), patterns.next()) {
+                *value = Some(pattern);
+            }
+        }
+        data
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Create a WFC where every pattern is compatible with every other one.
+    fn fully_compatible_wfc(n_patterns: usize, height: usize, width: usize) -> WFC {
+        let compatibility = vec![DirArray::new(&(0..n_patterns).collect()); n_patterns];
+        WFC::from_u64_seed(
+            false,
+            0,
+            vec![1.0; n_patterns],
+            compatibility,
+            height,
+            width,
+        )
+    }
+
+    /// Create a WFC where two patterns have to alternate like a checkerboard.
+    fn checkerboard_wfc(is_toric: bool, height: usize, width: usize) -> WFC {
+        let compatibility = vec![DirArray::new(&vec![1]), DirArray::new(&vec![0])];
+        WFC::from_u64_seed(is_toric, 0, vec![1.0, 1.0], compatibility, height, width)
+    }
+
+    #[test]
+    fn test_seed_from_u64() {
+        let seed = seed_from_u64(0x0102_0304_0506_0708);
+        assert_eq!(
+            seed,
+            [8, 7, 6, 5, 4, 3, 2, 1, 247, 248, 249, 250, 251, 252, 253, 254]
+        );
+    }
+
+    #[test]
+    fn test_derive_seed() {
+        let seed = seed_from_u64(42);
+        assert_eq!(derive_seed(seed, 3), derive_seed(seed, 3));
+        assert_ne!(derive_seed(seed, 3), derive_seed(seed, 4));
+        assert_ne!(derive_seed(seed, 0), seed);
+    }
+
+    #[test]
+    fn test_run_bounded() {
+        let mut wfc = fully_compatible_wfc(2, 4, 4);
+        assert!(matches!(wfc.run_bounded(0), Ok(None)));
+        assert!(matches!(wfc.run_bounded(3), Ok(None)));
+        assert!(matches!(wfc.run_bounded(100), Ok(Some(_))));
+        assert!(matches!(wfc.run_bounded(0), Ok(Some(_))));
+
+        // A single step decides the only cell.
+        let mut wfc = fully_compatible_wfc(2, 1, 1);
+        assert!(matches!(wfc.run_bounded(1), Ok(Some(_))));
+    }
+
+    #[test]
+    fn test_run_best_effort() {
+        let mut wfc = fully_compatible_wfc(2, 3, 4);
+        let output = wfc.run_best_effort();
+        assert!(output.iter().all(Option::is_some));
+
+        // Neighbors must have different colors, with 3 colors. The local
+        // propagation does not prevent the contradictions for some seeds.
+        let compatibility = (0..3)
+            .map(|color| DirArray::new(&(0..3).filter(|&c| c != color).collect()))
+            .collect::<Vec<_>>();
+        let failing = (0..100).find_map(|seed| {
+            let mut wfc =
+                WFC::from_u64_seed(false, seed, vec![1.0; 3], compatibility.clone(), 10, 10);
+            let output = wfc.run_best_effort();
+            if output.iter().all(Option::is_some) {
+                None
+            } else {
+                Some(output)
+            }
+        });
+        let output = failing.unwrap();
+        assert!(output.iter().any(Option::is_some));
+        for ((i, j), value) in output.iter_enumerate() {
+            if j + 1 < output.width() && value.is_some() {
+                assert_ne!(*value, output[i][j + 1]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_finished_output() {
+        let mut wfc = checkerboard_wfc(false, 3, 4);
+        assert!(wfc.run_result().is_ok());
+
+        let mut wfc = checkerboard_wfc(true, 3, 3);
+        assert!(matches!(
+            wfc.run_result(),
+            Err(WaveError::Impossible { .. })
+        ));
+
+        let wfc = fully_compatible_wfc(2, 3, 4);
+        assert_eq!(
+            wfc.finished_output(),
+            Err(WaveError::Undetermined { y: 0, x: 0 })
+        );
+    }
+
+    #[test]
+    fn test_run_result_no_patterns() {
+        let mut wfc = WFC::from_u64_seed(false, 0, vec![], vec![], 2, 3);
+        assert_eq!(wfc.run_result(), Err(WaveError::NoPatterns));
+        let mut wfc = WFC::from_u64_seed(false, 0, vec![], vec![], 2, 3);
+        assert_eq!(
+            wfc.run_with_backtracking_diagnostics(),
+            Err(WaveError::NoPatterns)
+        );
+    }
+
+    #[test]
+    fn test_run_with_backtracking() {
+        let mut wfc = checkerboard_wfc(false, 4, 5);
+        let output = wfc.run_with_backtracking().unwrap();
+        for ((i, j), &pattern) in output.iter_enumerate() {
+            if i + 1 < output.height() {
+                assert_ne!(pattern, output[i + 1][j]);
+            }
+            if j + 1 < output.width() {
+                assert_ne!(pattern, output[i][j + 1]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_solutions() {
+        // Neighbors must have different colors, with 3 colors. A 2x2 grid has
+        // 18 such colorings.
+        let compatibility = (0..3)
+            .map(|color| DirArray::new(&(0..3).filter(|&c| c != color).collect()))
+            .collect::<Vec<_>>();
+        let mut wfc = WFC::from_u64_seed(false, 0, vec![1.0; 3], compatibility, 2, 2);
+        let solutions: Vec<_> = wfc.solutions(1000).collect();
+        assert_eq!(solutions.len(), 18);
+        for (k, solution) in solutions.iter().enumerate() {
+            assert_ne!(solution[0][0], solution[0][1]);
+            assert_ne!(solution[0][0], solution[1][0]);
+            assert_ne!(solution[1][1], solution[0][1]);
+            assert_ne!(solution[1][1], solution[1][0]);
+            assert!(!solutions[..k].contains(solution));
+        }
+
+        wfc.restart_u64(0);
+        assert!(wfc.solutions(10).count() < 18);
+
+        let mut wfc = checkerboard_wfc(true, 3, 3);
+        assert_eq!(wfc.solutions(1000).count(), 0);
+    }
+
+    #[test]
+    fn test_run_with_backtracking_impossible() {
+        let mut wfc = checkerboard_wfc(true, 3, 3);
+        assert!(wfc.run_with_backtracking().is_none());
+        wfc.restart_u64(0);
+        assert!(matches!(
+            wfc.run_with_backtracking_diagnostics(),
+            Err(WaveError::Impossible { .. })
+        ));
+    }
+
+    #[test]
+    fn test_observe() {
+        let mut wfc = checkerboard_wfc(false, 3, 3);
+        assert!(wfc.observe(1, 1, 1).is_ok());
+        let output = wfc.run().unwrap();
+        assert_eq!(output[1][1], 1);
+        assert_eq!(output[0][0], 1);
+        assert_eq!(output[0][1], 0);
+    }
+
+    #[test]
+    fn test_observe_contradiction() {
+        let mut wfc = checkerboard_wfc(false, 3, 3);
+        assert!(wfc.observe(0, 0, 0).is_ok());
+        assert!(matches!(
+            wfc.observe(0, 2, 1),
+            Err(WaveError::Impossible { .. })
+        ));
+
+        // Pattern 2 cannot have a pattern on its right, so it can be placed
+        // in a cell, but collapsing the cell fails.
+        let mut compatibility = vec![DirArray::new(&vec![0, 1, 2]); 3];
+        for compatible in &mut compatibility {
+            compatible[Direction::Left] = vec![0, 1];
+        }
+        compatibility[2][Direction::Right] = vec![];
+        let mut wfc = WFC::from_u64_seed(false, 0, vec![1.0; 3], compatibility, 1, 2);
+        assert!(wfc.wave().get(0, 0, 2));
+        assert!(wfc.observe(0, 0, 2).is_err());
+        assert!(wfc.observations().is_empty());
+    }
+
+    #[test]
+    fn test_scanline_heuristic() {
+        let mut wfc = fully_compatible_wfc(3, 3, 3);
+        wfc.set_heuristic(Heuristic::Scanline);
+        assert!(matches!(wfc.run_bounded(2), Ok(None)));
+        let n_possible =
+            |wfc: &mut WFC, y, x| wfc.propagator().wave().cell(y, x).filter(|b| *b).count();
+        assert_eq!(n_possible(&mut wfc, 0, 0), 1);
+        assert_eq!(n_possible(&mut wfc, 0, 1), 1);
+        assert_eq!(n_possible(&mut wfc, 0, 2), 3);
+        assert_eq!(n_possible(&mut wfc, 1, 0), 3);
+    }
+
+    #[test]
+    fn test_most_constrained_heuristic() {
+        let mut wfc = checkerboard_wfc(false, 5, 6);
+        wfc.set_heuristic(Heuristic::MostConstrained);
+        let output = wfc.run().unwrap();
+        for ((i, j), &pattern) in output.iter_enumerate() {
+            assert_eq!(pattern, (i + j + output[0][0]) % 2);
+        }
+
+        let mut wfc = fully_compatible_wfc(3, 4, 4);
+        wfc.set_heuristic(Heuristic::MostConstrained);
+        assert!(wfc.run().is_some());
+    }
+
+    #[test]
+    fn test_run_result() {
+        let mut wfc = checkerboard_wfc(true, 1, 3);
+        match wfc.run_result() {
+            Err(WaveError::Impossible { y, .. }) => assert_eq!(y, 0),
+            _ => panic!("expected a contradiction"),
+        }
+    }
+
+    #[test]
+    fn test_step_and_observe() {
+        let mut wfc = fully_compatible_wfc(2, 2, 2);
+        let mut collapsed = vec![];
+        loop {
+            match wfc.step_and_observe() {
+                Ok((wave, (y, x))) => {
+                    assert_eq!(wave.cell(y, x).filter(|b| *b).count(), 1);
+                    collapsed.push((y, x));
+                }
+                Err(error) => {
+                    assert_eq!(error, WaveError::Finished);
+                    break;
                 }
-                data[i][j] = cell_values[0];
             }
         }
-        Some(data)
+        collapsed.sort();
+        assert_eq!(collapsed, vec![(0, 0), (0, 1), (1, 0), (1, 1)]);
+    }
+
+    #[test]
+    fn test_std_rng_determinism() {
+        use rand::rngs::StdRng;
+
+        let run = || {
+            let compatibility = vec![DirArray::new(&vec![0, 1, 2]); 3];
+            let mut wfc: WFC<StdRng> =
+                WFC::new(false, [7; 32], vec![1.0, 2.0, 3.0], compatibility, 6, 6);
+            wfc.run().unwrap()
+        };
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn test_checkpoint_resume() {
+        let new_wfc = || {
+            let compatibility = vec![
+                DirArray::new(&vec![0, 1]),
+                DirArray::new(&vec![0, 1, 2]),
+                DirArray::new(&vec![1, 2]),
+            ];
+            WFC::from_u64_seed(false, 3, vec![1.0, 2.0, 3.0], compatibility, 8, 8)
+        };
+        let expected = new_wfc().run();
+        assert!(expected.is_some());
+
+        let mut wfc = new_wfc();
+        for _ in 0..10 {
+            wfc.step().unwrap();
+        }
+        let checkpoint = wfc.checkpoint();
+        #[cfg(feature = "serde")]
+        let checkpoint: WfcCheckpoint =
+            serde_json::from_str(&serde_json::to_string(&checkpoint).unwrap()).unwrap();
+
+        let mut resumed = new_wfc();
+        resumed.restart_u64(42);
+        resumed.resume(checkpoint).unwrap();
+        assert_eq!(resumed.run(), expected);
+        assert_eq!(wfc.run(), expected);
+    }
+
+    #[test]
+    fn test_checkpoint_settings() {
+        let new_wfc = || checkerboard_wfc(false, 4, 4);
+        let mut wfc = new_wfc();
+        wfc.set_heuristic(Heuristic::Scanline);
+        wfc.set_frequency_matching(true);
+        wfc.set_entropy_noise(0.5);
+        wfc.set_temperature(2.0);
+        wfc.observe(0, 0, 1).unwrap();
+        let checkpoint = wfc.checkpoint();
+
+        let mut resumed = new_wfc();
+        resumed.resume(checkpoint.clone()).unwrap();
+        assert_eq!(resumed.heuristic(), Heuristic::Scanline);
+        assert!(resumed.frequency_matching);
+        assert_eq!(resumed.entropy_noise, 0.5);
+        assert_eq!(resumed.temperature, 2.0);
+        assert_eq!(resumed.observations(), &[(0, 0, 1)]);
+        assert_eq!(resumed.run(), wfc.run());
+
+        let mut reweighted = new_wfc();
+        reweighted.set_pattern_weight(0, 3.0);
+        assert_eq!(
+            reweighted.resume(checkpoint.clone()).err(),
+            Some(CheckpointError::Weights)
+        );
+        let compatibility = vec![DirArray::new(&vec![0, 1, 2]); 3];
+        let mut other = WFC::from_u64_seed(false, 0, vec![1.0; 3], compatibility, 4, 4);
+        assert_eq!(
+            other.resume(checkpoint.clone()).err(),
+            Some(CheckpointError::PatternCount)
+        );
+        assert_eq!(
+            checkerboard_wfc(false, 4, 5).resume(checkpoint).err(),
+            Some(CheckpointError::Dimensions)
+        );
+    }
+
+    #[test]
+    fn test_ban_pattern() {
+        let mut wfc = fully_compatible_wfc(3, 5, 5);
+        wfc.ban_pattern(1);
+        wfc.ban_pattern(1);
+        assert_eq!(wfc.banned_patterns(), &[1]);
+        for seed in 0..4 {
+            wfc.restart_u64(seed);
+            let output = wfc.run().unwrap();
+            assert!(output.iter().all(|pattern| *pattern != 1));
+        }
+    }
+
+    #[test]
+    fn test_run_with_progress() {
+        let mut wfc = fully_compatible_wfc(3, 4, 5);
+        let mut infos = vec![];
+        assert!(wfc.run_with_progress(|info| infos.push(info)).is_some());
+        assert_eq!(infos.len(), 20);
+        for (k, info) in infos.iter().enumerate() {
+            assert_eq!(info.steps, k + 1);
+            assert_eq!(info.total, 20);
+            assert_eq!(info.collapsed, k + 1);
+        }
+    }
+
+    #[test]
+    fn test_zero_weight_patterns() {
+        let mut wfc = fully_compatible_wfc(3, 4, 4);
+        wfc.set_pattern_weight(0, 0.0);
+        wfc.set_pattern_weight(1, 0.0);
+        wfc.ban_pattern(2);
+        for seed in 0..100 {
+            wfc.restart_u64(seed);
+            let output = wfc.run().unwrap();
+            assert!(output.iter().all(|&pattern| pattern < 2));
+        }
+
+        // A zero weight pattern is never chosen when another pattern is possible.
+        let mut wfc = fully_compatible_wfc(3, 4, 4);
+        wfc.set_pattern_weight(1, 0.0);
+        for seed in 0..100 {
+            wfc.restart_u64(seed);
+            assert!(wfc.entropy_grid().iter().all(|entropy| entropy.is_finite()));
+            let output = wfc.run().unwrap();
+            assert!(output.iter().all(|&pattern| pattern != 1));
+        }
+    }
+
+    #[test]
+    fn test_n_collapsed() {
+        let mut wfc = fully_compatible_wfc(2, 3, 4);
+        assert_eq!(wfc.n_cells(), 12);
+        assert_eq!(wfc.n_collapsed(), 0);
+        wfc.step().unwrap();
+        assert_eq!(wfc.n_collapsed(), 1);
+        wfc.run().unwrap();
+        assert_eq!(wfc.n_collapsed(), 12);
+    }
+
+    #[test]
+    fn test_collapsed_mask() {
+        let mut wfc = fully_compatible_wfc(2, 3, 4);
+        assert!(wfc.collapsed_mask().iter().all(|&collapsed| !collapsed));
+        wfc.observe(1, 2, 0).unwrap();
+        let mask = wfc.collapsed_mask();
+        assert!(mask[1][2]);
+        assert_eq!(mask.iter().filter(|&&collapsed| collapsed).count(), 1);
+        wfc.run().unwrap();
+        assert!(wfc.collapsed_mask().iter().all(|&collapsed| collapsed));
+    }
+
+    #[test]
+    fn test_restart_keep_constraints() {
+        let mut wfc = fully_compatible_wfc(3, 4, 4);
+        wfc.ban_pattern(2);
+        wfc.observe(1, 2, 1).unwrap();
+        assert_eq!(wfc.observations(), &[(1, 2, 1)]);
+
+        for seed in 0..5 {
+            wfc.restart_keep_constraints(seed_from_u64(seed)).unwrap();
+            let output = wfc.run().unwrap();
+            assert_eq!(output[1][2], 1);
+            assert!(output.iter().all(|&pattern| pattern != 2));
+        }
+
+        wfc.restart_u64(0);
+        assert!(wfc.observations().is_empty());
+        assert!(!wfc.collapsed_mask()[1][2]);
+        assert!(!wfc.propagator.wave().get(1, 2, 2));
+    }
+
+    #[test]
+    fn test_restart_keep_failed_constraints() {
+        let mut wfc = fully_compatible_wfc(3, 4, 4);
+        wfc.observe(0, 0, 0).unwrap();
+        wfc.observe(1, 1, 1).unwrap();
+        wfc.ban_pattern(0);
+        let observations = [(0, 0, 0), (1, 1, 1)];
+
+        // The first observation fails, but the second one is still applied,
+        // and both are kept for the next restart.
+        for seed in 0..2 {
+            assert_eq!(
+                wfc.restart_keep_constraints(seed_from_u64(seed)),
+                Err(WaveError::Impossible {
+                    y: 0,
+                    x: 0,
+                    pattern: 0
+                })
+            );
+            assert_eq!(wfc.observations(), &observations);
+            assert!(wfc.collapsed_mask()[1][1]);
+            assert!(wfc.propagator.wave().get(1, 1, 1));
+        }
+    }
+
+    #[test]
+    fn test_step_observe() {
+        let mut wfc = fully_compatible_wfc(3, 1, 2);
+        let (y, x, pattern) = wfc.step_observe().unwrap();
+        assert_eq!(y, 0);
+        assert!(x < 2);
+        assert!(pattern < 3);
+        assert!(wfc.possibility_cube()[(y, x, pattern)]);
+        assert!(wfc.collapsed_mask()[y][x]);
+
+        let (_, other_x, _) = wfc.step_observe().unwrap();
+        assert_eq!(other_x, 1 - x);
+        assert_eq!(wfc.step_observe(), Err(WaveError::Finished));
+    }
+
+    #[test]
+    fn test_step_contradiction() {
+        // Neighbors must have different colors, with 3 colors, which leads to
+        // contradictions for some seeds. The step that finds it fails.
+        let compatibility = (0..3)
+            .map(|color| DirArray::new(&(0..3).filter(|&c| c != color).collect()))
+            .collect::<Vec<_>>();
+        let mut failed = false;
+        for seed in 0..100 {
+            let mut wfc =
+                WFC::from_u64_seed(false, seed, vec![1.0; 3], compatibility.clone(), 10, 10);
+            loop {
+                match wfc.step_observe() {
+                    Ok(_) => assert!(!wfc.wave().has_contradiction()),
+                    Err(WaveError::Impossible { .. }) => {
+                        failed = true;
+                        break;
+                    }
+                    Err(_) => break,
+                }
+            }
+        }
+        assert!(failed);
+    }
+
+    /// The cells and patterns chosen with a given seed should not change, so
+    /// that outputs are reproducible. The pattern depends on the precision of
+    /// Real, since the weighted sampling draws a number of this type.
+    #[test]
+    fn test_first_collapse_reproducible() {
+        let expected = if cfg!(feature = "f64") {
+            [(3, 1, 2), (3, 2, 2)]
+        } else {
+            [(3, 1, 0), (3, 2, 1)]
+        };
+        let mut wfc = fully_compatible_wfc(3, 4, 5);
+        assert_eq!(wfc.step_observe(), Ok(expected[0]));
+        wfc.restart_u64(42);
+        assert_eq!(wfc.step_observe(), Ok(expected[1]));
+    }
+
+    #[test]
+    fn test_temperature() {
+        // The number of cells where the heaviest pattern 0 is chosen.
+        let heaviest_count = |temperature: Real| {
+            let compatibility = vec![DirArray::new(&vec![0, 1, 2]); 3];
+            let weights = vec![3.0, 2.0, 1.0];
+            let mut wfc = WFC::from_u64_seed(false, 0, weights, compatibility, 10, 10);
+            wfc.set_temperature(temperature);
+            let output = wfc.run().unwrap();
+            output.iter().filter(|&&pattern| pattern == 0).count()
+        };
+        assert!(heaviest_count(0.05) >= 99);
+        assert!(heaviest_count(1.0) < 80);
+        assert!(heaviest_count(100.0) < 50);
+
+        // A temperature of 1 does not change the result.
+        let compatibility = vec![DirArray::new(&vec![0, 1, 2]); 3];
+        let mut wfc = WFC::from_u64_seed(false, 0, vec![3.0, 2.0, 1.0], compatibility, 10, 10);
+        let output = wfc.run();
+        wfc.restart_u64(0);
+        wfc.set_temperature(1.0);
+        assert_eq!(wfc.run(), output);
+    }
+
+    #[test]
+    fn test_frequency_matching() {
+        // The mean distance between the ratio of pattern 0 in the output and
+        // its target frequency.
+        let mean_error = |frequency_matching: bool| {
+            let compatibility = vec![DirArray::new(&vec![0, 1]); 2];
+            let mut wfc = WFC::from_u64_seed(false, 0, vec![3.0, 1.0], compatibility, 8, 8);
+            wfc.set_frequency_matching(frequency_matching);
+            let n_runs = 50;
+            let mut error = 0.0;
+            for seed in 0..n_runs {
+                wfc.restart_u64(seed);
+                let output = wfc.run().unwrap();
+                let ratio = output.iter().filter(|&&p| p == 0).count() as Real / 64.0;
+                error += (ratio - 0.75).abs();
+            }
+            error / n_runs as Real
+        };
+        assert!(mean_error(true) < mean_error(false));
+    }
+
+    #[test]
+    fn test_frequency_matching_propagation() {
+        // Pattern 0 can only be next to pattern 0 horizontally, so collapsing
+        // it decides its whole row, while patterns 1 and 2 are decided one
+        // cell at a time. Counting only the collapsed cells would favor 0.
+        let mut compatibility = vec![DirArray::new(&vec![0, 1, 2]); 3];
+        for (pattern, compatible) in compatibility.iter_mut().enumerate() {
+            let horizontal = if pattern == 0 { vec![0] } else { vec![1, 2] };
+            compatible[Direction::Left] = horizontal.clone();
+            compatible[Direction::Right] = horizontal;
+        }
+        let mut wfc = WFC::from_u64_seed(false, 0, vec![1.0; 3], compatibility, 8, 8);
+        wfc.set_frequency_matching(true);
+        let n_runs = 20;
+        let mut ratio = 0.0;
+        for seed in 0..n_runs {
+            wfc.restart_u64(seed);
+            let output = wfc.run().unwrap();
+            let decided_counts = wfc.wave().decided_counts();
+            assert_eq!(decided_counts.iter().sum::<usize>(), 64);
+            assert_eq!(
+                decided_counts[0],
+                output.iter().filter(|&&p| p == 0).count()
+            );
+            ratio += decided_counts[0] as Real / 64.0;
+        }
+        let ratio = ratio / n_runs as Real;
+        assert!(ratio < 0.5);
+    }
+
+    #[test]
+    fn test_output_content_hash() {
+        let mut wfc = fully_compatible_wfc(4, 5, 5);
+        let hash = wfc.run().unwrap().content_hash();
+        wfc.restart_u64(0);
+        assert_eq!(wfc.run().unwrap().content_hash(), hash);
+        wfc.restart_u64(1);
+        assert_ne!(wfc.run().unwrap().content_hash(), hash);
     }
 }